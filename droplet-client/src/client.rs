@@ -1,21 +1,30 @@
 use anyhow::{bail, Result};
 use droplet_core::droplet::{
-    droplet_client::DropletClient, HeartbeatRequest, NodeStatus, SinkGridSampleRequest,
-    StartSinkPartitionRequest,
+    droplet_client::DropletClient, HeartbeatRequest, NodeStatus, ReadPartitionRequest,
+    SinkGridSampleRequest, StartSinkPartitionRequest,
 };
 use droplet_server::tool::{get_droplet_client, get_droplet_default_client};
 use std::iter::Iterator;
 
+use droplet_core::data_type::DataType;
 use droplet_core::error_bail;
+use droplet_core::grid_sample::{KeySchema, SampleKey};
+use droplet_core::partition::get_partition_path;
+use droplet_core::predicate::Predicate;
 use log::error;
-use std::sync::Arc;
 
-use droplet_core::db::db::DB;
 use gridbuffer::core::gridbuffer::GridBuffer;
 
-use crate::gridbuffer_reader::{GridRowRef, LocalGridRowMergeReader, LocalGridRowReader};
+use crate::gridbuffer_reader::{
+    FilteredGridRowReader, JoinType, LocalGridBufferMergeReader, LocalGridRowMergeReader,
+    LocalGridRowReader, SchemaVersions,
+};
+use crate::remote_reader::RemoteGridRowReader;
 
-use droplet_core::droplet::FinishSinkPartitionRequest;
+use droplet_core::droplet::{
+    FinishSinkPartitionRequest, GetPartitionPathsResponse, GetPartitionStatusRequest,
+    GetPartitionStatusResponse,
+};
 use droplet_meta_client::client::MetaClientWrapper;
 
 /// Wrapper of grpc droplet client.
@@ -27,13 +36,9 @@ pub struct Client {
 }
 
 impl Client {
-    pub async fn new(
-        server_endpoint: &String,
-        db: Arc<DB>,
-        meta_server_endpoint: &String,
-    ) -> Result<Self> {
+    pub async fn new(server_endpoint: &String, meta_server_endpoint: &String) -> Result<Self> {
         match get_droplet_client(server_endpoint).await {
-            Ok(droplet_client) => match MetaClientWrapper::new(db, meta_server_endpoint).await {
+            Ok(droplet_client) => match MetaClientWrapper::new(meta_server_endpoint).await {
                 Ok(meta_client) => Ok(Self {
                     droplet_client,
                     meta_client,
@@ -86,25 +91,367 @@ impl Client {
     /// Read gridbuffer from single table.
     ///
     /// Read local files for test.
-    pub fn read_gridbuffer(
+    pub async fn read_gridbuffer(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        keys: &Vec<String>,
+    ) -> Result<LocalGridRowReader> {
+        let partitions = self
+            .meta_client
+            .get_paths_by_date(table, partition_date)
+            .await?;
+
+        self.get_local_reader(table, partitions, keys, None).await
+    }
+
+    /// Read rows with timestamp in `[start_ts, end_ts)` from single table.
+    ///
+    /// Only the blocks in the time range are decoded.
+    pub async fn read_gridbuffer_by_time(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        keys: &Vec<String>,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<LocalGridRowReader> {
+        let partitions = self
+            .meta_client
+            .get_paths_by_date(table, partition_date)
+            .await?;
+
+        self.get_local_reader(table, partitions, keys, Some((start_ts, end_ts)))
+            .await
+    }
+
+    /// Read rows with timestamp in `[start_ts, end_ts)` from single table, timestamp in the unit
+    /// of the table.
+    ///
+    /// Partitions are pruned by `time_start` and `time_end` in `partition_info`, and the rows at
+    /// the edges are trimmed by `SampleKey.timestamp`. The range can span multiple days, and the
+    /// rows are in order of `SampleKey` across all the partitions.
+    pub async fn read_gridbuffer_range(
+        &mut self,
+        table: &str,
+        start_ts: u64,
+        end_ts: u64,
+        columns: &Vec<String>,
+    ) -> Result<LocalGridRowReader> {
+        if start_ts >= end_ts {
+            error_bail!(
+                "Invalid time range, start_ts: {}, end_ts: {}",
+                start_ts,
+                end_ts
+            );
+        }
+
+        let partitions = self
+            .meta_client
+            .get_paths_by_time(table, start_ts, end_ts)
+            .await?;
+
+        if partitions.paths.is_empty() {
+            error_bail!(
+                "No partition found in time range, table: {}, start_ts: {}, end_ts: {}",
+                table,
+                start_ts,
+                end_ts
+            );
+        }
+
+        self.get_local_reader(table, partitions, columns, Some((start_ts, end_ts)))
+            .await
+    }
+
+    /// Reader of the partitions of the table, only rows with timestamp in `[start_ts, end_ts)`
+    /// are read if `time_range` is set.
+    ///
+    /// `keys` must be the key columns or the columns of the table. Columns added after a
+    /// partition is created are read as null from the partition.
+    async fn get_local_reader(
+        &mut self,
+        table: &str,
+        partitions: GetPartitionPathsResponse,
+        keys: &Vec<String>,
+        time_range: Option<(u64, u64)>,
+    ) -> Result<LocalGridRowReader> {
+        let key_ids = self.meta_client.get_key_ids(keys).await?;
+        let key_schema = self.meta_client.get_key_schema(table).await?;
+        let schema_versions = self
+            .get_schema_versions(table, &key_ids, &key_schema, partitions.schema_versions)
+            .await?;
+
+        let reader = match time_range {
+            Some((start_ts, end_ts)) => LocalGridRowReader::new_with_time_range(
+                partitions.paths,
+                key_ids,
+                start_ts,
+                end_ts,
+            )?,
+            None => LocalGridRowReader::new(partitions.paths, key_ids)?,
+        };
+
+        Ok(reader
+            .with_key_schema(key_schema)
+            .with_schema_versions(schema_versions))
+    }
+
+    /// Schema versions of the partitions and the columns of `key_ids`.
+    ///
+    /// Key columns are in all partitions, their schema version is `0`. Ids which are neither key
+    /// columns nor columns of the table are rejected.
+    async fn get_schema_versions(
+        &mut self,
+        table: &str,
+        key_ids: &[u32],
+        key_schema: &KeySchema,
+        partition_versions: Vec<u32>,
+    ) -> Result<SchemaVersions> {
+        let columns = self.meta_client.get_table_columns(table).await?;
+
+        let column_versions = key_ids
+            .iter()
+            .map(|key_id| {
+                if key_schema.col_ids().contains(key_id) {
+                    return Ok(0);
+                }
+
+                match columns.iter().find(|column| column.column_id == *key_id) {
+                    Some(column) => Ok(column.schema_version),
+                    None => {
+                        error_bail!("Unknown column id {} of table {}", key_id, table);
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(SchemaVersions::new(partition_versions, column_versions))
+    }
+
+    /// Schema versions of one partition and the columns of `key_ids`, for reading the partition
+    /// from the droplet server.
+    async fn get_partition_schema_versions(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        partition_index: u32,
+        key_ids: &[u32],
+        key_schema: &KeySchema,
+    ) -> Result<SchemaVersions> {
+        let partitions = self
+            .meta_client
+            .get_paths_by_date(table, partition_date)
+            .await?;
+
+        let partition_version = partitions
+            .schema_versions
+            .get(partition_index as usize)
+            .copied()
+            .unwrap_or(0);
+
+        self.get_schema_versions(table, key_ids, key_schema, vec![partition_version])
+            .await
+    }
+
+    /// Parse the predicate and resolve the column names to global ids.
+    ///
+    /// Names are resolved against the columns of the table, without inserting new keys, so
+    /// unknown columns are rejected.
+    pub async fn parse_predicate(&mut self, table: &str, filter: &str) -> Result<Predicate> {
+        let mut predicate = Predicate::parse(filter)?;
+
+        let columns = self.meta_client.get_table_columns(table).await?;
+
+        // Sample keys are not in the columns of the table, their values are `u64`.
+        predicate.resolve(&mut |name: &str| -> Result<(u32, DataType)> {
+            if let Some(column) = columns.iter().find(|column| column.column_name == name) {
+                return Ok((
+                    column.column_id,
+                    DataType::from_column_type(column.column_type)?,
+                ));
+            }
+
+            match SampleKey::get_sample_key_pair()
+                .iter()
+                .find(|(key, _)| *key == name)
+            {
+                Some((_, id)) => Ok((*id, DataType::U64)),
+                None => {
+                    error_bail!("Unknown column {} in predicate, table: {}", name, table);
+                }
+            }
+        })?;
+
+        Ok(predicate)
+    }
+
+    /// Read rows matching `filter` from single table.
+    ///
+    /// See `droplet_core::predicate` for the syntax of `filter`.
+    pub async fn read_gridbuffer_filter(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        keys: &Vec<String>,
+        filter: &str,
+    ) -> Result<FilteredGridRowReader<LocalGridRowReader>> {
+        let predicate = self.parse_predicate(table, filter).await?;
+
+        let partitions = self
+            .meta_client
+            .get_paths_by_date(table, partition_date)
+            .await?;
+
+        let reader = self.get_local_reader(table, partitions, keys, None).await?;
+
+        Ok(FilteredGridRowReader::new(reader, predicate))
+    }
+
+    /// Read rows matching `filter` of one partition from the droplet server.
+    ///
+    /// The columns of the predicate are added to the projection, so they are sent by server.
+    pub async fn read_partition_filter(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        partition_index: u32,
+        keys: &Vec<String>,
+        filter: &str,
+        time_range: Option<(u64, u64)>,
+    ) -> Result<FilteredGridRowReader<RemoteGridRowReader>> {
+        let predicate = self.parse_predicate(table, filter).await?;
+        let key_ids = self.meta_client.get_key_ids(keys).await?;
+        let key_schema = self.meta_client.get_key_schema(table).await?;
+        let schema_versions = self
+            .get_partition_schema_versions(
+                table,
+                partition_date,
+                partition_index,
+                &key_ids,
+                &key_schema,
+            )
+            .await?;
+
+        let mut column_ids = key_ids.clone();
+        for id in predicate.column_ids() {
+            if !column_ids.contains(&id) {
+                column_ids.push(id);
+            }
+        }
+
+        let req = Self::get_read_partition_request(
+            table,
+            partition_date,
+            partition_index,
+            column_ids,
+            key_schema.col_ids().to_vec(),
+            time_range,
+        );
+
+        let reader = RemoteGridRowReader::new(self.droplet_client.clone(), req, key_ids)
+            .await?
+            .with_schema_versions(schema_versions);
+
+        Ok(FilteredGridRowReader::new(reader, predicate))
+    }
+
+    fn get_read_partition_request(
+        table: &str,
+        partition_date: u32,
+        partition_index: u32,
+        column_ids: Vec<u32>,
+        key_column_ids: Vec<u32>,
+        time_range: Option<(u64, u64)>,
+    ) -> ReadPartitionRequest {
+        let (time_start, time_end) = time_range.unwrap_or((0, 0));
+
+        ReadPartitionRequest {
+            table_name: table.to_string(),
+            partition_date,
+            partition_index,
+            column_ids,
+            time_start,
+            time_end,
+            key_column_ids,
+        }
+    }
+
+    /// Read one partition from the droplet server the client connected to.
+    ///
+    /// Only rows with timestamp in `[start_ts, end_ts)` are returned if `time_range` is set.
+    pub async fn read_partition(
         &mut self,
         table: &str,
         partition_date: u32,
+        partition_index: u32,
         keys: &Vec<String>,
-    ) -> Result<impl Iterator<Item = GridRowRef>> {
-        let file_paths = self.meta_client.get_paths_by_date(table, partition_date)?;
-        let key_ids = self.meta_client.get_key_ids(keys)?;
+        time_range: Option<(u64, u64)>,
+    ) -> Result<RemoteGridRowReader> {
+        let key_ids = self.meta_client.get_key_ids(keys).await?;
+        let key_schema = self.meta_client.get_key_schema(table).await?;
+        let schema_versions = self
+            .get_partition_schema_versions(
+                table,
+                partition_date,
+                partition_index,
+                &key_ids,
+                &key_schema,
+            )
+            .await?;
+
+        let req = Self::get_read_partition_request(
+            table,
+            partition_date,
+            partition_index,
+            key_ids.clone(),
+            key_schema.col_ids().to_vec(),
+            time_range,
+        );
 
-        LocalGridRowReader::new(file_paths, key_ids)
+        Ok(
+            RemoteGridRowReader::new(self.droplet_client.clone(), req, key_ids)
+                .await?
+                .with_schema_versions(schema_versions),
+        )
     }
 
     /// Merge on read.
-    pub fn read_gridbuffer_merge(
+    ///
+    /// Tables are joined by `SampleKey` according to `join_type`, `GridRowRef::table_mask` of
+    /// each row tells which tables contribute to it.
+    pub async fn read_gridbuffer_merge(
+        &mut self,
+        tables: &Vec<String>,
+        partition_date: u32,
+        keys: &Vec<Vec<String>>,
+        join_type: JoinType,
+    ) -> Result<LocalGridRowMergeReader> {
+        let (readers, key_ids) = self.get_merge_readers(tables, partition_date, keys).await?;
+
+        LocalGridRowMergeReader::new(readers, key_ids, join_type)
+    }
+
+    /// Merge on read, return batches of `batch_size` rows.
+    pub async fn read_gridbuffer_merge_batch(
+        &mut self,
+        tables: &Vec<String>,
+        partition_date: u32,
+        keys: &Vec<Vec<String>>,
+        batch_size: usize,
+        join_type: JoinType,
+    ) -> Result<LocalGridBufferMergeReader> {
+        let (readers, key_ids) = self.get_merge_readers(tables, partition_date, keys).await?;
+
+        LocalGridBufferMergeReader::new(readers, key_ids, batch_size, join_type)
+    }
+
+    async fn get_merge_readers(
         &mut self,
         tables: &Vec<String>,
         partition_date: u32,
         keys: &Vec<Vec<String>>,
-    ) -> Result<impl Iterator<Item = GridRowRef>> {
+    ) -> Result<(Vec<LocalGridRowReader>, Vec<Vec<u32>>)> {
         if tables.len() != keys.len() {
             error_bail!(
                 "The length of tables and keys must be the same, tables.len(): {}, keys.len(): {}",
@@ -117,28 +464,36 @@ impl Client {
         let mut key_ids = Vec::with_capacity(keys.len());
 
         for i in 0..tables.len() {
-            let paths = self
+            let partitions = self
                 .meta_client
-                .get_paths_by_date(&tables[i], partition_date)?;
-            let ids = self.meta_client.get_key_ids(&keys[i])?;
+                .get_paths_by_date(&tables[i], partition_date)
+                .await?;
 
-            key_ids.push(ids.clone());
+            let reader = self
+                .get_local_reader(&tables[i], partitions, &keys[i], None)
+                .await?;
 
-            let reader = LocalGridRowReader::new(paths, ids)?;
+            key_ids.push(reader.key_ids().to_vec());
             readers.push(reader);
         }
 
-        Ok(LocalGridRowMergeReader::new(readers, key_ids))
+        Ok((readers, key_ids))
     }
 
+    /// Start sink partition, return the id of the partition path.
+    ///
+    /// The id must be used in following `sink_grid_sample` and `finish_sink_partition`. The key
+    /// columns of the table are sent to the server, so the partition is sorted by them.
     pub async fn start_sink_partition(
         &mut self,
         table: &str,
         sinker_id: u32,
+        partition_date: u32,
         partition_index: u32,
-    ) -> Result<()> {
-        let path = self.meta_client.get_path_by_table(&table);
-        let path_id = self.meta_client.get_or_insert_key_id(path.as_str())?;
+    ) -> Result<u32> {
+        let path = get_partition_path(table, partition_date, partition_index);
+        let path_id = self.meta_client.get_or_insert_key_id(path.as_str()).await?;
+        let key_schema = self.meta_client.get_key_schema(table).await?;
 
         self.droplet_client
             .start_sink_partition(StartSinkPartitionRequest {
@@ -146,12 +501,17 @@ impl Client {
                 path_id,
                 sinker_id,
                 partition_index,
+                key_column_ids: key_schema.col_ids().to_vec(),
             })
             .await?;
 
-        Ok(())
+        Ok(path_id)
     }
 
+    /// Sink the `GridBuffer` to the partition.
+    ///
+    /// `watermark` is the event time in the `timestamp_unit` of the table, the sinker will not
+    /// send rows with smaller timestamp any more, `0` if unknown.
     pub async fn sink_grid_sample(
         &mut self,
         table: &str,
@@ -159,8 +519,12 @@ impl Client {
         sinker_id: u32,
         partition_index: u32,
         gridbuffer: GridBuffer,
+        watermark: u64,
     ) -> Result<()> {
-        let new_path_id = path_id.unwrap_or(self.meta_client.get_or_insert_key_id(table)?);
+        let new_path_id = match path_id {
+            Some(path_id) => path_id,
+            None => self.meta_client.get_or_insert_key_id(table).await?,
+        };
 
         self.droplet_client
             .sink_grid_sample(SinkGridSampleRequest {
@@ -168,6 +532,7 @@ impl Client {
                 sinker_id,
                 partition_index,
                 grid_sample_bytes: gridbuffer.to_bytes(),
+                watermark,
             })
             .await?;
 
@@ -191,6 +556,28 @@ impl Client {
         Ok(())
     }
 
+    /// Get the state of sinking the partition on the server the client connected to.
+    ///
+    /// Files are merged in background after `finish_sink_partition`, the partition could be read
+    /// when the state is `Sealed`.
+    pub async fn get_partition_status(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+        partition_index: u32,
+    ) -> Result<GetPartitionStatusResponse> {
+        let res = self
+            .droplet_client
+            .get_partition_status(GetPartitionStatusRequest {
+                table_name: table.to_string(),
+                partition_date,
+                partition_index,
+            })
+            .await?;
+
+        Ok(res.into_inner())
+    }
+
     pub async fn heartbeat(&mut self, node_id: u32) -> Result<()> {
         self.droplet_client
             .heartbeat(HeartbeatRequest {
@@ -1,16 +1,130 @@
 use anyhow::{bail, Result};
 
-use std::{fs::File, io::BufRead, io::BufReader, iter::Iterator, path::Path};
+use std::{iter::Iterator, path::Path, sync::Arc};
 
 use droplet_core::{
-    error_bail,
-    grid_sample::{GridRow, SampleKey},
+    data_type, error_bail,
+    grid_sample::{GridRow, KeySchema, SampleKey},
+    grid_segment::GridSegmentReader,
+    partition::list_grid_files,
+    predicate::Predicate,
 };
 use gridbuffer::core::gridbuffer::GridBuffer;
-use log::error;
+use log::{error, info};
+
+/// Whether the timestamp of the row is in `[start_ts, end_ts)`. Always `true` if `time_range`
+/// is not set.
+#[inline]
+pub fn is_row_in_time_range(
+    gridbuffer: &GridBuffer,
+    row: usize,
+    time_range: Option<(u64, u64)>,
+) -> bool {
+    match time_range {
+        Some((start_ts, end_ts)) => {
+            let timestamp = SampleKey::from_gridbuffer(gridbuffer, row).timestamp;
+            timestamp >= start_ts && timestamp < end_ts
+        }
+        None => true,
+    }
+}
+
+/// Get the cells of the row by `col_indexes` from `get_col_indexes`, `None` are null cells.
+pub fn get_row_ref(
+    gridbuffer: &Arc<GridBuffer>,
+    row: usize,
+    col_indexes: &[Option<usize>],
+) -> GridRowRef {
+    let cells = col_indexes
+        .iter()
+        .map(|col| match col {
+            Some(col) => GridCellRef::new(gridbuffer.clone(), row, *col),
+            None => GridCellRef::default(),
+        })
+        .collect();
+
+    // Set `inner_row` even if `key_ids` is empty, predicate is evaluated on it.
+    GridRowRef {
+        cells,
+        inner_row: Some(GridRow::new(gridbuffer.clone(), row)),
+        table_mask: 1,
+    }
+}
+
+/// Schema versions of the partitions and the columns read, to decide whether a column missing
+/// in a partition is read as null.
+#[derive(Clone, Debug, Default)]
+pub struct SchemaVersions {
+    /// Schema version of the partition of each path, in the same order as the paths. `0` if
+    /// unknown.
+    pub partition_versions: Vec<u32>,
+
+    /// Schema version in which each column of `key_ids` is added, in the same order. `0` for
+    /// key columns.
+    pub column_versions: Vec<u32>,
+}
+
+impl SchemaVersions {
+    pub fn new(partition_versions: Vec<u32>, column_versions: Vec<u32>) -> Self {
+        Self {
+            partition_versions,
+            column_versions,
+        }
+    }
+
+    /// Schema version of the partition of the path, `None` if unknown.
+    #[inline]
+    pub fn get_partition_version(&self, path_index: usize) -> Option<u32> {
+        self.partition_versions
+            .get(path_index)
+            .copied()
+            .filter(|version| *version > 0)
+    }
+}
+
+/// Get the column indexes of `key_ids` in the `GridBuffer`, `None` for the columns read as null.
+///
+/// Columns can be added to a table after some partitions are written, so older partitions
+/// don't have them. They are read as null cells, so one read can cross partitions of different
+/// schema versions. Only a column whose schema version is greater than `partition_version` is
+/// read as null, any other missing column is an error.
+pub fn get_col_indexes(
+    gridbuffer: &GridBuffer,
+    key_ids: &[u32],
+    partition_version: Option<u32>,
+    column_versions: &[u32],
+) -> Result<Vec<Option<usize>>> {
+    key_ids
+        .iter()
+        .enumerate()
+        .map(|(i, key_id)| {
+            if let Some(col) = gridbuffer.get_col_by_id(*key_id) {
+                return Ok(Some(col));
+            }
+
+            match (partition_version, column_versions.get(i)) {
+                (Some(partition_version), Some(column_version))
+                    if partition_version < *column_version =>
+                {
+                    Ok(None)
+                }
+                _ => {
+                    error_bail!(
+                        "column id not found: {}, partition schema version: {:?}, column schema version: {:?}",
+                        key_id,
+                        partition_version,
+                        column_versions.get(i)
+                    );
+                }
+            }
+        })
+        .collect()
+}
 
 pub struct LocalGridbufferReader {
     /// Paths to local gridbuffer files.
+    ///
+    /// A path of directory is expanded to all `<n>.grid` files under it, in order of `n`.
     paths: Vec<String>,
 
     /// Key ids.
@@ -18,43 +132,145 @@ pub struct LocalGridbufferReader {
     /// Need to check whether key_ids exists in table column ids.
     key_ids: Vec<u32>,
 
+    /// Index of the given path of each file in `paths`, to find the schema version of the file.
+    path_indexes: Vec<usize>,
+
     /// Index of the current path.
     cur_path_index: usize,
 
-    /// File handle of the current file.
-    file_reader: BufReader<File>,
+    /// Segment reader of the current file.
+    segment_reader: GridSegmentReader,
 
     /// Current gridbuffer.
-    cur_gridbuffer: Option<GridBuffer>,
+    cur_gridbuffer: Option<Arc<GridBuffer>>,
+
+    /// Column indexes of `key_ids` in current gridbuffer, see `get_col_indexes`.
+    cur_col_indexes: Vec<Option<usize>>,
 
     /// Current row index.
     cur_row_index: usize,
+
+    /// Only read rows with timestamp in `[start_ts, end_ts)` if set.
+    time_range: Option<(u64, u64)>,
+
+    /// Key columns of the table, the rows are sorted by them.
+    key_schema: KeySchema,
+
+    /// Schema versions of the given paths and `key_ids`. Missing columns are errors if not set.
+    schema_versions: SchemaVersions,
 }
 
 impl LocalGridbufferReader {
     pub fn new(paths: Vec<String>, key_ids: Vec<u32>) -> Result<Self> {
+        Self::new_with_time_range(paths, key_ids, None)
+    }
+
+    /// Only read rows with timestamp in `[start_ts, end_ts)` if `time_range` is set.
+    ///
+    /// The block index of each file is used to skip the blocks out of range.
+    pub fn new_with_time_range(
+        paths: Vec<String>,
+        key_ids: Vec<u32>,
+        time_range: Option<(u64, u64)>,
+    ) -> Result<Self> {
         if paths.is_empty() {
             error_bail!("No gridbuffer files provided");
         }
 
-        for p in paths.iter() {
+        let mut filenames = Vec::with_capacity(paths.len());
+        let mut path_indexes = Vec::with_capacity(paths.len());
+
+        for (i, p) in paths.iter().enumerate() {
             if !Path::new(p).exists() {
                 error_bail!("Gridbuffer file {} does not exist", p);
             }
+
+            if Path::new(p).is_dir() {
+                filenames.extend(list_grid_files(p.as_str())?);
+            } else {
+                filenames.push(p.clone());
+            }
+
+            path_indexes.resize(filenames.len(), i);
+        }
+
+        if filenames.is_empty() {
+            error_bail!("No gridbuffer files found in paths: {:?}", paths);
         }
 
-        let file = File::open(paths[0].clone())?;
+        let paths = filenames;
+
+        let segment_reader = Self::open_file(paths[0].as_str(), time_range)?;
 
         Ok(Self {
             paths,
             key_ids,
+            path_indexes,
             cur_path_index: 0,
-            file_reader: BufReader::new(file),
+            segment_reader,
             cur_gridbuffer: None,
+            cur_col_indexes: Vec::new(),
             cur_row_index: 0,
+            time_range,
+            key_schema: KeySchema::default(),
+            schema_versions: SchemaVersions::default(),
         })
     }
 
+    /// Use the key columns of the table, default is the fixed sample key.
+    pub fn with_key_schema(mut self, key_schema: KeySchema) -> Self {
+        self.key_schema = key_schema;
+        self
+    }
+
+    pub fn key_schema(&self) -> &KeySchema {
+        &self.key_schema
+    }
+
+    /// Read the columns added after a partition is created as null, see `get_col_indexes`.
+    pub fn with_schema_versions(mut self, schema_versions: SchemaVersions) -> Self {
+        self.schema_versions = schema_versions;
+        self
+    }
+
+    /// Column indexes of `key_ids` in the gridbuffer of current file.
+    fn get_col_indexes(&self, gridbuffer: &GridBuffer) -> Result<Vec<Option<usize>>> {
+        let partition_version = self
+            .schema_versions
+            .get_partition_version(self.path_indexes[self.cur_path_index]);
+
+        get_col_indexes(
+            gridbuffer,
+            &self.key_ids,
+            partition_version,
+            &self.schema_versions.column_versions,
+        )
+        .map_err(|e| {
+            anyhow::anyhow!(
+                "{}, filename: {}",
+                e,
+                self.paths[self.cur_path_index].as_str()
+            )
+        })
+    }
+
+    fn open_file(filename: &str, time_range: Option<(u64, u64)>) -> Result<GridSegmentReader> {
+        let mut segment_reader = GridSegmentReader::open(filename)?;
+
+        if let Some((start_ts, end_ts)) = time_range {
+            segment_reader.seek_by_time(start_ts, end_ts)?;
+        }
+
+        Ok(segment_reader)
+    }
+
+    /// Whether the row is in the time range. Blocks at the edges of the range may contain rows
+    /// out of range.
+    #[inline]
+    fn is_row_in_time_range(&self, gridbuffer: &GridBuffer, row: usize) -> bool {
+        is_row_in_time_range(gridbuffer, row, self.time_range)
+    }
+
     fn open_next_file(&mut self) -> Result<()> {
         self.cur_path_index += 1;
 
@@ -62,44 +278,61 @@ impl LocalGridbufferReader {
             return Err(anyhow::anyhow!("No more gridbuffer files"));
         }
 
-        let file = File::open(self.paths[self.cur_path_index].clone())?;
-        self.file_reader = BufReader::new(file);
+        self.segment_reader =
+            Self::open_file(self.paths[self.cur_path_index].as_str(), self.time_range)?;
         Ok(())
     }
 
-    fn read_line(&mut self) -> Result<Option<String>> {
-        let mut line = String::new();
-        match self.file_reader.read_line(&mut line) {
-            Ok(bytes_read) => {
-                if bytes_read == 0 {
-                    return Ok(None);
-                } else {
-                    return Ok(Some(line));
-                }
-            }
-            Err(e) => {
+    /// Read next `GridBuffer` from current file. Return `None` if the current file is done.
+    fn read_next(&mut self) -> Result<Option<GridBuffer>> {
+        match self.segment_reader.next() {
+            Some(Ok(gridbuffer)) => Ok(Some(gridbuffer)),
+            Some(Err(e)) => {
                 error_bail!(
-                    "Failed to read line from gridbuffer file, filename: {}, error: {}",
+                    "Failed to read gridbuffer from file, filename: {}, error: {}",
                     self.paths[self.cur_path_index],
                     e
                 );
             }
+            None => Ok(None),
+        }
+    }
+
+    /// Read next `GridBuffer`, open next file if current file is done or has error.
+    ///
+    /// Return `None` if all files are done.
+    fn next_gridbuffer(&mut self) -> Option<GridBuffer> {
+        loop {
+            match self.read_next() {
+                Ok(Some(gridbuffer)) => return Some(gridbuffer),
+                Ok(None) => {}
+                Err(e) => {
+                    error!(
+                        "Failed to read gridbuffer from file, try next file, error: {}",
+                        e
+                    );
+                }
+            }
+
+            if self.cur_path_index + 1 >= self.paths.len() {
+                return None;
+            }
+
+            if let Err(e) = self.open_next_file() {
+                error!("Failed to open next gridbuffer file, error: {}", e);
+                return None;
+            }
         }
     }
 
     fn read_gridbuffer(&mut self) -> Result<()> {
-        match self.read_line() {
-            Ok(line_opt) => match line_opt {
-                Some(line) => match GridBuffer::from_bytes(line.as_bytes()) {
-                    Ok(gridbuffer) => {
-                        self.cur_gridbuffer = Some(gridbuffer);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!("Failed to parse gridbuffer, error: {}", e);
-                        return Err(e);
-                    }
-                },
+        match self.read_next() {
+            Ok(gridbuffer_opt) => match gridbuffer_opt {
+                Some(gridbuffer) => {
+                    self.cur_col_indexes = self.get_col_indexes(&gridbuffer)?;
+                    self.cur_gridbuffer = Some(Arc::new(gridbuffer));
+                    Ok(())
+                }
                 None => {
                     self.open_next_file()?;
                     self.read_gridbuffer()
@@ -107,7 +340,7 @@ impl LocalGridbufferReader {
             },
             Err(e) => {
                 error!(
-                    "Failed to read line from gridbuffer file, open next file, error: {}",
+                    "Failed to read gridbuffer from file, open next file, error: {}",
                     e
                 );
                 self.open_next_file()?;
@@ -121,68 +354,27 @@ impl Iterator for LocalGridbufferReader {
     type Item = GridRowRefs;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.read_line() {
-            Ok(line_opt) => match line_opt {
-                Some(line) => match GridBuffer::from_bytes(line.as_bytes()) {
-                    Ok(gridbuffer) => {
-                        let mut rows = Vec::with_capacity(gridbuffer.num_rows());
-
-                        self.cur_gridbuffer = Some(gridbuffer);
-
-                        for i in 0..self.cur_gridbuffer.as_ref().unwrap().num_rows() {
-                            let mut row = Vec::with_capacity(self.key_ids.len());
-
-                            for key_id in self.key_ids.iter() {
-                                match self.cur_gridbuffer.as_ref().unwrap().get_col_by_id(*key_id) {
-                                    Some(col) => {
-                                        let cell = GridCellRef {
-                                            gridbuffer: self.cur_gridbuffer.as_ref().unwrap(),
-                                            row_index: i,
-                                            col_index: col,
-                                        };
-
-                                        row.push(cell);
-                                    }
-                                    None => {
-                                        error!("column id not found: {}", key_id);
-                                        return None;
-                                    }
-                                }
-                            }
-
-                            rows.push(GridRowRef::new(row));
-                        }
-
-                        Some(GridRowRefs { rows })
-                    }
-                    Err(e) => {
-                        error!("Failed to parse gridbuffer, error: {}", e);
-                        None
-                    }
-                },
-                None => match self.open_next_file() {
-                    Ok(_) => self.next(),
-                    Err(e) => {
-                        error!("Failed to open next gridbuffer file, error: {}", e);
-                        None
-                    }
-                },
-            },
+        let gridbuffer = Arc::new(self.next_gridbuffer()?);
+        let col_indexes = match self.get_col_indexes(&gridbuffer) {
+            Ok(col_indexes) => col_indexes,
             Err(e) => {
-                error!(
-                    "Failed to read line from gridbuffer file, try next file, error: {}",
-                    e
-                );
+                error!("Failed to read gridbuffer, error: {}", e);
+                return None;
+            }
+        };
 
-                match self.open_next_file() {
-                    Ok(_) => self.next(),
-                    Err(e) => {
-                        error!("Failed to open next gridbuffer file, error: {}", e);
-                        None
-                    }
-                }
+        let mut rows = GridRowRefs::new(self.key_ids.len(), gridbuffer.num_rows());
+
+        for i in 0..gridbuffer.num_rows() {
+            if self.is_row_in_time_range(&gridbuffer, i) {
+                rows.push_cells(&gridbuffer, i, &col_indexes);
+                rows.finish_row(1);
             }
         }
+
+        self.cur_gridbuffer = Some(gridbuffer);
+
+        Some(rows)
     }
 }
 
@@ -193,147 +385,586 @@ impl LocalGridRowReader {
         let reader = LocalGridbufferReader::new(file_paths, key_ids)?;
         Ok(Self(reader))
     }
+
+    /// Only read rows with timestamp in `[start_ts, end_ts)`.
+    pub fn new_with_time_range(
+        file_paths: Vec<String>,
+        key_ids: Vec<u32>,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<Self> {
+        let reader = LocalGridbufferReader::new_with_time_range(
+            file_paths,
+            key_ids,
+            Some((start_ts, end_ts)),
+        )?;
+        Ok(Self(reader))
+    }
+
+    /// Use the key columns of the table, default is the fixed sample key.
+    pub fn with_key_schema(self, key_schema: KeySchema) -> Self {
+        Self(self.0.with_key_schema(key_schema))
+    }
+
+    /// Read the columns added after a partition is created as null, see `get_col_indexes`.
+    pub fn with_schema_versions(self, schema_versions: SchemaVersions) -> Self {
+        Self(self.0.with_schema_versions(schema_versions))
+    }
+
+    pub fn key_ids(&self) -> &[u32] {
+        &self.0.key_ids
+    }
 }
 
 impl Iterator for LocalGridRowReader {
     type Item = GridRowRef;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.0.cur_gridbuffer.as_ref() {
-            Some(gridbuffer) => {
-                if self.0.cur_row_index < gridbuffer.num_rows() {
-                    let mut cells = Vec::with_capacity(self.0.key_ids.len());
-
-                    for key_id in self.0.key_ids.iter() {
-                        match gridbuffer.get_col_by_id(*key_id) {
-                            Some(col) => {
-                                let cell = GridCellRef {
-                                    gridbuffer: gridbuffer,
-                                    row_index: self.0.cur_row_index,
-                                    col_index: col,
-                                };
-
-                                cells.push(cell);
-                            }
-                            None => {
-                                error!("column id not found: {}", key_id);
-                                return None;
-                            }
-                        }
-                    }
+        let reader = &mut self.0;
 
-                    self.0.cur_row_index += 1;
-                    return Some(GridRowRef::new(cells));
-                } else {
-                    self.0.cur_row_index = 0;
-
-                    match self.0.read_gridbuffer() {
-                        Ok(_) => self.next(),
-                        Err(e) => {
-                            error!("Failed to read gridbuffer, error: {}", e);
-                            None
-                        }
+        // Loop instead of recursion, there could be many rows out of time range in a row.
+        loop {
+            if let Some(gridbuffer) = reader.cur_gridbuffer.as_ref() {
+                if reader.cur_row_index < gridbuffer.num_rows() {
+                    let row_index = reader.cur_row_index;
+                    reader.cur_row_index += 1;
+
+                    if reader.is_row_in_time_range(gridbuffer, row_index) {
+                        return Some(get_row_ref(gridbuffer, row_index, &reader.cur_col_indexes));
                     }
+
+                    continue;
                 }
             }
-            None => {
-                self.0.cur_row_index = 0;
 
-                match self.0.read_gridbuffer() {
-                    Ok(_) => self.next(),
-                    Err(e) => {
-                        error!("Failed to read gridbuffer, error: {}", e);
-                        None
-                    }
-                }
+            reader.cur_row_index = 0;
+
+            if let Err(e) = reader.read_gridbuffer() {
+                error!("Failed to read gridbuffer, error: {}", e);
+                return None;
             }
         }
     }
 }
 
+/// A cell in a `GridBuffer`, `gridbuffer` is `None` for null cells.
+///
+/// The `GridBuffer` is shared by `Arc`, so the cell can be kept after the reader moves to next
+/// `GridBuffer`.
+#[derive(Clone, Default)]
 pub struct GridCellRef {
-    pub gridbuffer: *const GridBuffer,
+    pub gridbuffer: Option<Arc<GridBuffer>>,
     pub row_index: usize,
     pub col_index: usize,
 }
 
-impl Default for GridCellRef {
-    fn default() -> Self {
-        Self::new(std::ptr::null(), 0, 0)
-    }
-}
-
 impl GridCellRef {
-    pub fn new(gridbuffer: *const GridBuffer, row_index: usize, col_index: usize) -> Self {
+    pub fn new(gridbuffer: Arc<GridBuffer>, row_index: usize, col_index: usize) -> Self {
         Self {
-            gridbuffer,
+            gridbuffer: Some(gridbuffer),
             row_index,
             col_index,
         }
     }
 
     pub fn is_valid(&self) -> bool {
-        !self.gridbuffer.is_null()
+        self.gridbuffer.is_some()
     }
 
+    /// Sample key of the row with the default key columns.
     pub fn get_sample_key(&self) -> Option<SampleKey> {
-        if self.gridbuffer.is_null() {
-            None
-        } else {
-            let row = GridRow::new(self.gridbuffer, self.row_index);
-            Some(row.get_sample_key())
-        }
+        self.gridbuffer
+            .as_ref()
+            .map(|gridbuffer| SampleKey::from_gridbuffer(gridbuffer, self.row_index))
+    }
+
+    /// Get the `u64` value of the cell. `None` if the cell is null.
+    pub fn get_u64(&self) -> Option<u64> {
+        self.gridbuffer
+            .as_ref()
+            .and_then(|gridbuffer| gridbuffer.get_u64(self.row_index, self.col_index))
+    }
+
+    /// Get the `f32` value of the cell. `None` if the cell is null.
+    pub fn get_f32(&self) -> Option<f32> {
+        self.gridbuffer
+            .as_ref()
+            .and_then(|gridbuffer| gridbuffer.get_f32(self.row_index, self.col_index))
+    }
+
+    /// Get the `i64` value of the cell, for `I64` and `I32` columns. `None` if the cell is null.
+    pub fn get_i64(&self) -> Option<i64> {
+        self.gridbuffer
+            .as_ref()
+            .and_then(|gridbuffer| data_type::get_i64(gridbuffer, self.row_index, self.col_index))
+    }
+
+    /// Get the `f64` value of the cell. `None` if the cell is null.
+    pub fn get_f64(&self) -> Option<f64> {
+        self.gridbuffer
+            .as_ref()
+            .and_then(|gridbuffer| data_type::get_f64(gridbuffer, self.row_index, self.col_index))
+    }
+
+    /// Get the `bool` value of the cell. `None` if the cell is null.
+    pub fn get_bool(&self) -> Option<bool> {
+        self.gridbuffer
+            .as_ref()
+            .and_then(|gridbuffer| data_type::get_bool(gridbuffer, self.row_index, self.col_index))
+    }
+
+    /// Get the string value of the cell. `None` if the cell is null.
+    pub fn get_string(&self) -> Option<String> {
+        self.gridbuffer.as_ref().and_then(|gridbuffer| {
+            data_type::get_string(gridbuffer, self.row_index, self.col_index)
+        })
     }
 }
 
+/// A row of cells, which can be kept as long as needed.
+#[derive(Clone)]
 pub struct GridRowRef {
     pub cells: Vec<GridCellRef>,
     pub inner_row: Option<GridRow>,
+
+    /// Bit `i` is set if table `i` contributes to the row. Always `1` for single table.
+    pub table_mask: u64,
 }
 
 impl GridRowRef {
     pub fn new(cells: Vec<GridCellRef>) -> Self {
-        if cells.is_empty() {
-            Self {
-                cells,
-                inner_row: None,
+        Self::new_with_table_mask(cells, 1)
+    }
+
+    /// `inner_row` is the row of the first valid cell.
+    pub fn new_with_table_mask(cells: Vec<GridCellRef>, table_mask: u64) -> Self {
+        let inner_row = cells.iter().find_map(|cell| {
+            cell.gridbuffer
+                .as_ref()
+                .map(|gridbuffer| GridRow::new(gridbuffer.clone(), cell.row_index))
+        });
+
+        Self {
+            cells,
+            inner_row,
+            table_mask,
+        }
+    }
+
+    /// Whether table `table_index` contributes to the row.
+    #[inline]
+    pub fn has_table(&self, table_index: usize) -> bool {
+        self.table_mask & (1 << table_index) != 0
+    }
+}
+
+/// A batch of rows.
+///
+/// To avoid allocating a `Vec` for each row, cells of all rows are stored in one `Vec`, each row
+/// has `num_cols` cells. The `GridBuffer`s referenced by the cells are kept alive by the cells.
+pub struct GridRowRefs {
+    /// Cells of all rows.
+    pub cells: Vec<GridCellRef>,
+
+    /// Number of cells of each row.
+    pub num_cols: usize,
+
+    /// Table mask of each row, see `GridRowRef::table_mask`.
+    pub table_masks: Vec<u64>,
+}
+
+impl GridRowRefs {
+    pub fn new(num_cols: usize, num_rows: usize) -> Self {
+        Self {
+            cells: Vec::with_capacity(num_cols * num_rows),
+            num_cols,
+            table_masks: Vec::with_capacity(num_rows),
+        }
+    }
+
+    #[inline]
+    pub fn num_rows(&self) -> usize {
+        self.table_masks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.table_masks.is_empty()
+    }
+
+    /// Whether table `table_index` contributes to the row.
+    #[inline]
+    pub fn has_table(&self, row: usize, table_index: usize) -> bool {
+        self.table_masks[row] & (1 << table_index) != 0
+    }
+
+    /// Cells of the row.
+    #[inline]
+    pub fn get_row(&self, row: usize) -> &[GridCellRef] {
+        &self.cells[row * self.num_cols..(row + 1) * self.num_cols]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[GridCellRef]> {
+        (0..self.num_rows()).map(move |i| self.get_row(i))
+    }
+
+    /// Push cells of the row, null cells for columns not found.
+    #[inline]
+    fn push_cells(
+        &mut self,
+        gridbuffer: &Arc<GridBuffer>,
+        row: usize,
+        col_indexes: &[Option<usize>],
+    ) {
+        for col in col_indexes.iter() {
+            match col {
+                Some(col) => self
+                    .cells
+                    .push(GridCellRef::new(gridbuffer.clone(), row, *col)),
+                None => self.cells.push(GridCellRef::default()),
             }
-        } else {
-            let ptr = cells[0].gridbuffer;
-            let row_index = cells[0].row_index;
+        }
+    }
+
+    /// Finish the row after cells of all tables are pushed.
+    #[inline]
+    fn finish_row(&mut self, table_mask: u64) {
+        self.table_masks.push(table_mask);
+    }
+
+    /// Push null cells for missing columns.
+    #[inline]
+    fn push_null_cells(&mut self, num_cells: usize) {
+        for _ in 0..num_cells {
+            self.cells.push(GridCellRef::default());
+        }
+    }
+}
+
+/// Cursor over the rows of one table, used by merge readers.
+///
+/// The column indexes of `key_ids` are computed once for each `GridBuffer`, not for each row.
+struct TableCursor {
+    /// Reader of the table.
+    reader: LocalGridbufferReader,
+
+    /// Current gridbuffer. `None` if all rows are read.
+    gridbuffer: Option<Arc<GridBuffer>>,
+
+    /// Current row index.
+    row_index: usize,
+
+    /// Column indexes of `key_ids` in current gridbuffer, `None` for columns read as null.
+    col_indexes: Vec<Option<usize>>,
+}
+
+impl TableCursor {
+    fn new(reader: LocalGridbufferReader) -> Self {
+        let mut cursor = Self {
+            reader,
+            gridbuffer: None,
+            row_index: 0,
+            col_indexes: Vec::new(),
+        };
+
+        cursor.seek_valid_row();
+
+        cursor
+    }
+
+    /// Move to the first row in time range from current row, read next `GridBuffer` if needed.
+    fn seek_valid_row(&mut self) {
+        loop {
+            if let Some(gridbuffer) = self.gridbuffer.as_ref() {
+                while self.row_index < gridbuffer.num_rows() {
+                    if self.reader.is_row_in_time_range(gridbuffer, self.row_index) {
+                        return;
+                    }
 
-            Self {
-                cells,
-                inner_row: Some(GridRow::new(ptr, row_index)),
+                    self.row_index += 1;
+                }
+            }
+
+            match self.reader.next_gridbuffer() {
+                Some(gridbuffer) => match self.reader.get_col_indexes(&gridbuffer) {
+                    Ok(col_indexes) => {
+                        self.col_indexes = col_indexes;
+                        self.gridbuffer = Some(Arc::new(gridbuffer));
+                        self.row_index = 0;
+                    }
+                    Err(e) => {
+                        error!("Failed to read gridbuffer, stop the table, error: {}", e);
+                        self.gridbuffer = None;
+                        return;
+                    }
+                },
+                None => {
+                    self.gridbuffer = None;
+                    return;
+                }
             }
         }
     }
+
+    /// Sample key of current row by the key columns of the table. `None` if all rows are read.
+    #[inline]
+    fn key(&self) -> Option<SampleKey> {
+        self.gridbuffer.as_ref().map(|gridbuffer| {
+            self.reader
+                .key_schema
+                .get_sample_key(gridbuffer, self.row_index)
+        })
+    }
+
+    #[inline]
+    fn advance(&mut self) {
+        self.row_index += 1;
+        self.seek_valid_row();
+    }
+
+    #[inline]
+    fn num_cols(&self) -> usize {
+        self.reader.key_ids.len()
+    }
+
+    /// Push cells of current row to `rows`.
+    #[inline]
+    fn push_cells(&self, rows: &mut GridRowRefs) {
+        if let Some(gridbuffer) = self.gridbuffer.as_ref() {
+            rows.push_cells(gridbuffer, self.row_index, &self.col_indexes);
+        }
+    }
 }
 
-pub struct GridRowRefs {
-    pub rows: Vec<GridRowRef>,
+/// `FilteredGridRowReader` only yields rows matching the predicate.
+///
+/// It works on both local and remote readers. The predicate is evaluated on the whole row of
+/// the underlying `GridBuffer`, so the columns of predicate must be read, but not necessary in
+/// the `key_ids` of the reader.
+pub struct FilteredGridRowReader<T: Iterator<Item = GridRowRef>> {
+    /// Underlying reader.
+    reader: T,
+
+    /// Predicate with resolved column ids.
+    predicate: Predicate,
+
+    /// Number of rows dropped by the predicate.
+    num_dropped: u64,
+
+    /// Number of rows yielded.
+    num_matched: u64,
+}
+
+impl<T: Iterator<Item = GridRowRef>> FilteredGridRowReader<T> {
+    pub fn new(reader: T, predicate: Predicate) -> Self {
+        Self {
+            reader,
+            predicate,
+            num_dropped: 0,
+            num_matched: 0,
+        }
+    }
+
+    /// Number of rows dropped by the predicate so far.
+    pub fn num_dropped(&self) -> u64 {
+        self.num_dropped
+    }
+
+    /// Number of rows matching the predicate so far.
+    pub fn num_matched(&self) -> u64 {
+        self.num_matched
+    }
+}
+
+impl<T: Iterator<Item = GridRowRef>> Iterator for FilteredGridRowReader<T> {
+    type Item = GridRowRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.reader.next() {
+                Some(row) => {
+                    let is_matched = row
+                        .inner_row
+                        .as_ref()
+                        .map_or(false, |inner_row| self.predicate.eval(inner_row));
+
+                    if is_matched {
+                        self.num_matched += 1;
+                        return Some(row);
+                    }
+
+                    self.num_dropped += 1;
+                }
+                None => {
+                    info!(
+                        "filter rows done, matched: {}, dropped: {}",
+                        self.num_matched, self.num_dropped
+                    );
+                    return None;
+                }
+            }
+        }
+    }
 }
 
+/// Join type of merging multiple tables by `SampleKey`.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinType {
+    /// Only rows found in all tables.
+    Inner,
+
+    /// All rows of the first table, with null cells for tables not found.
+    #[default]
+    Left,
+
+    /// All rows of all tables, with null cells for tables not found.
+    FullOuter,
+}
+
+/// Max number of tables to merge, limited by the bits of table mask.
+pub const MAX_MERGE_TABLES: usize = 64;
+
 /// `LocalGridBufferMergeReader` is used to merge multiple tables.
+///
+/// All tables are sorted by `SampleKey`, so they are merged like merge sort. Rows with the same
+/// `SampleKey` in different tables are joined into one row according to `JoinType`. Cells of
+/// tables not found are null, and `table_masks` of the batch tells which tables contribute to
+/// each row.
+///
+/// If there are multiple rows with the same `SampleKey` in one table, each of them is joined
+/// with at most one row of other tables.
+///
+/// The merged rows are returned in batches of `batch_size` rows.
 pub struct LocalGridBufferMergeReader {
-    /// Gridbuffer readers.
-    readers: Vec<LocalGridRowReader>,
+    /// Cursors of tables.
+    cursors: Vec<TableCursor>,
 
     /// Key ids.
     key_ids: Vec<Vec<u32>>,
 
     /// Total key ids.
     total_key_ids: usize,
+
+    /// Number of rows in each batch.
+    batch_size: usize,
+
+    /// Join type.
+    join_type: JoinType,
 }
 
 impl LocalGridBufferMergeReader {
-    pub fn new(readers: Vec<LocalGridRowReader>, key_ids: Vec<Vec<u32>>) -> Self {
+    pub fn new(
+        readers: Vec<LocalGridRowReader>,
+        key_ids: Vec<Vec<u32>>,
+        batch_size: usize,
+        join_type: JoinType,
+    ) -> Result<Self> {
+        if readers.is_empty() || readers.len() > MAX_MERGE_TABLES {
+            error_bail!(
+                "The number of tables to merge must be in [1, {}], but got {}",
+                MAX_MERGE_TABLES,
+                readers.len()
+            );
+        }
+
+        if readers.len() != key_ids.len() {
+            error_bail!(
+                "The length of readers and key_ids must be the same, readers.len(): {}, key_ids.len(): {}",
+                readers.len(),
+                key_ids.len()
+            );
+        }
+
+        // Rows are joined by `SampleKey`, which only makes sense with the same key columns.
+        let key_schema = readers[0].0.key_schema();
+        if let Some(reader) = readers.iter().find(|x| x.0.key_schema() != key_schema) {
+            error_bail!(
+                "The key columns of tables to merge must be the same, {:?} vs {:?}",
+                key_schema.col_ids(),
+                reader.0.key_schema().col_ids()
+            );
+        }
+
         let total_key_ids = key_ids.iter().map(|k| k.len()).sum();
 
-        Self {
-            readers,
+        let cursors = readers
+            .into_iter()
+            .map(|reader| TableCursor::new(reader.0))
+            .collect();
+
+        Ok(Self {
+            cursors,
             key_ids,
             total_key_ids,
+            batch_size: batch_size.max(1),
+            join_type,
+        })
+    }
+
+    /// Merge next row and push the cells to `rows`.
+    ///
+    /// Return `false` if there are no more rows.
+    fn merge_next_row(&mut self, rows: &mut GridRowRefs) -> bool {
+        let key = match self.join_type {
+            JoinType::Inner => self.seek_inner_key(),
+            JoinType::Left => self.cursors[0].key(),
+            JoinType::FullOuter => self.cursors.iter().filter_map(|cursor| cursor.key()).min(),
+        };
+
+        let key = match key {
+            Some(key) => key,
+            None => return false,
+        };
+
+        let mut table_mask = 0;
+
+        for (i, cursor) in self.cursors.iter_mut().enumerate() {
+            // Only needed for left join, the other tables may have rows not in the first table.
+            while cursor.key().map_or(false, |x| x < key) {
+                cursor.advance();
+            }
+
+            if cursor.key() == Some(key) {
+                cursor.push_cells(rows);
+                cursor.advance();
+
+                table_mask |= 1 << i;
+            } else {
+                rows.push_null_cells(cursor.num_cols());
+            }
+        }
+
+        rows.finish_row(table_mask);
+
+        true
+    }
+
+    /// Skip rows until all tables have the same `SampleKey`, return the key.
+    ///
+    /// Return `None` if any table is done.
+    fn seek_inner_key(&mut self) -> Option<SampleKey> {
+        loop {
+            let mut max_key = self.cursors[0].key()?;
+            let mut is_same = true;
+
+            for cursor in self.cursors.iter().skip(1) {
+                let key = cursor.key()?;
+
+                if key != max_key {
+                    is_same = false;
+                    max_key = max_key.max(key);
+                }
+            }
+
+            if is_same {
+                return Some(max_key);
+            }
+
+            for cursor in self.cursors.iter_mut() {
+                while cursor.key().map_or(false, |x| x < max_key) {
+                    cursor.advance();
+                }
+            }
         }
     }
 }
@@ -341,17 +972,33 @@ impl LocalGridBufferMergeReader {
 impl Iterator for LocalGridBufferMergeReader {
     type Item = GridRowRefs;
 
-    /// TODO: Implement this.
     fn next(&mut self) -> Option<Self::Item> {
-        None
+        let mut rows = GridRowRefs::new(self.total_key_ids, self.batch_size);
+
+        while rows.num_rows() < self.batch_size && self.merge_next_row(&mut rows) {}
+
+        if rows.is_empty() {
+            None
+        } else {
+            Some(rows)
+        }
     }
 }
 
+/// Row level merge reader, same join semantics as `LocalGridBufferMergeReader`.
+///
+/// Use `LocalGridBufferMergeReader` to avoid allocating a `Vec` for each row.
 pub struct LocalGridRowMergeReader(LocalGridBufferMergeReader);
 
 impl LocalGridRowMergeReader {
-    pub fn new(readers: Vec<LocalGridRowReader>, key_ids: Vec<Vec<u32>>) -> Self {
-        Self(LocalGridBufferMergeReader::new(readers, key_ids))
+    pub fn new(
+        readers: Vec<LocalGridRowReader>,
+        key_ids: Vec<Vec<u32>>,
+        join_type: JoinType,
+    ) -> Result<Self> {
+        Ok(Self(LocalGridBufferMergeReader::new(
+            readers, key_ids, 1, join_type,
+        )?))
     }
 }
 
@@ -359,69 +1006,13 @@ impl Iterator for LocalGridRowMergeReader {
     type Item = GridRowRef;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.0.total_key_ids == 0 {
-            return None;
-        }
-
-        let mut cells = Vec::with_capacity(self.0.total_key_ids);
+        let mut rows = GridRowRefs::new(self.0.total_key_ids, 1);
 
-        let mut _primary_key = None;
-
-        match self.0.readers[0].next() {
-            Some(row) => {
-                if row.cells.len() == 0 {
-                    error!("The number of cells of first reader is 0!");
-                    return None;
-                } else {
-                    if row.cells[0].is_valid() {
-                        _primary_key = row.cells[0].get_sample_key();
-                        cells.extend(row.cells);
-                    } else {
-                        error!("The first row has no inner row!");
-                        return None;
-                    }
-                }
-            }
-            None => {
-                error!("Read first reader failed!");
-                return None;
-            }
-        }
-
-        // Read other readers to match the first sample key. Stop until the sample key is found
-        // or bigger than the first one.
-        for i in 1..self.0.readers.len() {
-            let mut has_value = false;
-
-            while let Some(row) = self.0.readers[i].next() {
-                if row.cells[0].is_valid() {
-                    let cur_key = row.cells[0].get_sample_key();
-
-                    match (cur_key.as_ref(), _primary_key.as_ref()) {
-                        (Some(key), Some(primary_key)) => {
-                            if *key > *primary_key {
-                                break;
-                            } else if *key == *primary_key {
-                                has_value = true;
-                                cells.extend(row.cells);
-                                break;
-                            }
-                        }
-                        (_, _) => {
-                            error!("Primary key is not set!");
-                            return None;
-                        }
-                    }
-                }
-            }
-
-            if !has_value {
-                for _j in 0..self.0.key_ids[i].len() {
-                    cells.push(GridCellRef::default());
-                }
-            }
+        if self.0.merge_next_row(&mut rows) {
+            let table_mask = rows.table_masks[0];
+            Some(GridRowRef::new_with_table_mask(rows.cells, table_mask))
+        } else {
+            None
         }
-
-        Some(GridRowRef::new(cells))
     }
 }
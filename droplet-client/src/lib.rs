@@ -4,3 +4,4 @@
 
 pub mod client;
 pub mod gridbuffer_reader;
+pub mod remote_reader;
@@ -0,0 +1,147 @@
+use anyhow::{anyhow, Result};
+use log::error;
+use std::sync::Arc;
+
+use droplet_core::droplet::droplet_client::DropletClient;
+use droplet_core::droplet::ReadPartitionRequest;
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use crate::gridbuffer_reader::{
+    get_col_indexes, get_row_ref, is_row_in_time_range, GridRowRef, SchemaVersions,
+};
+
+/// `RemoteGridRowReader` reads one partition from droplet server by `ReadPartition`.
+///
+/// The `GridBuffer`s are received by a background task, and decoded before sent to the reader.
+/// The iterator is blocking, so it must not be consumed in the thread of a single-threaded
+/// `tokio` runtime, or the background task cannot make progress.
+pub struct RemoteGridRowReader {
+    /// Receiver of decoded `GridBuffer`s.
+    receiver: async_channel::Receiver<Result<GridBuffer>>,
+
+    /// Key ids.
+    key_ids: Vec<u32>,
+
+    /// Only yield rows with timestamp in `[start_ts, end_ts)` if set.
+    time_range: Option<(u64, u64)>,
+
+    /// Current gridbuffer.
+    cur_gridbuffer: Option<Arc<GridBuffer>>,
+
+    /// Column indexes of `key_ids` in current gridbuffer, see `get_col_indexes`.
+    cur_col_indexes: Vec<Option<usize>>,
+
+    /// Current row index.
+    cur_row_index: usize,
+
+    /// Schema versions of the partition and `key_ids`. Missing columns are errors if not set.
+    schema_versions: SchemaVersions,
+}
+
+impl RemoteGridRowReader {
+    pub async fn new(
+        mut client: DropletClient<tonic::transport::Channel>,
+        req: ReadPartitionRequest,
+        key_ids: Vec<u32>,
+    ) -> Result<Self> {
+        let time_range = if req.time_end > req.time_start {
+            Some((req.time_start, req.time_end))
+        } else {
+            None
+        };
+
+        let mut stream = client.read_partition(req).await?.into_inner();
+
+        let (sender, receiver) = async_channel::bounded::<Result<GridBuffer>>(16);
+
+        tokio::spawn(async move {
+            loop {
+                match stream.message().await {
+                    Ok(Some(res)) => {
+                        let gridbuffer = GridBuffer::from_bytes(&res.gridbuffer_bytes);
+
+                        if sender.send(gridbuffer).await.is_err() {
+                            // Reader is dropped.
+                            break;
+                        }
+                    }
+                    Ok(None) => break,
+                    Err(e) => {
+                        let _ = sender
+                            .send(Err(anyhow!("Read partition failed, error: {}", e)))
+                            .await;
+                        break;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            receiver,
+            key_ids,
+            time_range,
+            cur_gridbuffer: None,
+            cur_col_indexes: Vec::new(),
+            cur_row_index: 0,
+            schema_versions: SchemaVersions::default(),
+        })
+    }
+
+    /// Read the columns added after the partition is created as null, see `get_col_indexes`.
+    pub fn with_schema_versions(mut self, schema_versions: SchemaVersions) -> Self {
+        self.schema_versions = schema_versions;
+        self
+    }
+
+    /// Receive next `GridBuffer`. Return `false` if the stream is done or has error.
+    fn read_gridbuffer(&mut self) -> bool {
+        match self.receiver.recv_blocking() {
+            Ok(Ok(gridbuffer)) => {
+                match get_col_indexes(
+                    &gridbuffer,
+                    &self.key_ids,
+                    self.schema_versions.get_partition_version(0),
+                    &self.schema_versions.column_versions,
+                ) {
+                    Ok(col_indexes) => self.cur_col_indexes = col_indexes,
+                    Err(e) => {
+                        error!("Failed to read gridbuffer from server, error: {}", e);
+                        return false;
+                    }
+                }
+
+                self.cur_gridbuffer = Some(Arc::new(gridbuffer));
+                self.cur_row_index = 0;
+                true
+            }
+            Ok(Err(e)) => {
+                error!("Failed to read gridbuffer from server, error: {}", e);
+                false
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+impl Iterator for RemoteGridRowReader {
+    type Item = GridRowRef;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(gridbuffer) = self.cur_gridbuffer.as_ref() {
+                while self.cur_row_index < gridbuffer.num_rows() {
+                    let row_index = self.cur_row_index;
+                    self.cur_row_index += 1;
+
+                    if is_row_in_time_range(gridbuffer, row_index, self.time_range) {
+                        return Some(get_row_ref(gridbuffer, row_index, &self.cur_col_indexes));
+                    }
+                }
+            }
+
+            if !self.read_gridbuffer() {
+                return None;
+            }
+        }
+    }
+}
@@ -1,9 +1,26 @@
 use anyhow::Result;
 use log::info;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tonic::transport::Server;
 
 use droplet_client::client::Client;
+use droplet_client::gridbuffer_reader::{
+    GridRowRef, GridRowRefs, JoinType, LocalGridBufferMergeReader, LocalGridRowReader,
+    SchemaVersions,
+};
+use droplet_core::db::memory_store::MemoryMetaStore;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::droplet::droplet_server::DropletServer;
+use droplet_core::droplet::meta_server::MetaServer;
 use droplet_core::droplet::ColumnInfo;
+use droplet_core::grid_sample::{GridSample, KeySchema, SampleKey};
+use droplet_core::grid_segment::GridSegmentWriter;
+use droplet_core::partition::{get_sorted_partition_path, PartitionScheme, SORTED_TABLE_ROOT};
 use droplet_core::{droplet::DataType, tool::setup_log};
+use droplet_meta_server::request_handler::MetaServerImpl;
+use droplet_server::request_handler::DropletServerImpl;
 
 #[tokio::test]
 async fn test_heartbeat() -> Result<()> {
@@ -14,3 +31,429 @@ async fn test_heartbeat() -> Result<()> {
 
     Ok(())
 }
+
+/// Temp directory of a test, removed when dropped, so files are cleaned up even if the test
+/// fails.
+struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Directory `droplet_test_<name>_<pid>` under the temp dir of the system.
+    fn new(name: &str) -> Self {
+        Self::with_path(std::env::temp_dir().join(format!(
+            "droplet_test_{}_{}",
+            name,
+            std::process::id()
+        )))
+    }
+
+    /// Use `path` as the directory, files left by a previous run are removed.
+    fn with_path(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let _ = std::fs::remove_dir_all(&path);
+
+        Self(path)
+    }
+
+    /// Path of `name` under the directory.
+    fn path(&self, name: &str) -> String {
+        self.0.join(name).to_string_lossy().to_string()
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Write a sorted table to `<path>/0.grid`, one `GridBuffer` for each item of `blocks`.
+///
+/// The value of column `col_id` is `timestamp * factor`.
+fn write_test_table(path: &str, col_id: u32, factor: u64, blocks: &[Vec<u64>]) -> Result<()> {
+    std::fs::create_dir_all(path)?;
+
+    let mut writer = GridSegmentWriter::create(format!("{}/0.grid", path).as_str())?;
+
+    for timestamps in blocks.iter() {
+        let mut sample = GridSample::new(timestamps.len(), &vec![col_id]);
+
+        for (row, timestamp) in timestamps.iter().enumerate() {
+            sample.set_sample_key(row, &SampleKey::new(*timestamp, 1, 2, 3));
+            sample.push_u64(row, 4, timestamp * factor);
+        }
+
+        writer.write(&sample.gridbuffer)?;
+    }
+
+    writer.finish()
+}
+
+fn merge_test_tables(dir: &TestDir, join_type: JoinType) -> Result<Vec<GridRowRefs>> {
+    let readers = vec![
+        LocalGridRowReader::new(vec![dir.path("a")], vec![100])?,
+        LocalGridRowReader::new(vec![dir.path("b")], vec![200])?,
+    ];
+
+    Ok(
+        LocalGridBufferMergeReader::new(readers, vec![vec![100], vec![200]], 4, join_type)?
+            .collect(),
+    )
+}
+
+#[test]
+fn test_merge_batch() -> Result<()> {
+    setup_log();
+
+    let dir = TestDir::new("merge_batch");
+
+    // Table a: 0..10, table b: even numbers in 0..10 and 11.
+    write_test_table(
+        &dir.path("a"),
+        100,
+        1,
+        &[(0..5).collect(), (5..10).collect()],
+    )?;
+    write_test_table(&dir.path("b"), 200, 10, &[vec![0, 2, 4], vec![6, 8, 11]])?;
+
+    let cases = [
+        (JoinType::Left, (0..10).collect::<Vec<u64>>()),
+        (JoinType::Inner, vec![0, 2, 4, 6, 8]),
+        (JoinType::FullOuter, (0..10).chain(11..12).collect()),
+    ];
+
+    for (join_type, expected) in cases.iter() {
+        let batches = merge_test_tables(&dir, *join_type)?;
+
+        assert!(batches.iter().all(|x| x.num_rows() <= 4 && x.num_cols == 2));
+
+        let mut timestamps = Vec::new();
+
+        for batch in batches.iter() {
+            for (i, row) in batch.rows().enumerate() {
+                let timestamp = match row[0].get_u64() {
+                    Some(timestamp) => timestamp,
+                    None => row[1].get_u64().unwrap() / 10,
+                };
+
+                assert_eq!(batch.has_table(i, 0), timestamp < 10);
+                assert_eq!(batch.has_table(i, 0), row[0].is_valid());
+                assert_eq!(batch.has_table(i, 1), timestamp % 2 == 0 || timestamp == 11);
+
+                if batch.has_table(i, 1) {
+                    assert_eq!(row[1].get_u64(), Some(timestamp * 10));
+                } else {
+                    assert!(!row[1].is_valid());
+                }
+
+                timestamps.push(timestamp);
+            }
+        }
+
+        assert_eq!(&timestamps, expected, "join_type: {:?}", join_type);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_keep_rows() -> Result<()> {
+    setup_log();
+
+    let dir = TestDir::new("keep_rows");
+    let path = dir.path("a");
+
+    write_test_table(&path, 100, 3, &[vec![0, 1], vec![2, 3], vec![4]])?;
+
+    // Rows are kept after the reader moves to next `GridBuffer`.
+    let rows = LocalGridRowReader::new(vec![path], vec![100])?.collect::<Vec<_>>();
+
+    assert_eq!(rows.len(), 5);
+
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row.cells[0].get_u64(), Some(i as u64 * 3));
+        assert_eq!(
+            row.cells[0].get_sample_key().map(|key| key.timestamp),
+            Some(i as u64)
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_read_across_schema_change() -> Result<()> {
+    setup_log();
+
+    let dir = TestDir::new("schema_change");
+    let path_old = dir.path("old");
+    let path_new = dir.path("new");
+
+    // Old partition only has column 100, column 101 is added later.
+    write_test_table(&path_old, 100, 1, &[vec![0, 1]])?;
+
+    std::fs::create_dir_all(&path_new)?;
+    let mut writer = GridSegmentWriter::create(format!("{}/0.grid", path_new).as_str())?;
+
+    let mut sample = GridSample::new(2, &vec![100, 101]);
+    for (row, timestamp) in [2, 3].iter().enumerate() {
+        sample.set_sample_key(row, &SampleKey::new(*timestamp, 1, 2, 3));
+        sample.push_u64(row, 4, *timestamp);
+        sample.push_u64(row, 5, timestamp * 10);
+    }
+
+    writer.write(&sample.gridbuffer)?;
+    writer.finish()?;
+
+    let read_rows = |schema_versions: SchemaVersions| -> Result<Vec<GridRowRef>> {
+        Ok(
+            LocalGridRowReader::new(vec![path_old.clone(), path_new.clone()], vec![100, 101])?
+                .with_schema_versions(schema_versions)
+                .collect(),
+        )
+    };
+
+    // Column 101 is added in schema version 2, after the old partition is created.
+    let rows = read_rows(SchemaVersions::new(vec![1, 2], vec![1, 2]))?;
+
+    assert_eq!(rows.len(), 4);
+
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row.cells[0].get_u64(), Some(i as u64));
+
+        if i < 2 {
+            assert!(!row.cells[1].is_valid());
+            assert_eq!(row.cells[1].get_u64(), None);
+        } else {
+            assert_eq!(row.cells[1].get_u64(), Some(i as u64 * 10));
+        }
+    }
+
+    // Column 101 should be in the old partition, or the schema versions are unknown, the reader
+    // stops at the old partition.
+    assert!(read_rows(SchemaVersions::new(vec![1, 2], vec![1, 1]))?.is_empty());
+    assert!(read_rows(SchemaVersions::default())?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_merge_with_key_schema() -> Result<()> {
+    setup_log();
+
+    let dir = TestDir::new("key_schema");
+
+    // Key columns `[timestamp, 300]`, rows are joined by both of them.
+    let key_schema = KeySchema::new(vec![2, 300])?;
+
+    let write_table = |name: &str, col_id: u32, keys: &[(u64, u64)]| -> Result<String> {
+        let path = dir.path(name);
+        std::fs::create_dir_all(&path)?;
+
+        let mut writer = GridSegmentWriter::create(format!("{}/0.grid", path).as_str())?
+            .with_key_schema(&key_schema);
+
+        let mut sample = GridSample::new_with_key_schema(keys.len(), &key_schema, &vec![col_id]);
+        for (row, (timestamp, key)) in keys.iter().enumerate() {
+            sample.set_sample_key(row, &SampleKey::new(*timestamp, *key, 0, 0));
+            sample.push_u64(row, 2, key * 10);
+        }
+
+        writer.write(&sample.gridbuffer)?;
+        writer.finish()?;
+
+        Ok(path)
+    };
+
+    let path_a = write_table("a", 100, &[(0, 1), (0, 2), (1, 1)])?;
+    let path_b = write_table("b", 200, &[(0, 2), (1, 1), (1, 3)])?;
+
+    let readers = vec![
+        LocalGridRowReader::new(vec![path_a.clone()], vec![100])?
+            .with_key_schema(key_schema.clone()),
+        LocalGridRowReader::new(vec![path_b.clone()], vec![200])?
+            .with_key_schema(key_schema.clone()),
+    ];
+
+    let rows =
+        LocalGridBufferMergeReader::new(readers, vec![vec![100], vec![200]], 4, JoinType::Inner)?
+            .flat_map(|batch| {
+                batch
+                    .rows()
+                    .map(|row| (row[0].get_u64(), row[1].get_u64()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+    assert_eq!(rows, vec![(Some(20), Some(20)), (Some(10), Some(10))]);
+
+    // Tables with different key columns cannot be merged.
+    let readers = vec![
+        LocalGridRowReader::new(vec![path_a], vec![100])?.with_key_schema(key_schema.clone()),
+        LocalGridRowReader::new(vec![path_b], vec![200])?,
+    ];
+
+    assert!(LocalGridBufferMergeReader::new(
+        readers,
+        vec![vec![100], vec![200]],
+        4,
+        JoinType::Inner
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_read_typed_columns() -> Result<()> {
+    setup_log();
+
+    let dir = TestDir::new("typed");
+    let path = dir.path("a");
+    std::fs::create_dir_all(&path)?;
+
+    let mut writer = GridSegmentWriter::create(format!("{}/0.grid", path).as_str())?;
+
+    // Columns of `I64`, `F64`, `Bool` and `String`.
+    let mut sample = GridSample::new(2, &vec![100, 101, 102, 103]);
+    for row in 0..2 {
+        sample.set_sample_key(row, &SampleKey::new(row as u64, 1, 2, 3));
+        sample.push_i64(row, 4, -(row as i64));
+        sample.push_f64(row, 5, row as f64 / 3.0);
+        sample.push_bool(row, 6, row == 1);
+        sample.push_string(row, 7, format!("row_{}", row).as_str());
+    }
+
+    writer.write(&sample.gridbuffer)?;
+    writer.finish()?;
+
+    let rows = LocalGridRowReader::new(vec![path], vec![103, 100, 101, 102])?.collect::<Vec<_>>();
+
+    assert_eq!(rows.len(), 2);
+
+    for (i, row) in rows.iter().enumerate() {
+        assert_eq!(row.cells[0].get_string(), Some(format!("row_{}", i)));
+        assert_eq!(row.cells[1].get_i64(), Some(-(i as i64)));
+        assert_eq!(row.cells[2].get_f64(), Some(i as f64 / 3.0));
+        assert_eq!(row.cells[3].get_bool(), Some(i == 1));
+    }
+
+    Ok(())
+}
+
+/// Serve meta server and droplet server backed by `store` on a free local port, return the
+/// endpoint of both.
+async fn serve_test_servers(store: Arc<dyn MetaStore>) -> Result<String> {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+
+    let router = Server::builder()
+        .add_service(MetaServer::new(MetaServerImpl::new(store.clone())))
+        .add_service(DropletServer::new(DropletServerImpl::new(store)));
+
+    tokio::spawn(router.serve(addr));
+
+    Ok(addr.to_string())
+}
+
+/// Connect to the servers started by `serve_test_servers`, retry until they are ready.
+async fn connect_test_client(endpoint: &String) -> Result<Client> {
+    for _ in 0..50 {
+        if let Ok(client) = Client::new(endpoint, endpoint).await {
+            return Ok(client);
+        }
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    Client::new(endpoint, endpoint).await
+}
+
+/// Values of column `value` of the rows in `[start_ts, end_ts)`.
+async fn read_range_values(
+    client: &mut Client,
+    table: &str,
+    start_ts: u64,
+    end_ts: u64,
+) -> Result<Vec<Option<u64>>> {
+    let rows = client
+        .read_gridbuffer_range(table, start_ts, end_ts, &vec!["value".to_string()])
+        .await?;
+
+    Ok(rows.map(|row| row.cells[0].get_u64()).collect())
+}
+
+#[tokio::test]
+async fn test_read_gridbuffer_range() -> Result<()> {
+    setup_log();
+
+    let table = format!("droplet_test_read_range_{}", std::process::id());
+    let _table_dir = TestDir::with_path(format!("{}/{}", SORTED_TABLE_ROOT, table));
+    let store: Arc<dyn MetaStore> = Arc::new(MemoryMetaStore::new());
+
+    let node_id = store.register_node("node_a", "127.0.0.1", 50052)?;
+    store.update_storage_info(node_id, 0, 1 << 40)?;
+
+    let columns = vec![ColumnInfo {
+        column_name: "value".to_string(),
+        column_type: DataType::Ui64.into(),
+        ..Default::default()
+    }];
+    store.insert_table_info(&table, &PartitionScheme::new(24), &[], &columns)?;
+    let value_id = store.get_or_insert_key_id("value")?;
+
+    // 2024-10-11 23:00:00 UTC, partitions `20241011/23`, `20241012/0` and `20241012/1`.
+    let ts: u64 = 1728604800 + 3600 * 23;
+    for hour in 0..3 {
+        store.get_partition_infos(&table, ts + hour * 3600)?;
+    }
+
+    // The value of each row is its timestamp.
+    let write_partition = |partition_date: u32, partition_index: u32, timestamps: &[u64]| {
+        write_test_table(
+            &get_sorted_partition_path(&table, partition_date, partition_index),
+            value_id,
+            1,
+            &[timestamps.to_vec()],
+        )
+    };
+
+    // One row every 10 minutes.
+    let timestamps = (0..12).map(|i| ts + i * 600).collect::<Vec<_>>();
+    write_partition(20241011, 23, &timestamps[0..6])?;
+    write_partition(20241012, 0, &timestamps[6..12])?;
+
+    // Rows in the time range, they would be read if the partition was not pruned.
+    write_partition(20241012, 1, &[ts + 1800, ts + 4200])?;
+
+    let endpoint = serve_test_servers(store).await?;
+    let mut client = connect_test_client(&endpoint).await?;
+
+    // From 23:30 to 00:30 of the next day, rows at the edges are trimmed, and rows of
+    // `20241011/23` come before `20241012/0`.
+    assert_eq!(
+        read_range_values(&mut client, &table, ts + 1800, ts + 5400).await?,
+        timestamps[3..9]
+            .iter()
+            .copied()
+            .map(Some)
+            .collect::<Vec<_>>()
+    );
+
+    // Inside one partition.
+    assert_eq!(
+        read_range_values(&mut client, &table, ts + 4200, ts + 4201).await?,
+        vec![Some(ts + 4200)]
+    );
+
+    // No partition in range.
+    assert!(read_range_values(&mut client, &table, ts - 3600, ts)
+        .await
+        .is_err());
+
+    // Columns not in the table are rejected.
+    assert!(client
+        .read_gridbuffer_range(&table, ts, ts + 3600, &vec!["unknown".to_string()])
+        .await
+        .is_err());
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+//! Rewrite `.grid` files in legacy text format to segment format in place.
+//!
+//! Usage: `convert-grid-files <path>...`
+//!
+//! Each path can be a file or a directory. For directory, all `.grid` files under it are
+//! converted recursively. Files already in segment format are skipped.
+use anyhow::Result;
+use log::{error, info};
+use std::path::Path;
+
+use droplet_core::grid_segment::rewrite_base64_file;
+use droplet_core::tool::init_log;
+
+fn collect_grid_files(path: &Path, filenames: &mut Vec<String>) -> Result<()> {
+    if path.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            collect_grid_files(&entry?.path(), filenames)?;
+        }
+    } else if path.extension().map_or(false, |ext| ext == "grid") {
+        filenames.push(path.to_string_lossy().to_string());
+    }
+
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    init_log();
+
+    let paths = std::env::args().skip(1).collect::<Vec<String>>();
+    if paths.is_empty() {
+        error!("Usage: convert-grid-files <path>...");
+        return Ok(());
+    }
+
+    let mut filenames = Vec::new();
+    for path in paths.iter() {
+        collect_grid_files(Path::new(path), &mut filenames)?;
+    }
+
+    let mut count_converted = 0;
+    for filename in filenames.iter() {
+        if rewrite_base64_file(filename)? {
+            count_converted += 1;
+        }
+    }
+
+    info!(
+        "convert grid files done, total: {}, converted: {}",
+        filenames.len(),
+        count_converted
+    );
+
+    Ok(())
+}
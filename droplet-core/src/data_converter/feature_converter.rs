@@ -1,39 +1,264 @@
-use std::sync::Arc;
-use std::sync::RwLock;
+use anyhow::{bail, Result};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use likely_stable::unlikely;
+use log::error;
+use prost::Message;
 
-use crate::grid_buffer::GridBuffer;
+use crate::data_type::DataType;
+use crate::droplet::ColumnInfo;
+use crate::error_bail;
+use crate::grid_sample::{GridSample, KeySchema, SampleKey};
 use crate::id_mapping::IDMapping;
-use crate::sample_key::SampleKey;
-use crate::simple_features::SimpleFeatures;
+use crate::sniper::SimpleFeatures;
+
+/// Parse `SimpleFeatures` from one line of feature file, the line is `base64` of the proto.
+pub fn parse_simple_features(line: &str) -> Result<SimpleFeatures> {
+    let bytes = STANDARD.decode(line.trim())?;
+    Ok(SimpleFeatures::decode(bytes.as_slice())?)
+}
 
 /// Convert `SimpleFeatures` to `GridSample`.
 ///
 /// Add `SampleKey` and `col_id` for the data.
+///
+/// The `index` of `SparseFeature` and `DenseFeature` is the position in the sparse and dense
+/// features of the table, the column ids are resolved from the feature names by `IDMapping`.
+/// The columns of result are the `SampleKey` columns, then sparse features and dense features,
+/// so all `GridSample`s of one converter have the same `col_ids`.
 pub struct SimpleFeaturesConverter {
-    /// ID mapping from string to u32.
-    id_mapping: Arc<RwLock<IDMapping>>,
+    /// Number of sparse features.
+    sparse_count: usize,
+
+    /// Column ids of sparse features then dense features, not including `SampleKey` columns.
+    col_ids: Vec<u32>,
 }
 
 impl SimpleFeaturesConverter {
-    pub fn new(id_mapping: Arc<RwLock<IDMapping>>) -> Self {
-        Self { id_mapping }
+    /// Create a converter with the names of sparse and dense features in order of `index`.
+    pub fn new(
+        id_mapping: &IDMapping,
+        sparse_feature_names: &Vec<String>,
+        dense_feature_names: &Vec<String>,
+    ) -> Result<Self> {
+        let mut col_ids = id_mapping.get_ids(sparse_feature_names)?;
+        col_ids.extend(id_mapping.get_ids(dense_feature_names)?);
+
+        Ok(Self {
+            sparse_count: sparse_feature_names.len(),
+            col_ids,
+        })
+    }
+
+    /// Create a converter with the columns of the table.
+    ///
+    /// `F32` and `F32List` columns are dense features, other columns are sparse features. The
+    /// order of `index` is the order of `column_index`. Deprecated columns and `SampleKey`
+    /// columns are ignored.
+    ///
+    /// `SimpleFeatures` only has the fields of `SampleKey`, so the table must use the default
+    /// `KeySchema`, otherwise it's an error.
+    pub fn from_columns(
+        id_mapping: &IDMapping,
+        key_schema: &KeySchema,
+        columns: &[ColumnInfo],
+    ) -> Result<Self> {
+        if unlikely(*key_schema != KeySchema::default()) {
+            error_bail!(
+                "SimpleFeatures only supports the default key columns, key_column_ids: {:?}",
+                key_schema.col_ids()
+            );
+        }
+
+        let mut columns = columns
+            .iter()
+            .filter(|column| {
+                !column.deprecated
+                    && !SampleKey::get_sample_key_names().contains(&column.column_name.as_str())
+            })
+            .collect::<Vec<_>>();
+
+        columns.sort_by_key(|column| column.column_index);
+
+        let mut sparse_feature_names = Vec::new();
+        let mut dense_feature_names = Vec::new();
+
+        for column in columns {
+            if DataType::from_column_type(column.column_type)?.is_f32_cell() {
+                dense_feature_names.push(column.column_name.clone());
+            } else {
+                sparse_feature_names.push(column.column_name.clone());
+            }
+        }
+
+        Self::new(id_mapping, &sparse_feature_names, &dense_feature_names)
     }
 
-    /// Convert `SimpleFeatures` to `GridBuffer`.
+    /// Column ids of features, not including `SampleKey` columns.
+    pub fn col_ids(&self) -> &Vec<u32> {
+        &self.col_ids
+    }
+
+    /// Convert a batch of `SimpleFeatures` to `GridSample`, one row for each `SimpleFeatures`.
     ///
-    /// The `feature_names` must be provided, and they are converted to ids before creating `GridBuffer`.
-    pub fn to_gridbuffer(
-        &self,
-        simple_features: &SimpleFeatures,
-        feature_names: &Vec<String>,
-    ) -> Result<GridBuffer> {
-        let id_mapping = self.id_mapping.read().unwrap();
-        let ids = id_mapping.get_ids(feature_names)?;
+    /// The rows are sorted by `SampleKey`. Features with `index` out of range are errors.
+    pub fn to_grid_sample(&self, features: &[SimpleFeatures]) -> Result<GridSample> {
+        let num_key_cols = SampleKey::get_sample_key_ids().len();
+        let dense_count = self.col_ids.len() - self.sparse_count;
+
+        let mut grid_sample = GridSample::new(features.len(), &self.col_ids);
+
+        for (row, simple_features) in features.iter().enumerate() {
+            // `request_id` is `llsid` in `SimpleFeatures`.
+            let sample_key = SampleKey::new(
+                simple_features.timestamp,
+                simple_features.user_id,
+                simple_features.item_id,
+                simple_features.llsid,
+            );
+            grid_sample.set_sample_key(row, &sample_key);
+
+            for sparse_feature in simple_features.sparse_feature.iter() {
+                let index = sparse_feature.index as usize;
+
+                if unlikely(index >= self.sparse_count) {
+                    error_bail!(
+                        "Sparse feature index out of range, index: {}, sparse_count: {}",
+                        index,
+                        self.sparse_count
+                    );
+                }
+
+                grid_sample.push_u64_values(row, num_key_cols + index, &sparse_feature.values);
+            }
+
+            for dense_feature in simple_features.dense_feature.iter() {
+                let index = dense_feature.index as usize;
+
+                if unlikely(index >= dense_count) {
+                    error_bail!(
+                        "Dense feature index out of range, index: {}, dense_count: {}",
+                        index,
+                        dense_count
+                    );
+                }
+
+                grid_sample.push_f32_values(
+                    row,
+                    num_key_cols + self.sparse_count + index,
+                    &dense_feature.values,
+                );
+            }
+        }
+
+        grid_sample.sort_rows_by_sample_key();
+
+        Ok(grid_sample)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::sync::Arc;
+
+    use crate::db::memory_store::MemoryMetaStore;
+    use crate::droplet;
+    use crate::local_file_reader::LocalFileReader;
+    use crate::tool::setup_log;
+
+    #[test]
+    fn test_simple_features_to_grid_sample() -> Result<()> {
+        setup_log();
+
+        let filenames = vec!["resources/simple_features_nohash_96.txt".to_string()];
+        let features = LocalFileReader::new(&filenames)?
+            .take(8)
+            .map(|line| parse_simple_features(&line?))
+            .collect::<Result<Vec<_>>>()?;
+
+        let sparse_count = 76;
+        let dense_count = 5;
+
+        let columns = (0..sparse_count + dense_count)
+            .map(|i| ColumnInfo {
+                column_name: if i < sparse_count {
+                    format!("ExtractSparse{}", i)
+                } else {
+                    format!("ExtractDense{}", i)
+                },
+                column_type: if i < sparse_count {
+                    droplet::DataType::Ui64Array.into()
+                } else {
+                    droplet::DataType::F32Array.into()
+                },
+                column_index: i as u32,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let id_mapping = IDMapping::with_store(Arc::new(MemoryMetaStore::new()));
+        let converter =
+            SimpleFeaturesConverter::from_columns(&id_mapping, &KeySchema::default(), &columns)?;
+
+        assert_eq!(converter.col_ids().len(), sparse_count + dense_count);
+        assert_eq!(
+            converter.col_ids()[0],
+            id_mapping.get_id(&"ExtractSparse0".to_string())?
+        );
+
+        let grid_sample = converter.to_grid_sample(&features)?;
+        let gridbuffer = &grid_sample.gridbuffer;
+
+        assert_eq!(gridbuffer.num_rows(), features.len());
+        assert!(SampleKey::is_valid_gridbuffer(gridbuffer));
+
+        // Feature ids never collide with the fixed ids of the key columns.
+        let unique_col_ids = gridbuffer.col_ids().iter().collect::<HashSet<_>>();
+        assert_eq!(unique_col_ids.len(), gridbuffer.col_ids().len());
+
+        for row in 1..gridbuffer.num_rows() {
+            assert!(grid_sample.get_sample_key(row - 1) <= grid_sample.get_sample_key(row));
+        }
+
+        // Every row could be found by its `SampleKey`, with the same values.
+        for simple_features in features.iter() {
+            let row = (0..gridbuffer.num_rows())
+                .find(|row| {
+                    grid_sample.get_request_id(*row) == simple_features.llsid
+                        && grid_sample.get_item_id(*row) == simple_features.item_id
+                })
+                .unwrap();
+
+            assert_eq!(grid_sample.get_timestamp(row), simple_features.timestamp);
+            assert_eq!(grid_sample.get_user_id(row), simple_features.user_id);
+
+            for sparse_feature in simple_features.sparse_feature.iter() {
+                assert_eq!(
+                    gridbuffer.get_u64_values(row, 4 + sparse_feature.index as usize),
+                    sparse_feature.values.as_slice()
+                );
+            }
+
+            for dense_feature in simple_features.dense_feature.iter() {
+                assert_eq!(
+                    gridbuffer.get_f32_values(row, 4 + sparse_count + dense_feature.index as usize),
+                    dense_feature.values.as_slice()
+                );
+            }
+        }
 
-        let sample_key_ids = SampleKey::get_sample_key_ids();
+        // Key columns other than the default could not be filled from `SimpleFeatures`.
+        let key_schema = KeySchema::new(vec![2, 100])?;
+        assert!(SimpleFeaturesConverter::from_columns(&id_mapping, &key_schema, &columns).is_err());
 
-        let gridbuffer = GridBuffer::from_simple_features(sample_key_ids, &ids, simple_features);
+        let small_converter = SimpleFeaturesConverter::new(
+            &id_mapping,
+            &vec!["ExtractSparse0".to_string()],
+            &vec![],
+        )?;
+        assert!(small_converter.to_grid_sample(&features).is_err());
 
-        Ok(gridbuffer)
+        Ok(())
     }
 }
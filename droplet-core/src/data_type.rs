@@ -0,0 +1,386 @@
+//! Data types of columns.
+//!
+//! `DataType` is the canonical type of a column in droplet, the protobuf `DataType` in
+//! `meta.proto` is used over the wire and in `column_info`, they are converted into each other
+//! without loss.
+//!
+//! `GridBuffer` only has `u64` and `f32` cells, so other types are encoded into them:
+//! 1. `F32` is stored in `f32` cells.
+//! 2. `U64`, `U32` are stored in `u64` cells directly.
+//! 3. `I64`, `I32` are stored in `u64` cells by two's complement, see `encode_i64`.
+//! 4. `F64` is stored in `u64` cells by `f64::to_bits`.
+//! 5. `Bool` is stored in `u64` cells as `0` or `1`.
+//! 6. `String` is stored in `u64` cells as the byte length followed by the bytes packed in
+//!    little endian, eight bytes per value, see `encode_strings`.
+//!
+//! Lists are stored as the values of the same cell.
+use anyhow::{bail, Result};
+use log::error;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumCount, EnumDiscriminants, EnumString, FromRepr};
+
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use crate::droplet;
+use crate::error_bail;
+
+/// Data type.
+///
+/// The discriminants are saved in `feature_info`, so new types must be appended.
+#[derive(
+    Default,
+    Clone,
+    Copy,
+    FromRepr,
+    Debug,
+    PartialEq,
+    Eq,
+    EnumCount,
+    EnumDiscriminants,
+    EnumString,
+    Deserialize,
+    Serialize,
+    Display,
+)]
+#[repr(u8)]
+pub enum DataType {
+    /// `u64`
+    #[default]
+    U64 = 0,
+
+    /// `f32`
+    F32 = 1,
+
+    /// `u64` list
+    U64List = 2,
+
+    /// `f32` list
+    F32List = 3,
+
+    /// `i32`
+    I32 = 4,
+
+    /// `i64`
+    I64 = 5,
+
+    /// `u32`
+    U32 = 6,
+
+    /// `f64`
+    F64 = 7,
+
+    /// `String`
+    String = 8,
+
+    /// `bool`
+    Bool = 9,
+
+    /// `i32` list
+    I32List = 10,
+
+    /// `i64` list
+    I64List = 11,
+
+    /// `u32` list
+    U32List = 12,
+
+    /// `f64` list
+    F64List = 13,
+
+    /// `String` list
+    StringList = 14,
+
+    /// `bool` list
+    BoolList = 15,
+}
+
+impl DataType {
+    /// Convert from `column_type` of `ColumnInfo`, `None` and unknown values are errors.
+    pub fn from_column_type(column_type: i32) -> Result<Self> {
+        match droplet::DataType::try_from(column_type) {
+            Ok(data_type) => Self::try_from(data_type),
+            Err(_) => {
+                error_bail!("Unknown column type: {}", column_type);
+            }
+        }
+    }
+
+    /// `column_type` of `ColumnInfo`.
+    #[inline]
+    pub fn to_column_type(self) -> i32 {
+        droplet::DataType::from(self).into()
+    }
+
+    #[inline]
+    pub fn is_list(self) -> bool {
+        self != self.element_type()
+    }
+
+    /// Type of the elements of a list, the type itself if it's not a list.
+    pub fn element_type(self) -> Self {
+        match self {
+            DataType::U64List => DataType::U64,
+            DataType::F32List => DataType::F32,
+            DataType::I32List => DataType::I32,
+            DataType::I64List => DataType::I64,
+            DataType::U32List => DataType::U32,
+            DataType::F64List => DataType::F64,
+            DataType::StringList => DataType::String,
+            DataType::BoolList => DataType::Bool,
+            _ => self,
+        }
+    }
+
+    /// Whether the values are stored in `f32` cells, otherwise in `u64` cells.
+    #[inline]
+    pub fn is_f32_cell(self) -> bool {
+        matches!(self, DataType::F32 | DataType::F32List)
+    }
+}
+
+impl From<DataType> for droplet::DataType {
+    fn from(data_type: DataType) -> Self {
+        match data_type {
+            DataType::U64 => droplet::DataType::Ui64,
+            DataType::F32 => droplet::DataType::F32,
+            DataType::U64List => droplet::DataType::Ui64Array,
+            DataType::F32List => droplet::DataType::F32Array,
+            DataType::I32 => droplet::DataType::I32,
+            DataType::I64 => droplet::DataType::I64,
+            DataType::U32 => droplet::DataType::Ui32,
+            DataType::F64 => droplet::DataType::F64,
+            DataType::String => droplet::DataType::String,
+            DataType::Bool => droplet::DataType::Bool,
+            DataType::I32List => droplet::DataType::I32Array,
+            DataType::I64List => droplet::DataType::I64Array,
+            DataType::U32List => droplet::DataType::Ui32Array,
+            DataType::F64List => droplet::DataType::F64Array,
+            DataType::StringList => droplet::DataType::StringArray,
+            DataType::BoolList => droplet::DataType::BoolArray,
+        }
+    }
+}
+
+impl TryFrom<droplet::DataType> for DataType {
+    type Error = anyhow::Error;
+
+    fn try_from(data_type: droplet::DataType) -> Result<Self> {
+        Ok(match data_type {
+            droplet::DataType::None => {
+                error_bail!("Invalid data type: None");
+            }
+            droplet::DataType::Ui64 => DataType::U64,
+            droplet::DataType::F32 => DataType::F32,
+            droplet::DataType::Ui64Array => DataType::U64List,
+            droplet::DataType::F32Array => DataType::F32List,
+            droplet::DataType::I32 => DataType::I32,
+            droplet::DataType::I64 => DataType::I64,
+            droplet::DataType::Ui32 => DataType::U32,
+            droplet::DataType::F64 => DataType::F64,
+            droplet::DataType::String => DataType::String,
+            droplet::DataType::Bool => DataType::Bool,
+            droplet::DataType::I32Array => DataType::I32List,
+            droplet::DataType::I64Array => DataType::I64List,
+            droplet::DataType::Ui32Array => DataType::U32List,
+            droplet::DataType::F64Array => DataType::F64List,
+            droplet::DataType::StringArray => DataType::StringList,
+            droplet::DataType::BoolArray => DataType::BoolList,
+        })
+    }
+}
+
+#[inline]
+pub fn encode_i64(value: i64) -> u64 {
+    value as u64
+}
+
+#[inline]
+pub fn decode_i64(value: u64) -> i64 {
+    value as i64
+}
+
+#[inline]
+pub fn encode_f64(value: f64) -> u64 {
+    value.to_bits()
+}
+
+#[inline]
+pub fn decode_f64(value: u64) -> f64 {
+    f64::from_bits(value)
+}
+
+#[inline]
+pub fn encode_bool(value: bool) -> u64 {
+    value as u64
+}
+
+#[inline]
+pub fn decode_bool(value: u64) -> bool {
+    value != 0
+}
+
+/// Encode strings into `u64` values.
+///
+/// Each string is the byte length followed by `ceil(len / 8)` values of the bytes in little
+/// endian, the last value is padded with `0`.
+pub fn encode_strings<S: AsRef<str>>(values: &[S]) -> Vec<u64> {
+    let mut res = Vec::new();
+
+    for value in values {
+        let bytes = value.as_ref().as_bytes();
+        res.push(bytes.len() as u64);
+
+        for chunk in bytes.chunks(8) {
+            let mut buf = [0u8; 8];
+            buf[..chunk.len()].copy_from_slice(chunk);
+            res.push(u64::from_le_bytes(buf));
+        }
+    }
+
+    res
+}
+
+/// Decode strings from the `u64` values of `encode_strings`.
+pub fn decode_strings(values: &[u64]) -> Result<Vec<String>> {
+    let mut res = Vec::new();
+    let mut pos = 0;
+
+    while pos < values.len() {
+        let len = values[pos] as usize;
+        let num_values = len.div_ceil(8);
+        pos += 1;
+
+        if pos + num_values > values.len() {
+            error_bail!(
+                "Invalid encoded string, len: {}, values left: {}",
+                len,
+                values.len() - pos
+            );
+        }
+
+        let mut bytes = Vec::with_capacity(num_values * 8);
+        for value in values[pos..pos + num_values].iter() {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        bytes.truncate(len);
+
+        res.push(String::from_utf8(bytes)?);
+        pos += num_values;
+    }
+
+    Ok(res)
+}
+
+/// Get the `i64` value of the cell, `None` if the cell is null.
+#[inline]
+pub fn get_i64(gridbuffer: &GridBuffer, row: usize, col: usize) -> Option<i64> {
+    gridbuffer.get_u64(row, col).map(decode_i64)
+}
+
+#[inline]
+pub fn get_i64_values(gridbuffer: &GridBuffer, row: usize, col: usize) -> Vec<i64> {
+    gridbuffer
+        .get_u64_values(row, col)
+        .iter()
+        .map(|x| decode_i64(*x))
+        .collect()
+}
+
+/// Get the `f64` value of the cell, `None` if the cell is null.
+#[inline]
+pub fn get_f64(gridbuffer: &GridBuffer, row: usize, col: usize) -> Option<f64> {
+    gridbuffer.get_u64(row, col).map(decode_f64)
+}
+
+#[inline]
+pub fn get_f64_values(gridbuffer: &GridBuffer, row: usize, col: usize) -> Vec<f64> {
+    gridbuffer
+        .get_u64_values(row, col)
+        .iter()
+        .map(|x| decode_f64(*x))
+        .collect()
+}
+
+/// Get the `bool` value of the cell, `None` if the cell is null.
+#[inline]
+pub fn get_bool(gridbuffer: &GridBuffer, row: usize, col: usize) -> Option<bool> {
+    gridbuffer.get_u64(row, col).map(decode_bool)
+}
+
+#[inline]
+pub fn get_bool_values(gridbuffer: &GridBuffer, row: usize, col: usize) -> Vec<bool> {
+    gridbuffer
+        .get_u64_values(row, col)
+        .iter()
+        .map(|x| decode_bool(*x))
+        .collect()
+}
+
+/// Get the string value of the cell, `None` if the cell is null or not a valid string.
+pub fn get_string(gridbuffer: &GridBuffer, row: usize, col: usize) -> Option<String> {
+    match decode_strings(gridbuffer.get_u64_values(row, col)) {
+        Ok(values) => values.into_iter().next(),
+        Err(e) => {
+            error!(
+                "Failed to decode string, row: {}, col: {}, error: {}",
+                row, col, e
+            );
+            None
+        }
+    }
+}
+
+pub fn get_string_values(gridbuffer: &GridBuffer, row: usize, col: usize) -> Result<Vec<String>> {
+    decode_strings(gridbuffer.get_u64_values(row, col))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_type_conversion() -> Result<()> {
+        for i in 0..DataType::COUNT {
+            let data_type = DataType::from_repr(i as u8).unwrap();
+
+            assert_eq!(
+                DataType::from_column_type(data_type.to_column_type())?,
+                data_type
+            );
+            assert!(!data_type.element_type().is_list());
+        }
+
+        assert!(DataType::from_column_type(droplet::DataType::None.into()).is_err());
+        assert!(DataType::from_column_type(100).is_err());
+
+        assert_eq!(DataType::I64List.element_type(), DataType::I64);
+        assert!(DataType::F32List.is_f32_cell());
+        assert!(!DataType::F64.is_f32_cell());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_values() -> Result<()> {
+        for value in [0, -1, i64::MIN, i64::MAX] {
+            assert_eq!(decode_i64(encode_i64(value)), value);
+        }
+
+        for value in [0.0, -1.5, f64::MAX, f64::MIN_POSITIVE] {
+            assert_eq!(decode_f64(encode_f64(value)), value);
+        }
+
+        assert!(decode_bool(encode_bool(true)));
+        assert!(!decode_bool(encode_bool(false)));
+
+        let strings = ["", "droplet", "exactly8", "多字节字符串"];
+        let encoded = encode_strings(&strings);
+
+        // Four lengths, and 0, 1, 1, 3 values of bytes.
+        assert_eq!(encoded.len(), 9);
+        assert_eq!(decode_strings(&encoded)?, strings);
+        assert!(decode_strings(&encoded[..encoded.len() - 1]).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1368 @@
+//! `MetaStore` in memory.
+use anyhow::{bail, Result};
+use log::error;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+use crate::data_type::DataType;
+use crate::db::meta_store::{
+    check_column_types, check_key_columns, check_partition_scheme, get_default_key_columns,
+    get_heartbeat_status, get_partitions_stats, MetaStore, DEFAULT_MIN_FREE_DISK_SIZE,
+    INITIAL_SCHEMA_VERSION,
+};
+use crate::droplet::{
+    ColumnInfo, NodeInfo, NodeStatus, PartitionInfo, PartitionStatus, TableStats,
+};
+use crate::error_bail;
+use crate::feature_info::FeatureInfo;
+use crate::grid_sample::SampleKey;
+use crate::partition::{get_sorted_partition_path, PartitionScheme};
+use crate::tool::get_timestamp_secs;
+
+/// Node with the latest reported disk usage and heartbeat time.
+///
+/// `info.total_disk_size` is `0` if storage info is not reported yet, and the node is not used
+/// for partitions. `partition_count` is counted when the node info is got.
+struct NodeState {
+    info: NodeInfo,
+
+    /// Timestamp in seconds, used as the last heartbeat if no heartbeat yet.
+    registered_at: u64,
+}
+
+impl NodeState {
+    fn get_last_heartbeat(&self) -> u64 {
+        if self.info.last_heartbeat > 0 {
+            self.info.last_heartbeat
+        } else {
+            self.registered_at
+        }
+    }
+}
+
+struct TableState {
+    partition_scheme: PartitionScheme,
+
+    /// Increased by each change of columns.
+    schema_version: u32,
+
+    /// Names of the key columns, never empty.
+    key_columns: Vec<String>,
+
+    columns: Vec<ColumnInfo>,
+}
+
+/// All meta info, same as the tables in `mysql`.
+struct MetaState {
+    /// Key string to key id, the fixed ids of the sample keys are inserted at first, new ids
+    /// start from the max id plus `1`, same as `AUTO_INCREMENT`.
+    key_ids: HashMap<String, u32>,
+
+    /// Id of the next inserted key.
+    next_key_id: u32,
+
+    /// Feature name to data type.
+    feature_infos: HashMap<String, DataType>,
+
+    /// Node id is `index + 1`.
+    nodes: Vec<NodeState>,
+
+    tables: HashMap<String, TableState>,
+
+    /// Active partitions ordered by table, partition date and partition index.
+    partitions: BTreeMap<(String, u32, u32), PartitionInfo>,
+
+    /// Partitions of dropped tables, status is `ToDelete`.
+    to_delete_partitions: Vec<PartitionInfo>,
+}
+
+impl Default for MetaState {
+    /// The ids of the sample keys are fixed, see `SampleKey::get_sample_key_pair`, so they are
+    /// reserved and never given to other keys.
+    fn default() -> Self {
+        let key_ids = SampleKey::get_sample_key_pair()
+            .iter()
+            .map(|(name, id)| (name.to_string(), *id))
+            .collect::<HashMap<_, _>>();
+
+        let next_key_id = key_ids.values().max().copied().unwrap_or(0) + 1;
+
+        Self {
+            key_ids,
+            next_key_id,
+            feature_infos: HashMap::new(),
+            nodes: Vec::new(),
+            tables: HashMap::new(),
+            partitions: BTreeMap::new(),
+            to_delete_partitions: Vec::new(),
+        }
+    }
+}
+
+impl MetaState {
+    fn get_or_insert_key_id(&mut self, key_str: &str) -> u32 {
+        if let Some(key_id) = self.key_ids.get(key_str) {
+            return *key_id;
+        }
+
+        let key_id = self.next_key_id;
+        self.key_ids.insert(key_str.to_string(), key_id);
+        self.next_key_id += 1;
+
+        key_id
+    }
+
+    fn get_table(&self, table_name: &str) -> Result<&TableState> {
+        match self.tables.get(table_name) {
+            Some(table) => Ok(table),
+            None => {
+                error_bail!("Table not found, table_name: {}", table_name);
+            }
+        }
+    }
+
+    /// Add or deprecate columns, return the schema version after the change.
+    ///
+    /// The schema version is increased only if any column is added, added back or deprecated.
+    fn alter_table(
+        &mut self,
+        table_name: &str,
+        add_columns: &[ColumnInfo],
+        deprecate_columns: &[String],
+    ) -> Result<u32> {
+        let existing_columns = &self.get_table(table_name)?.columns;
+        check_column_types(existing_columns, add_columns)?;
+
+        for column_name in deprecate_columns {
+            if !existing_columns
+                .iter()
+                .any(|x| &x.column_name == column_name)
+            {
+                error_bail!(
+                    "Column not found, table_name: {}, column_name: {}",
+                    table_name,
+                    column_name
+                );
+            }
+        }
+
+        let column_ids = add_columns
+            .iter()
+            .map(|column| self.get_or_insert_key_id(column.column_name.as_str()))
+            .collect::<Vec<_>>();
+
+        let table = match self.tables.get_mut(table_name) {
+            Some(table) => table,
+            None => {
+                error_bail!("Table not found, table_name: {}", table_name);
+            }
+        };
+
+        let schema_version = table.schema_version + 1;
+        let mut is_changed = false;
+
+        for (column, column_id) in add_columns.iter().zip(column_ids) {
+            match table
+                .columns
+                .iter_mut()
+                .find(|x| x.column_name == column.column_name)
+            {
+                Some(existing) => {
+                    is_changed |= existing.deprecated;
+                    existing.deprecated = false;
+                }
+                None => {
+                    let column_index = table
+                        .columns
+                        .iter()
+                        .map(|x| x.column_index + 1)
+                        .max()
+                        .unwrap_or(0);
+
+                    table.columns.push(ColumnInfo {
+                        column_id,
+                        column_index,
+                        deprecated: false,
+                        schema_version,
+                        ..column.clone()
+                    });
+
+                    is_changed = true;
+                }
+            }
+        }
+
+        for column in table.columns.iter_mut() {
+            if deprecate_columns.contains(&column.column_name) {
+                is_changed |= !column.deprecated;
+                column.deprecated = true;
+            }
+        }
+
+        if is_changed {
+            table.schema_version = schema_version;
+        }
+
+        Ok(table.schema_version)
+    }
+
+    /// Node info with the partition count.
+    fn get_node_info(&self, node: &NodeState) -> NodeInfo {
+        let partition_count = self
+            .partitions
+            .values()
+            .filter(|p| p.node_id == node.info.node_id)
+            .count();
+
+        NodeInfo {
+            partition_count: partition_count as u32,
+            ..node.info.clone()
+        }
+    }
+
+    /// Select the alive node with the least disk usage ratio, and free disk size no less than
+    /// `min_free_disk_size`.
+    fn get_available_node(&self, min_free_disk_size: u64) -> Result<NodeInfo> {
+        let node = self
+            .nodes
+            .iter()
+            .filter(|node| {
+                node.info.status == NodeStatus::Alive as i32
+                    && node.info.total_disk_size > 0
+                    && node.info.total_disk_size
+                        >= node.info.used_disk_size.saturating_add(min_free_disk_size)
+            })
+            .min_by(|a, b| {
+                let ratio_a = a.info.used_disk_size as f64 / a.info.total_disk_size as f64;
+                let ratio_b = b.info.used_disk_size as f64 / b.info.total_disk_size as f64;
+                ratio_a.total_cmp(&ratio_b)
+            });
+
+        match node {
+            Some(node) => Ok(node.info.clone()),
+            None => {
+                error_bail!("No available node");
+            }
+        }
+    }
+}
+
+/// `MemoryMetaStore` keeps all meta info in process, nothing is persisted.
+///
+/// It has the same behavior as `MysqlMetaStore`, so the meta server and tests can run without
+/// `mysql`.
+pub struct MemoryMetaStore {
+    state: Mutex<MetaState>,
+
+    /// Nodes with less free disk size in bytes are not used for new partitions.
+    min_free_disk_size: AtomicU64,
+}
+
+impl Default for MemoryMetaStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MemoryMetaStore {
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MetaState::default()),
+            min_free_disk_size: AtomicU64::new(DEFAULT_MIN_FREE_DISK_SIZE),
+        }
+    }
+
+    pub fn with_min_free_disk_size(self, min_free_disk_size: u64) -> Self {
+        self.set_min_free_disk_size(min_free_disk_size);
+        self
+    }
+
+    pub fn set_min_free_disk_size(&self, min_free_disk_size: u64) {
+        self.min_free_disk_size
+            .store(min_free_disk_size, Ordering::Relaxed);
+    }
+
+    /// The store shared by the whole process.
+    pub fn global() -> Arc<MemoryMetaStore> {
+        static GLOBAL: OnceLock<Arc<MemoryMetaStore>> = OnceLock::new();
+
+        GLOBAL.get_or_init(|| Arc::new(Self::new())).clone()
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, MetaState>> {
+        match self.state.lock() {
+            Ok(state) => Ok(state),
+            Err(e) => {
+                error_bail!("Failed to lock memory meta store, error: {}", e);
+            }
+        }
+    }
+}
+
+impl MetaStore for MemoryMetaStore {
+    fn get_key_id(&self, key_str: &str) -> Result<Option<u32>> {
+        Ok(self.lock()?.key_ids.get(key_str).copied())
+    }
+
+    fn get_or_insert_key_id(&self, key_str: &str) -> Result<u32> {
+        Ok(self.lock()?.get_or_insert_key_id(key_str))
+    }
+
+    fn update_feature_infos(&self, feature_infos: &[FeatureInfo]) -> Result<()> {
+        let mut state = self.lock()?;
+
+        for feature_info in feature_infos {
+            if let Some(data_type) = state.feature_infos.get(&feature_info.feature_name) {
+                if *data_type != feature_info.data_type {
+                    error_bail!(
+                        "data type mismatch for feature: {}, data type in store: {}, data type in config: {}",
+                        feature_info.feature_name,
+                        data_type,
+                        feature_info.data_type
+                    );
+                }
+            }
+        }
+
+        for feature_info in feature_infos {
+            state
+                .feature_infos
+                .insert(feature_info.feature_name.clone(), feature_info.data_type);
+            state.get_or_insert_key_id(feature_info.feature_name.as_str());
+        }
+
+        Ok(())
+    }
+
+    fn register_node(&self, node_name: &str, node_ip: &str, node_port: u32) -> Result<u32> {
+        let mut state = self.lock()?;
+
+        if let Some(node) = state
+            .nodes
+            .iter()
+            .find(|node| node.info.node_name == node_name)
+        {
+            return Ok(node.info.node_id);
+        }
+
+        let node_id = state.nodes.len() as u32 + 1;
+
+        state.nodes.push(NodeState {
+            info: NodeInfo {
+                node_id,
+                node_name: node_name.to_string(),
+                node_ip: node_ip.to_string(),
+                node_port,
+                status: NodeStatus::Alive.into(),
+                ..Default::default()
+            },
+            registered_at: get_timestamp_secs(),
+        });
+
+        Ok(node_id)
+    }
+
+    fn get_worker_node_id(&self, node_name: &str) -> Result<u32> {
+        let state = self.lock()?;
+
+        match state
+            .nodes
+            .iter()
+            .find(|node| node.info.node_name == node_name)
+        {
+            Some(node) => Ok(node.info.node_id),
+            None => bail!("Node not found, node_name: {}", node_name),
+        }
+    }
+
+    fn get_worker_node_info(&self, node_id: u32) -> Result<NodeInfo> {
+        let state = self.lock()?;
+
+        match state.nodes.get((node_id as usize).wrapping_sub(1)) {
+            Some(node) => Ok(state.get_node_info(node)),
+            None => {
+                error_bail!("Node not found, node_id: {}", node_id);
+            }
+        }
+    }
+
+    fn list_nodes(&self, statuses: &[NodeStatus]) -> Result<Vec<NodeInfo>> {
+        let state = self.lock()?;
+
+        Ok(state
+            .nodes
+            .iter()
+            .filter(|node| {
+                statuses.is_empty()
+                    || statuses
+                        .iter()
+                        .any(|status| *status as i32 == node.info.status)
+            })
+            .map(|node| state.get_node_info(node))
+            .collect())
+    }
+
+    fn update_storage_info(
+        &self,
+        node_id: u32,
+        used_disk_size: u64,
+        total_disk_size: u64,
+    ) -> Result<()> {
+        let mut state = self.lock()?;
+
+        match state.nodes.get_mut((node_id as usize).wrapping_sub(1)) {
+            Some(node) => {
+                node.info.used_disk_size = used_disk_size;
+                node.info.total_disk_size = total_disk_size;
+                Ok(())
+            }
+            None => {
+                error_bail!("Node not found, node_id: {}", node_id);
+            }
+        }
+    }
+
+    fn update_node_heartbeat(
+        &self,
+        node_id: u32,
+        status: NodeStatus,
+        timestamp: u64,
+    ) -> Result<()> {
+        let mut state = self.lock()?;
+
+        match state.nodes.get_mut((node_id as usize).wrapping_sub(1)) {
+            Some(node) => {
+                node.info.status = get_heartbeat_status(status).into();
+                node.info.last_heartbeat = timestamp;
+                Ok(())
+            }
+            None => {
+                error_bail!("Node not found, node_id: {}", node_id);
+            }
+        }
+    }
+
+    fn update_node_status(
+        &self,
+        timestamp: u64,
+        unhealthy_timeout: u64,
+        offline_timeout: u64,
+    ) -> Result<()> {
+        let mut state = self.lock()?;
+
+        for node in state.nodes.iter_mut() {
+            let elapsed = timestamp.saturating_sub(node.get_last_heartbeat());
+
+            if elapsed > offline_timeout {
+                node.info.status = NodeStatus::Offline.into();
+            } else if elapsed > unhealthy_timeout && node.info.status == NodeStatus::Alive as i32 {
+                node.info.status = NodeStatus::Unhealthy.into();
+            }
+        }
+
+        Ok(())
+    }
+
+    fn insert_table_info(
+        &self,
+        table_name: &str,
+        partition_scheme: &PartitionScheme,
+        key_columns: &[String],
+        columns: &[ColumnInfo],
+    ) -> Result<()> {
+        check_key_columns(key_columns)?;
+
+        let mut state = self.lock()?;
+
+        check_partition_scheme(
+            table_name,
+            state
+                .tables
+                .get(table_name)
+                .map(|table| &table.partition_scheme),
+            partition_scheme,
+        )?;
+
+        // New columns of existing table are added as `alter_table`, with a new schema version.
+        if let Some(table) = state.tables.get(table_name) {
+            if !key_columns.is_empty() && key_columns != table.key_columns.as_slice() {
+                error_bail!(
+                    "Key columns cannot be changed, table_name: {}, key_columns: {:?}, new key_columns: {:?}",
+                    table_name,
+                    table.key_columns,
+                    key_columns
+                );
+            }
+
+            check_column_types(&table.columns, columns)?;
+
+            let new_columns = columns
+                .iter()
+                .filter(|column| {
+                    !table
+                        .columns
+                        .iter()
+                        .any(|x| x.column_name == column.column_name)
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+
+            if !new_columns.is_empty() {
+                state.alter_table(table_name, &new_columns, &[])?;
+            }
+
+            return Ok(());
+        }
+
+        check_column_types(&[], columns)?;
+
+        // Ids of the key columns are got by `get_key_schema` without inserting.
+        for name in key_columns {
+            state.get_or_insert_key_id(name.as_str());
+        }
+
+        // Column ids are assigned by `id_mapping`, same as `mysql`.
+        let mut table = TableState {
+            partition_scheme: *partition_scheme,
+            schema_version: INITIAL_SCHEMA_VERSION,
+            key_columns: if key_columns.is_empty() {
+                get_default_key_columns()
+            } else {
+                key_columns.to_vec()
+            },
+            columns: Vec::with_capacity(columns.len()),
+        };
+
+        for column in columns {
+            if !table
+                .columns
+                .iter()
+                .any(|x| x.column_name == column.column_name)
+            {
+                table.columns.push(ColumnInfo {
+                    column_id: state.get_or_insert_key_id(column.column_name.as_str()),
+                    schema_version: INITIAL_SCHEMA_VERSION,
+                    ..column.clone()
+                });
+            }
+        }
+
+        state.tables.insert(table_name.to_string(), table);
+
+        Ok(())
+    }
+
+    fn is_table_exist(&self, table_name: &str) -> Result<bool> {
+        Ok(self.lock()?.tables.contains_key(table_name))
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>> {
+        let mut table_names = self.lock()?.tables.keys().cloned().collect::<Vec<_>>();
+        table_names.sort();
+
+        Ok(table_names)
+    }
+
+    fn alter_table(
+        &self,
+        table_name: &str,
+        add_columns: &[ColumnInfo],
+        deprecate_columns: &[String],
+    ) -> Result<u32> {
+        self.lock()?
+            .alter_table(table_name, add_columns, deprecate_columns)
+    }
+
+    fn get_schema_version(&self, table_name: &str) -> Result<u32> {
+        Ok(self.lock()?.get_table(table_name)?.schema_version)
+    }
+
+    fn get_key_columns(&self, table_name: &str) -> Result<Vec<String>> {
+        Ok(self.lock()?.get_table(table_name)?.key_columns.clone())
+    }
+
+    fn drop_table(&self, table_name: &str) -> Result<u32> {
+        let mut state = self.lock()?;
+
+        if state.tables.remove(table_name).is_none() {
+            error_bail!("Table not found, table_name: {}", table_name);
+        }
+
+        let keys = state
+            .partitions
+            .keys()
+            .filter(|(table, _, _)| table == table_name)
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for key in keys.iter() {
+            if let Some(mut partition) = state.partitions.remove(key) {
+                partition.status = PartitionStatus::ToDelete.into();
+                state.to_delete_partitions.push(partition);
+            }
+        }
+
+        Ok(keys.len() as u32)
+    }
+
+    fn get_table_stats(&self, table_name: &str) -> Result<TableStats> {
+        let state = self.lock()?;
+        state.get_table(table_name)?;
+
+        Ok(get_partitions_stats(
+            state
+                .partitions
+                .iter()
+                .filter(|((table, _, _), _)| table == table_name)
+                .map(|(_, p)| p),
+        ))
+    }
+
+    fn update_partition_stats(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+        row_count: u64,
+        byte_size: u64,
+    ) -> Result<()> {
+        let mut state = self.lock()?;
+
+        let key = (table_name.to_string(), partition_date, partition_index);
+
+        if let Some(partition) = state.partitions.get_mut(&key) {
+            partition.row_count = row_count;
+            partition.byte_size = byte_size;
+        }
+
+        Ok(())
+    }
+
+    fn get_partition_count_per_day(&self, table_name: &str) -> Result<u32> {
+        Ok(self
+            .lock()?
+            .get_table(table_name)?
+            .partition_scheme
+            .partition_count_per_day)
+    }
+
+    fn get_partition_scheme(&self, table_name: &str) -> Result<PartitionScheme> {
+        Ok(self.lock()?.get_table(table_name)?.partition_scheme)
+    }
+
+    fn get_table_column_infos(&self, table_name: &str) -> Result<Vec<ColumnInfo>> {
+        Ok(self.lock()?.get_table(table_name)?.columns.clone())
+    }
+
+    fn get_partition_infos(&self, table_name: &str, timestamp: u64) -> Result<Vec<PartitionInfo>> {
+        let mut state = self.lock()?;
+
+        let partition_scheme = state.get_table(table_name)?.partition_scheme;
+
+        let (partition_date, partition_index) = partition_scheme.get_partition(timestamp)?;
+
+        let key = (table_name.to_string(), partition_date, partition_index);
+
+        if let Some(partition_info) = state.partitions.get(&key) {
+            return Ok(vec![partition_info.clone()]);
+        }
+
+        let schema_version = state.get_table(table_name)?.schema_version;
+
+        let (time_start, time_end) = partition_scheme.get_time_range(timestamp)?;
+        let node = state.get_available_node(self.min_free_disk_size.load(Ordering::Relaxed))?;
+
+        let partition_info = PartitionInfo {
+            partition_id: (state.partitions.len() + state.to_delete_partitions.len()) as u32 + 1,
+            partition_date,
+            partition_index,
+            node_id: node.node_id,
+            node_name: node.node_name,
+            node_ip: node.node_ip,
+            node_port: node.node_port,
+            time_start: partition_scheme.to_millis(time_start),
+            time_end: partition_scheme.to_millis(time_end),
+            schema_version,
+            ..Default::default()
+        };
+
+        state.partitions.insert(key, partition_info.clone());
+
+        Ok(vec![partition_info])
+    }
+
+    fn get_partition_schema_version(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+    ) -> Result<Option<u32>> {
+        let key = (table_name.to_string(), partition_date, partition_index);
+
+        Ok(self
+            .lock()?
+            .partitions
+            .get(&key)
+            .map(|partition| partition.schema_version))
+    }
+
+    fn get_table_paths_by_date(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+    ) -> Result<Vec<String>> {
+        let partition_count_per_day = self.get_partition_count_per_day(table_name)?;
+
+        Ok((0..partition_count_per_day)
+            .map(|index| get_sorted_partition_path(table_name, partition_date, index))
+            .collect())
+    }
+
+    fn get_table_paths_by_time(
+        &self,
+        table_name: &str,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<Vec<String>> {
+        let state = self.lock()?;
+
+        // Partitions of dropped tables are not used any more.
+        let partition_scheme = match state.tables.get(table_name) {
+            Some(table) => table.partition_scheme,
+            None => return Ok(Vec::new()),
+        };
+
+        // `time_start` and `time_end` of partitions are in milliseconds.
+        let start_ms = partition_scheme.to_millis(start_ts);
+        let end_ms = partition_scheme.to_millis(end_ts);
+
+        let mut partitions = state
+            .partitions
+            .iter()
+            .filter(|((table, _, _), p)| {
+                table == table_name && p.time_start < end_ms && p.time_end > start_ms
+            })
+            .map(|(_, p)| p)
+            .collect::<Vec<_>>();
+
+        partitions.sort_by_key(|p| (p.time_start, p.partition_date, p.partition_index));
+
+        Ok(partitions
+            .iter()
+            .map(|p| get_sorted_partition_path(table_name, p.partition_date, p.partition_index))
+            .collect())
+    }
+
+    fn get_server_endpoint_by_partition_index(
+        &self,
+        table_name: &str,
+        partition_index: u32,
+    ) -> Result<String> {
+        let state = self.lock()?;
+
+        // The latest partition of the index.
+        let partition = state
+            .partitions
+            .iter()
+            .rev()
+            .find(|((table, _, index), _)| table == table_name && *index == partition_index);
+
+        match partition {
+            Some((_, p)) => Ok(format!("{}:{}", p.node_name, p.node_port)),
+            None => {
+                error_bail!(
+                    "Server endpoint not found for table: {}, partition_index: {}",
+                    table_name,
+                    partition_index
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::droplet::DataType;
+    use crate::grid_sample::KeySchema;
+    use crate::partition::TimestampUnit;
+
+    const GB: u64 = 1024 * 1024 * 1024;
+
+    fn insert_test_table(store: &MemoryMetaStore) -> Result<()> {
+        let columns = ["label", "ctr"]
+            .iter()
+            .enumerate()
+            .map(|(i, name)| ColumnInfo {
+                column_name: name.to_string(),
+                column_type: DataType::F32.into(),
+                column_id: 0,
+                column_index: i as u32,
+                deprecated: false,
+                schema_version: 0,
+            })
+            .collect::<Vec<_>>();
+
+        store.insert_table_info("droplet_test", &PartitionScheme::new(24), &[], &columns)
+    }
+
+    #[test]
+    fn test_key_ids() -> Result<()> {
+        let store = MemoryMetaStore::new();
+
+        // Ids of the sample keys are reserved, new ids start after them.
+        assert_eq!(store.get_key_id("timestamp")?, Some(2));
+        assert_eq!(store.get_key_id("request_id")?, Some(6));
+        assert_eq!(store.get_or_insert_key_id("item_id")?, 5);
+
+        assert_eq!(store.get_key_id("a")?, None);
+        assert_eq!(store.get_or_insert_key_id("a")?, 7);
+        assert_eq!(
+            store.get_key_ids(&["b".to_string(), "a".to_string()])?,
+            vec![8, 7]
+        );
+        assert_eq!(store.get_key_id("b")?, Some(8));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_nodes_and_tables() -> Result<()> {
+        let store = MemoryMetaStore::new();
+
+        assert_eq!(store.register_node("node_a", "127.0.0.1", 50052)?, 1);
+        assert_eq!(store.register_node("node_b", "127.0.0.2", 50052)?, 2);
+        assert_eq!(store.register_node("node_a", "127.0.0.1", 50052)?, 1);
+        assert_eq!(store.get_worker_node_id("node_b")?, 2);
+        assert!(store.get_worker_node_id("node_c").is_err());
+        assert!(store.update_storage_info(3, 0, 1000 * GB).is_err());
+
+        assert!(!store.is_table_exist("droplet_test")?);
+        insert_test_table(&store)?;
+        insert_test_table(&store)?;
+        assert!(store.is_table_exist("droplet_test")?);
+
+        assert_eq!(store.get_partition_count_per_day("droplet_test")?, 24);
+
+        let columns = store.get_table_column_infos("droplet_test")?;
+        assert_eq!(columns.len(), 2);
+        assert_eq!(columns[1].column_id, store.get_or_insert_key_id("ctr")?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partitions() -> Result<()> {
+        let store = MemoryMetaStore::new();
+
+        // No table and no node.
+        assert!(store.get_partition_infos("droplet_test", 0).is_err());
+        insert_test_table(&store)?;
+        assert!(store.get_partition_infos("droplet_test", 0).is_err());
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.register_node("node_b", "127.0.0.2", 50052)?;
+        store.update_storage_info(1, 100 * GB, 1000 * GB)?;
+        store.update_storage_info(2, 0, 1000 * GB)?;
+
+        // 2024-10-11 23:00:00 UTC.
+        let ts = 1728604800 + 3600 * 23;
+
+        let partition = store.get_partition_infos("droplet_test", ts + 10)?[0].clone();
+        assert_eq!(partition.partition_date, 20241011);
+        assert_eq!(partition.partition_index, 23);
+        assert_eq!(partition.node_name, "node_b");
+        assert_eq!(partition.time_start, ts * 1000);
+        assert_eq!(partition.time_end, (ts + 3600) * 1000);
+
+        // Same partition is returned.
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts + 20)?[0].partition_id,
+            partition.partition_id
+        );
+
+        store.get_partition_infos("droplet_test", ts + 3600)?;
+
+        assert_eq!(
+            store.get_table_paths_by_time("droplet_test", ts + 100, ts + 3700)?,
+            vec![
+                get_sorted_partition_path("droplet_test", 20241011, 23),
+                get_sorted_partition_path("droplet_test", 20241012, 0),
+            ]
+        );
+        assert_eq!(
+            store.get_table_paths_by_time("droplet_test", ts + 3600, ts + 3700)?,
+            vec![get_sorted_partition_path("droplet_test", 20241012, 0)]
+        );
+        assert!(store
+            .get_table_paths_by_time("droplet_test", ts - 100, ts)?
+            .is_empty());
+
+        assert_eq!(
+            store.get_server_endpoint_by_partition_index("droplet_test", 23)?,
+            "node_b:50052"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_scheme() -> Result<()> {
+        let store = MemoryMetaStore::new();
+
+        // Milliseconds in UTC+8.
+        let partition_scheme = PartitionScheme::new(24)
+            .with_timestamp_unit(TimestampUnit::Millisecond)
+            .with_tz_offset_seconds(8 * 3600);
+        store.insert_table_info("droplet_test", &partition_scheme, &[], &[])?;
+        assert_eq!(
+            store.get_partition_scheme("droplet_test")?,
+            partition_scheme
+        );
+
+        // Timestamp unit and timezone cannot be changed.
+        assert!(store
+            .insert_table_info("droplet_test", &PartitionScheme::new(24), &[], &[])
+            .is_err());
+        assert!(store
+            .insert_table_info(
+                "droplet_test",
+                &partition_scheme.with_tz_offset_seconds(0),
+                &[],
+                &[]
+            )
+            .is_err());
+        store.insert_table_info("droplet_test", &partition_scheme, &[], &[])?;
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.update_storage_info(1, 0, 1000 * GB)?;
+
+        // 2024-10-11 16:00:00 UTC, 2024-10-12 00:00:00 in UTC+8.
+        let ts = (1728604800 + 3600 * 16) * 1000;
+
+        let partition = store.get_partition_infos("droplet_test", ts + 10)?[0].clone();
+        assert_eq!(partition.partition_date, 20241012);
+        assert_eq!(partition.partition_index, 0);
+        assert_eq!(partition.time_start, ts);
+        assert_eq!(partition.time_end, ts + 3600 * 1000);
+
+        let partition = store.get_partition_infos("droplet_test", ts - 1)?[0].clone();
+        assert_eq!(partition.partition_date, 20241011);
+        assert_eq!(partition.partition_index, 23);
+
+        assert_eq!(
+            store.get_table_paths_by_time("droplet_test", ts - 1000, ts + 1000)?,
+            vec![
+                get_sorted_partition_path("droplet_test", 20241011, 23),
+                get_sorted_partition_path("droplet_test", 20241012, 0),
+            ]
+        );
+        assert_eq!(
+            store.get_table_paths_by_time("droplet_test", ts, ts + 1000)?,
+            vec![get_sorted_partition_path("droplet_test", 20241012, 0)]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_status() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.register_node("node_b", "127.0.0.2", 50052)?;
+        store.update_storage_info(1, 0, 1000 * GB)?;
+        store.update_storage_info(2, 100 * GB, 1000 * GB)?;
+
+        // 2024-10-11 00:00:00 UTC.
+        let ts = 1728604800;
+
+        store.update_node_heartbeat(1, NodeStatus::Healthy, ts)?;
+        store.update_node_heartbeat(2, NodeStatus::Healthy, ts + 20)?;
+        assert!(store
+            .update_node_heartbeat(3, NodeStatus::Healthy, ts)
+            .is_err());
+
+        let get_status =
+            |node_id: u32| -> Result<i32> { Ok(store.get_worker_node_info(node_id)?.status) };
+
+        assert_eq!(get_status(1)?, NodeStatus::Alive as i32);
+
+        // node_a is unhealthy, so node_b is used even with more disk usage.
+        store.update_node_status(ts + 40, 30, 300)?;
+        assert_eq!(get_status(1)?, NodeStatus::Unhealthy as i32);
+        assert_eq!(get_status(2)?, NodeStatus::Alive as i32);
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts)?[0].node_name,
+            "node_b"
+        );
+
+        store.update_node_status(ts + 400, 30, 300)?;
+        assert_eq!(get_status(1)?, NodeStatus::Offline as i32);
+        assert_eq!(get_status(2)?, NodeStatus::Offline as i32);
+        assert!(store
+            .get_partition_infos("droplet_test", ts + 3600)
+            .is_err());
+
+        // Heartbeat brings the node back, unless it reports itself unhealthy.
+        store.update_node_heartbeat(1, NodeStatus::Healthy, ts + 410)?;
+        store.update_node_heartbeat(2, NodeStatus::Unhealthy, ts + 410)?;
+        assert_eq!(get_status(1)?, NodeStatus::Alive as i32);
+        assert_eq!(get_status(2)?, NodeStatus::Unhealthy as i32);
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts + 3600)?[0].node_name,
+            "node_a"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_placement_by_disk_usage() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.register_node("node_b", "127.0.0.2", 50052)?;
+        store.register_node("node_c", "127.0.0.3", 50052)?;
+
+        // No storage info is reported.
+        assert!(store.get_partition_infos("droplet_test", 0).is_err());
+
+        // node_b has less usage ratio though more used disk size, node_c is not reported.
+        store.update_storage_info(1, 60 * GB, 100 * GB)?;
+        store.update_storage_info(2, 100 * GB, 200 * GB)?;
+
+        // 2024-10-11 00:00:00 UTC.
+        let ts = 1728604800;
+
+        let partition = store.get_partition_infos("droplet_test", ts)?[0].clone();
+        assert_eq!(partition.node_name, "node_b");
+
+        // Free disk size of node_b is less than the min free disk size.
+        store.update_storage_info(2, 195 * GB, 200 * GB)?;
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts + 3600)?[0].node_name,
+            "node_a"
+        );
+
+        // Existing partition stays on its node, so all the sinkers write to the same node.
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts + 10)?[0],
+            partition
+        );
+
+        store.set_min_free_disk_size(50 * GB);
+        assert!(store
+            .get_partition_infos("droplet_test", ts + 7200)
+            .is_err());
+        assert_eq!(store.get_partition_infos("droplet_test", ts)?[0], partition);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_infos() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.register_node("node_b", "127.0.0.2", 50052)?;
+        assert!(store.get_worker_node_info(3).is_err());
+
+        // 2024-10-11 00:00:00 UTC.
+        let ts = 1728604800;
+
+        store.update_node_heartbeat(1, NodeStatus::Healthy, ts)?;
+        store.update_node_heartbeat(2, NodeStatus::Unhealthy, ts)?;
+        store.update_storage_info(1, 100 * GB, 1000 * GB)?;
+
+        store.get_partition_infos("droplet_test", ts)?;
+        store.get_partition_infos("droplet_test", ts + 3600)?;
+
+        let node_info = store.get_worker_node_info(1)?;
+        assert_eq!(node_info.node_name, "node_a");
+        assert_eq!(node_info.status, NodeStatus::Alive as i32);
+        assert_eq!(node_info.last_heartbeat, ts);
+        assert_eq!(node_info.used_disk_size, 100 * GB);
+        assert_eq!(node_info.total_disk_size, 1000 * GB);
+        assert_eq!(node_info.partition_count, 2);
+
+        assert_eq!(store.get_worker_node_info(2)?.partition_count, 0);
+
+        assert_eq!(store.list_nodes(&[])?.len(), 2);
+
+        let node_infos = store.list_nodes(&[NodeStatus::Unhealthy, NodeStatus::Offline])?;
+        assert_eq!(node_infos.len(), 1);
+        assert_eq!(node_infos[0].node_name, "node_b");
+
+        assert!(store.list_nodes(&[NodeStatus::Offline])?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_table_lifecycle() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        store.insert_table_info(
+            "droplet_test_b",
+            &PartitionScheme::new(1),
+            &[],
+            &[ColumnInfo {
+                column_name: "label".to_string(),
+                column_type: DataType::F32.into(),
+                ..Default::default()
+            }],
+        )?;
+
+        assert_eq!(
+            store.list_tables()?,
+            vec!["droplet_test".to_string(), "droplet_test_b".to_string()]
+        );
+
+        let column = |name: &str, data_type: DataType| ColumnInfo {
+            column_name: name.to_string(),
+            column_type: data_type.into(),
+            ..Default::default()
+        };
+
+        // Type of existing column cannot be changed.
+        assert!(store
+            .insert_table_info(
+                "droplet_test",
+                &PartitionScheme::new(24),
+                &[],
+                &[column("ctr", DataType::I64)]
+            )
+            .is_err());
+        assert!(store
+            .alter_table("droplet_test", &[column("ctr", DataType::I64)], &[])
+            .is_err());
+        assert!(store
+            .alter_table("droplet_test", &[column("uid", DataType::None)], &[])
+            .is_err());
+        assert!(store
+            .alter_table("droplet_test", &[], &["not_exist".to_string()])
+            .is_err());
+        assert!(store.alter_table("not_exist", &[], &[]).is_err());
+
+        store.alter_table(
+            "droplet_test",
+            &[column("uid", DataType::I64), column("ctr", DataType::F32)],
+            &["ctr".to_string()],
+        )?;
+
+        let columns = store.get_table_column_infos("droplet_test")?;
+        assert_eq!(columns.len(), 3);
+        assert!(columns[1].deprecated);
+        assert_eq!(columns[2].column_name, "uid");
+        assert_eq!(columns[2].column_index, 2);
+        assert_eq!(columns[2].column_id, store.get_or_insert_key_id("uid")?);
+
+        // Deprecated column is added back.
+        store.alter_table("droplet_test", &[column("ctr", DataType::F32)], &[])?;
+        assert!(!store.get_table_column_infos("droplet_test")?[1].deprecated);
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.update_storage_info(1, 0, 1000 * GB)?;
+
+        // 2024-10-11 00:00:00 UTC.
+        let ts = 1728604800;
+
+        let stats = store.get_table_stats("droplet_test")?;
+        assert_eq!(stats.partition_count, 0);
+        assert!(stats.earliest_partition.is_none());
+
+        store.get_partition_infos("droplet_test", ts + 3600)?;
+        store.get_partition_infos("droplet_test", ts)?;
+        store.get_partition_infos("droplet_test_b", ts)?;
+
+        store.update_partition_stats("droplet_test", 20241011, 0, 100, 1000)?;
+        store.update_partition_stats("droplet_test", 20241011, 1, 200, 3000)?;
+
+        let stats = store.get_table_stats("droplet_test")?;
+        assert_eq!(stats.partition_count, 2);
+        assert_eq!(stats.row_count, 300);
+        assert_eq!(stats.byte_size, 4000);
+        assert_eq!(
+            stats.earliest_partition.unwrap_or_default().partition_index,
+            0
+        );
+        assert_eq!(
+            stats.latest_partition.unwrap_or_default().partition_index,
+            1
+        );
+
+        assert_eq!(store.drop_table("droplet_test")?, 2);
+        assert!(store.drop_table("droplet_test").is_err());
+        assert!(!store.is_table_exist("droplet_test")?);
+        assert!(store.get_table_stats("droplet_test").is_err());
+        assert_eq!(store.list_tables()?, vec!["droplet_test_b".to_string()]);
+
+        // Partitions of the dropped table are not used any more.
+        assert!(store
+            .get_table_paths_by_time("droplet_test", ts, ts + 7200)?
+            .is_empty());
+        assert_eq!(store.get_table_stats("droplet_test_b")?.partition_count, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_schema_version() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        store.register_node("node_a", "127.0.0.1", 50052)?;
+        store.update_storage_info(1, 0, 1000 * GB)?;
+
+        // 2024-10-11 00:00:00 UTC.
+        let ts = 1728604800;
+
+        assert_eq!(
+            store.get_schema_version("droplet_test")?,
+            INITIAL_SCHEMA_VERSION
+        );
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts)?[0].schema_version,
+            1
+        );
+
+        let column = |name: &str| ColumnInfo {
+            column_name: name.to_string(),
+            column_type: DataType::I64.into(),
+            ..Default::default()
+        };
+
+        // Nothing changed, the schema version is kept.
+        assert_eq!(store.alter_table("droplet_test", &[], &[])?, 1);
+        insert_test_table(&store)?;
+        assert_eq!(store.get_schema_version("droplet_test")?, 1);
+
+        assert_eq!(store.alter_table("droplet_test", &[column("uid")], &[])?, 2);
+
+        // New column of existing table is added with a new schema version.
+        store.insert_table_info(
+            "droplet_test",
+            &PartitionScheme::new(24),
+            &[],
+            &[column("item_id")],
+        )?;
+        assert_eq!(store.get_schema_version("droplet_test")?, 3);
+
+        let columns = store
+            .get_table_column_infos("droplet_test")?
+            .iter()
+            .map(|x| (x.column_name.clone(), x.column_index, x.schema_version))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            columns,
+            vec![
+                ("label".to_string(), 0, 1),
+                ("ctr".to_string(), 1, 1),
+                ("uid".to_string(), 2, 2),
+                ("item_id".to_string(), 3, 3),
+            ]
+        );
+
+        // Old partition keeps its schema version, new partition has the current one.
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts)?[0].schema_version,
+            1
+        );
+        assert_eq!(
+            store.get_partition_infos("droplet_test", ts + 3600)?[0].schema_version,
+            3
+        );
+        assert_eq!(
+            store.get_partition_schema_version("droplet_test", 20241011, 0)?,
+            Some(1)
+        );
+        assert_eq!(
+            store.get_partition_schema_version("droplet_test", 20241011, 1)?,
+            Some(3)
+        );
+        assert_eq!(
+            store.get_partition_schema_version("droplet_test", 20241011, 2)?,
+            None
+        );
+
+        assert_eq!(
+            store.alter_table("droplet_test", &[], &["uid".to_string()])?,
+            4
+        );
+        assert_eq!(
+            store.alter_table("droplet_test", &[], &["uid".to_string()])?,
+            4
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_schema() -> Result<()> {
+        let store = MemoryMetaStore::new();
+        insert_test_table(&store)?;
+
+        assert_eq!(
+            store.get_key_columns("droplet_test")?,
+            get_default_key_columns()
+        );
+        assert_eq!(store.get_key_schema("droplet_test")?, KeySchema::default());
+
+        let key_columns = vec!["timestamp".to_string(), "device_id".to_string()];
+
+        // The first key column must be `timestamp`.
+        assert!(store
+            .insert_table_info(
+                "droplet_test_b",
+                &PartitionScheme::new(24),
+                &key_columns[1..],
+                &[]
+            )
+            .is_err());
+
+        store.insert_table_info(
+            "droplet_test_b",
+            &PartitionScheme::new(24),
+            &key_columns,
+            &[],
+        )?;
+
+        assert_eq!(store.get_key_columns("droplet_test_b")?, key_columns);
+
+        // Key columns are inserted into id mapping when the table is created.
+        let device_id = store.get_key_id("device_id")?;
+        assert!(device_id.is_some());
+        assert_eq!(
+            store.get_key_schema("droplet_test_b")?.col_ids(),
+            &[2, device_id.unwrap()]
+        );
+
+        // Key columns are joined by `,` in `table_info`.
+        let invalid_key_columns = vec!["timestamp".to_string(), "a,b".to_string()];
+        assert!(store
+            .insert_table_info(
+                "droplet_test_c",
+                &PartitionScheme::new(24),
+                &invalid_key_columns,
+                &[]
+            )
+            .is_err());
+
+        // Key columns of existing table cannot be changed, empty keeps them.
+        assert!(insert_test_table(&store).is_ok());
+        assert!(store
+            .insert_table_info("droplet_test", &PartitionScheme::new(24), &key_columns, &[])
+            .is_err());
+        store.insert_table_info("droplet_test_b", &PartitionScheme::new(24), &[], &[])?;
+        assert_eq!(store.get_key_columns("droplet_test_b")?, key_columns);
+
+        Ok(())
+    }
+}
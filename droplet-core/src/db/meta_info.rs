@@ -1,19 +1,23 @@
-use chrono::Duration;
-use chrono::Timelike;
 use chrono::{DateTime, Utc};
 use chrono::{NaiveDate, NaiveDateTime};
 use log::error;
 use mysql::params;
 use mysql::prelude::*;
-use mysql::PooledConn;
+use mysql::{PooledConn, TxOpts};
 
 use anyhow::{bail, Result};
 
+use crate::db::meta_store::{
+    check_column_types, check_key_columns, check_partition_scheme, get_default_key_columns,
+    get_partitions_stats, INITIAL_SCHEMA_VERSION,
+};
 use crate::droplet::ColumnInfo;
 use crate::droplet::NodeInfo;
 use crate::droplet::NodeStatus;
 use crate::droplet::PartitionInfo;
+use crate::droplet::{PartitionStatus, TableStats};
 use crate::error_bail;
+use crate::partition::{get_sorted_partition_path, PartitionScheme, TimestampUnit};
 
 /// Get key id from `id_mapping` table.
 pub fn get_key_id(conn: &mut PooledConn, key_str: &str) -> Option<u32> {
@@ -127,21 +131,162 @@ pub fn get_worker_node_id(conn: &mut PooledConn, node_name: &str) -> Result<u32>
     }
 }
 
+/// Select node info with the last heartbeat, the latest disk usage and the partition count.
+const NODE_INFO_QUERY: &str = "SELECT
+        n.id,
+        n.node_name,
+        n.node_ip,
+        n.node_port,
+        n.node_status,
+        CAST(COALESCE(UNIX_TIMESTAMP(n.last_heartbeat_at), 0) AS UNSIGNED),
+        COALESCE((
+            SELECT s.used_disk_size
+            FROM node_storage_info s
+            WHERE s.node_id = n.id
+            ORDER BY s.updated_at DESC, s.id DESC
+            LIMIT 1
+        ), 0),
+        n.total_disk_size,
+        (SELECT COUNT(*) FROM partition_info p WHERE p.node_id = n.id)
+    FROM worker_node_info n";
+
+type NodeInfoRow = (u32, String, String, u32, i32, u64, u64, u64, u32);
+
+fn get_node_info_from_row(row: NodeInfoRow) -> NodeInfo {
+    NodeInfo {
+        node_id: row.0,
+        node_name: row.1,
+        node_ip: row.2,
+        node_port: row.3,
+        status: row.4,
+        last_heartbeat: row.5,
+        used_disk_size: row.6,
+        total_disk_size: row.7,
+        partition_count: row.8,
+    }
+}
+
+pub fn get_worker_node_info(conn: &mut PooledConn, node_id: u32) -> Result<NodeInfo> {
+    match conn.exec_first::<NodeInfoRow, _, _>(
+        format!("{} WHERE n.id = :node_id", NODE_INFO_QUERY),
+        params! {
+            "node_id" => node_id,
+        },
+    )? {
+        Some(row) => Ok(get_node_info_from_row(row)),
+        None => {
+            error_bail!("Node not found, node_id: {}", node_id);
+        }
+    }
+}
+
+/// List nodes with the statuses ordered by node id, all nodes if `statuses` is empty.
+pub fn list_nodes(conn: &mut PooledConn, statuses: &[NodeStatus]) -> Result<Vec<NodeInfo>> {
+    let condition = if statuses.is_empty() {
+        String::new()
+    } else {
+        let statuses = statuses
+            .iter()
+            .map(|status| (*status as i32).to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        format!(" WHERE n.node_status IN ({})", statuses)
+    };
+
+    let rows =
+        conn.query::<NodeInfoRow, _>(format!("{}{} ORDER BY n.id", NODE_INFO_QUERY, condition))?;
+
+    Ok(rows.into_iter().map(get_node_info_from_row).collect())
+}
+
+/// Insert table info and columns.
+///
+/// Existing table and columns are ignored, the type of existing columns must not change, nor
+/// the timestamp unit and timezone of existing table. New columns of existing table are added
+/// by `alter_table`.
 pub fn insert_table_info(
     conn: &mut PooledConn,
     table_name: &str,
-    partition_count_per_day: u32,
-    columns: &Vec<ColumnInfo>,
+    partition_scheme: &PartitionScheme,
+    key_columns: &[String],
+    columns: &[ColumnInfo],
 ) -> Result<()> {
+    check_key_columns(key_columns)?;
+
+    let existing_scheme = if is_table_exist(conn, table_name)? {
+        Some(get_partition_scheme(conn, table_name)?)
+    } else {
+        None
+    };
+    check_partition_scheme(table_name, existing_scheme.as_ref(), partition_scheme)?;
+
+    let existing_columns = get_table_column_infos(conn, table_name)?;
+    check_column_types(&existing_columns, columns)?;
+
+    if existing_scheme.is_some() {
+        let existing_key_columns = get_key_columns(conn, table_name)?;
+
+        if !key_columns.is_empty() && key_columns != existing_key_columns.as_slice() {
+            error_bail!(
+                "Key columns cannot be changed, table_name: {}, key_columns: {:?}, new key_columns: {:?}",
+                table_name,
+                existing_key_columns,
+                key_columns
+            );
+        }
+
+        let new_columns = columns
+            .iter()
+            .filter(|column| {
+                !existing_columns
+                    .iter()
+                    .any(|x| x.column_name == column.column_name)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+
+        if !new_columns.is_empty() {
+            alter_table(conn, table_name, &new_columns, &[])?;
+        }
+
+        return Ok(());
+    }
+
     // Insert table info.
     conn.exec_drop(
-        "INSERT IGNORE INTO table_info (table_name, partition_count_per_day) VALUES (:table_name, :partition_count_per_day)",
+        "INSERT IGNORE INTO table_info (table_name, partition_count_per_day, timestamp_unit, tz_offset_seconds, schema_version, key_columns) VALUES (:table_name, :partition_count_per_day, :timestamp_unit, :tz_offset_seconds, :schema_version, :key_columns)",
         params! {
             "table_name" => table_name.to_string(),
-            "partition_count_per_day" => partition_count_per_day,
+            "partition_count_per_day" => partition_scheme.partition_count_per_day,
+            "timestamp_unit" => partition_scheme.timestamp_unit as i32,
+            "tz_offset_seconds" => partition_scheme.tz_offset_seconds,
+            "schema_version" => INITIAL_SCHEMA_VERSION,
+            "key_columns" => key_columns.join(","),
         }
     )?;
 
+    // Ids of the key columns are got by `get_key_schema` without inserting.
+    conn.exec_batch(
+        "INSERT IGNORE INTO id_mapping (key_str) VALUES (:key_str)",
+        key_columns.iter().map(|name| {
+            params! {
+                "key_str" => name.to_string(),
+            }
+        }),
+    )?;
+
+    insert_column_infos(conn, table_name, columns, INITIAL_SCHEMA_VERSION)
+}
+
+/// Insert columns into `id_mapping` and `column_info` with the schema version they are added
+/// in, existing columns are ignored.
+fn insert_column_infos(
+    conn: &mut impl Queryable,
+    table_name: &str,
+    columns: &[ColumnInfo],
+    schema_version: u32,
+) -> Result<()> {
     // Insert column name into id_mapping.
     let stmt_id_mapping = "INSERT IGNORE INTO id_mapping (key_str) VALUES (:column_name)";
     conn.exec_batch(
@@ -155,8 +300,8 @@ pub fn insert_table_info(
 
     // Insert column infos.
     let stmt_column_infos = "INSERT IGNORE INTO
-        column_info (table_name, column_name, column_type, column_id, column_index)
-    SELECT :table_name, :column_name, :column_type, id_mapping.key_id, :column_index
+        column_info (table_name, column_name, column_type, column_id, column_index, schema_version)
+    SELECT :table_name, :column_name, :column_type, id_mapping.key_id, :column_index, :schema_version
     FROM id_mapping
     WHERE id_mapping.key_str = :column_name";
     conn.exec_batch(
@@ -165,8 +310,9 @@ pub fn insert_table_info(
             params! {
                 "table_name" => table_name.to_string(),
                 "column_name" => c.column_name.to_string(),
-                "column_type" => c.column_type.to_string(),
+                "column_type" => c.column_type,
                 "column_index" => c.column_index,
+                "schema_version" => schema_version,
             }
         }),
     )?;
@@ -174,23 +320,426 @@ pub fn insert_table_info(
     Ok(())
 }
 
-pub fn get_table_column_infos(conn: &mut PooledConn, table_name: &str) -> Result<Vec<ColumnInfo>> {
+pub fn list_tables(conn: &mut PooledConn) -> Result<Vec<String>> {
+    Ok(conn.query::<String, _>("SELECT table_name FROM table_info ORDER BY table_name")?)
+}
+
+/// Add or deprecate columns of the table, return the schema version after the change.
+///
+/// All the columns are checked before any change is made. The schema version is increased only
+/// if any column is added, added back or deprecated.
+///
+/// The change is made in a transaction, and the row of the table is locked, so concurrent
+/// changes of the same table get different schema versions.
+pub fn alter_table(
+    conn: &mut PooledConn,
+    table_name: &str,
+    add_columns: &[ColumnInfo],
+    deprecate_columns: &[String],
+) -> Result<u32> {
+    let mut tx = conn.start_transaction(TxOpts::default())?;
+
+    let schema_version = alter_columns(&mut tx, table_name, add_columns, deprecate_columns)?;
+
+    tx.commit()?;
+
+    Ok(schema_version)
+}
+
+fn alter_columns(
+    conn: &mut impl Queryable,
+    table_name: &str,
+    add_columns: &[ColumnInfo],
+    deprecate_columns: &[String],
+) -> Result<u32> {
+    let cur_schema_version = match conn.exec_first::<u32, _, _>(
+        "SELECT schema_version FROM table_info WHERE table_name = :table_name FOR UPDATE",
+        params! {
+            "table_name" => table_name.to_string(),
+        },
+    )? {
+        Some(schema_version) => schema_version,
+        None => {
+            error_bail!("Table not found, table_name: {}", table_name);
+        }
+    };
+
+    let existing_columns = get_table_column_infos(conn, table_name)?;
+    check_column_types(&existing_columns, add_columns)?;
+
+    for column_name in deprecate_columns {
+        if !existing_columns
+            .iter()
+            .any(|x| &x.column_name == column_name)
+        {
+            error_bail!(
+                "Column not found, table_name: {}, column_name: {}",
+                table_name,
+                column_name
+            );
+        }
+    }
+
+    let mut next_column_index = existing_columns
+        .iter()
+        .map(|x| x.column_index + 1)
+        .max()
+        .unwrap_or(0);
+
+    let mut new_columns = Vec::new();
+    let mut revived_columns = Vec::new();
+
+    for column in add_columns {
+        if new_columns
+            .iter()
+            .chain(revived_columns.iter())
+            .any(|x: &ColumnInfo| x.column_name == column.column_name)
+        {
+            continue;
+        }
+
+        match existing_columns
+            .iter()
+            .find(|x| x.column_name == column.column_name)
+        {
+            Some(existing) if existing.deprecated => revived_columns.push(existing.clone()),
+            Some(_) => {}
+            None => {
+                new_columns.push(ColumnInfo {
+                    column_index: next_column_index,
+                    deprecated: false,
+                    ..column.clone()
+                });
+                next_column_index += 1;
+            }
+        }
+    }
+
+    let is_changed = !new_columns.is_empty()
+        || !revived_columns.is_empty()
+        || existing_columns
+            .iter()
+            .any(|x| !x.deprecated && deprecate_columns.contains(&x.column_name));
+
+    if !is_changed {
+        return Ok(cur_schema_version);
+    }
+
+    let schema_version = cur_schema_version + 1;
+
+    insert_column_infos(conn, table_name, &new_columns, schema_version)?;
+
+    let stmt_deprecated = "UPDATE column_info SET deprecated = :deprecated
+        WHERE table_name = :table_name AND column_name = :column_name";
+
+    conn.exec_batch(
+        stmt_deprecated,
+        revived_columns.iter().map(|c| {
+            params! {
+                "deprecated" => false,
+                "table_name" => table_name.to_string(),
+                "column_name" => c.column_name.to_string(),
+            }
+        }),
+    )?;
+
+    conn.exec_batch(
+        stmt_deprecated,
+        deprecate_columns.iter().map(|column_name| {
+            params! {
+                "deprecated" => true,
+                "table_name" => table_name.to_string(),
+                "column_name" => column_name.to_string(),
+            }
+        }),
+    )?;
+
+    conn.exec_drop(
+        "UPDATE table_info SET schema_version = :schema_version WHERE table_name = :table_name",
+        params! {
+            "schema_version" => schema_version,
+            "table_name" => table_name.to_string(),
+        },
+    )?;
+
+    Ok(schema_version)
+}
+
+pub fn get_schema_version(conn: &mut PooledConn, table_name: &str) -> Result<u32> {
+    match conn.exec_first::<u32, _, _>(
+        "SELECT schema_version FROM table_info WHERE table_name = :table_name",
+        params! {
+            "table_name" => table_name.to_string(),
+        },
+    )? {
+        Some(schema_version) => Ok(schema_version),
+        None => {
+            error_bail!("Table not found, table_name: {}", table_name);
+        }
+    }
+}
+
+/// Get names of the key columns, the default key columns if not declared.
+pub fn get_key_columns(conn: &mut PooledConn, table_name: &str) -> Result<Vec<String>> {
+    match conn.exec_first::<String, _, _>(
+        "SELECT key_columns FROM table_info WHERE table_name = :table_name",
+        params! {
+            "table_name" => table_name.to_string(),
+        },
+    )? {
+        Some(key_columns) if key_columns.is_empty() => Ok(get_default_key_columns()),
+        Some(key_columns) => Ok(key_columns.split(',').map(|x| x.to_string()).collect()),
+        None => {
+            error_bail!("Table not found, table_name: {}", table_name);
+        }
+    }
+}
+
+/// Delete the table and its columns, and move its partitions to `partition_info_to_delete` with
+/// status `ToDelete`.
+///
+/// The partitions are moved out of `partition_info`, so a table with the same name could be
+/// created again and get new partitions. All changes are made in one transaction.
+///
+/// Return the number of partitions moved.
+pub fn drop_table(conn: &mut PooledConn, table_name: &str) -> Result<u32> {
+    let mut tx = conn.start_transaction(TxOpts::default())?;
+
+    let table_id = tx.exec_first::<u32, _, _>(
+        "SELECT id FROM table_info WHERE table_name = :table_name FOR UPDATE",
+        params! {
+            "table_name" => table_name.to_string(),
+        },
+    )?;
+
+    if table_id.is_none() {
+        error_bail!("Table not found, table_name: {}", table_name);
+    }
+
+    tx.exec_drop(
+        "INSERT INTO partition_info_to_delete
+            (id, table_name, partition_date, partition_index, node_id, time_start, time_end,
+             partition_status, row_count, byte_size, schema_version)
+        SELECT id, table_name, partition_date, partition_index, node_id, time_start, time_end,
+            :to_delete, row_count, byte_size, schema_version
+        FROM partition_info
+        WHERE table_name = :table_name",
+        params! {
+            "to_delete" => PartitionStatus::ToDelete as i32,
+            "table_name" => table_name.to_string(),
+        },
+    )?;
+
+    let partition_count = tx.affected_rows() as u32;
+
+    for stmt in [
+        "DELETE FROM partition_info WHERE table_name = :table_name",
+        "DELETE FROM column_info WHERE table_name = :table_name",
+        "DELETE FROM table_info WHERE table_name = :table_name",
+    ] {
+        tx.exec_drop(
+            stmt,
+            params! {
+                "table_name" => table_name.to_string(),
+            },
+        )?;
+    }
+
+    tx.commit()?;
+
+    Ok(partition_count)
+}
+
+type PartitionInfoRow = (
+    u32,
+    u32,
+    u32,
+    u32,
+    String,
+    String,
+    u32,
+    u64,
+    u64,
+    u64,
+    u64,
+    u32,
+);
+
+/// Get active partitions of the table, with node info.
+pub fn get_active_partition_infos(
+    conn: &mut PooledConn,
+    table_name: &str,
+) -> Result<Vec<PartitionInfo>> {
+    let partitions = conn.exec_map(
+        "SELECT
+            p.id,
+            p.partition_date,
+            p.partition_index,
+            p.node_id,
+            COALESCE(n.node_name, ''),
+            COALESCE(n.node_ip, ''),
+            COALESCE(n.node_port, 0),
+            CAST(UNIX_TIMESTAMP(p.time_start) * 1000 AS UNSIGNED),
+            CAST(UNIX_TIMESTAMP(p.time_end) * 1000 AS UNSIGNED),
+            p.row_count,
+            p.byte_size,
+            p.schema_version
+        FROM partition_info p
+        LEFT JOIN worker_node_info n ON p.node_id = n.id
+        WHERE p.table_name = :table_name AND p.partition_status = :active
+        ORDER BY p.time_start, p.partition_date, p.partition_index",
+        params! {
+            "table_name" => table_name.to_string(),
+            "active" => PartitionStatus::Active as i32,
+        },
+        to_partition_info,
+    )?;
+
+    Ok(partitions)
+}
+
+/// Get the active partition by its date and index, with node info.
+pub fn get_partition_info(
+    conn: &mut PooledConn,
+    table_name: &str,
+    partition_date: u32,
+    partition_index: u32,
+) -> Result<Option<PartitionInfo>> {
+    let partition = conn.exec_first::<PartitionInfoRow, _, _>(
+        "SELECT
+            p.id,
+            p.partition_date,
+            p.partition_index,
+            p.node_id,
+            COALESCE(n.node_name, ''),
+            COALESCE(n.node_ip, ''),
+            COALESCE(n.node_port, 0),
+            CAST(UNIX_TIMESTAMP(p.time_start) * 1000 AS UNSIGNED),
+            CAST(UNIX_TIMESTAMP(p.time_end) * 1000 AS UNSIGNED),
+            p.row_count,
+            p.byte_size,
+            p.schema_version
+        FROM partition_info p
+        LEFT JOIN worker_node_info n ON p.node_id = n.id
+        WHERE p.table_name = :table_name
+            AND p.partition_date = :partition_date
+            AND p.partition_index = :partition_index
+            AND p.partition_status = :active",
+        params! {
+            "table_name" => table_name.to_string(),
+            "partition_date" => partition_date,
+            "partition_index" => partition_index,
+            "active" => PartitionStatus::Active as i32,
+        },
+    )?;
+
+    Ok(partition.map(to_partition_info))
+}
+
+/// `PartitionInfo` of an active partition, from `partition_info` joined with `worker_node_info`.
+fn to_partition_info(row: PartitionInfoRow) -> PartitionInfo {
+    PartitionInfo {
+        partition_id: row.0,
+        partition_date: row.1,
+        partition_index: row.2,
+        node_id: row.3,
+        node_name: row.4,
+        node_ip: row.5,
+        node_port: row.6,
+        time_start: row.7,
+        time_end: row.8,
+        status: PartitionStatus::Active.into(),
+        row_count: row.9,
+        byte_size: row.10,
+        schema_version: row.11,
+    }
+}
+
+pub fn get_table_stats(conn: &mut PooledConn, table_name: &str) -> Result<TableStats> {
+    if !is_table_exist(conn, table_name)? {
+        error_bail!("Table not found, table_name: {}", table_name);
+    }
+
+    let partitions = get_active_partition_infos(conn, table_name)?;
+
+    Ok(get_partitions_stats(partitions.iter()))
+}
+
+pub fn update_partition_stats(
+    conn: &mut PooledConn,
+    table_name: &str,
+    partition_date: u32,
+    partition_index: u32,
+    row_count: u64,
+    byte_size: u64,
+) -> Result<()> {
+    conn.exec_drop(
+        "UPDATE partition_info SET row_count = :row_count, byte_size = :byte_size
+        WHERE table_name = :table_name
+            AND partition_date = :partition_date
+            AND partition_index = :partition_index
+            AND partition_status = :active",
+        params! {
+            "row_count" => row_count,
+            "byte_size" => byte_size,
+            "table_name" => table_name.to_string(),
+            "partition_date" => partition_date,
+            "partition_index" => partition_index,
+            "active" => PartitionStatus::Active as i32,
+        },
+    )?;
+
+    Ok(())
+}
+
+pub fn get_partition_schema_version(
+    conn: &mut PooledConn,
+    table_name: &str,
+    partition_date: u32,
+    partition_index: u32,
+) -> Result<Option<u32>> {
+    let schema_version = conn.exec_first(
+        "SELECT schema_version FROM partition_info
+        WHERE table_name = :table_name
+            AND partition_date = :partition_date
+            AND partition_index = :partition_index
+            AND partition_status = :active",
+        params! {
+            "table_name" => table_name.to_string(),
+            "partition_date" => partition_date,
+            "partition_index" => partition_index,
+            "active" => PartitionStatus::Active as i32,
+        },
+    )?;
+
+    Ok(schema_version)
+}
+
+pub fn get_table_column_infos(
+    conn: &mut impl Queryable,
+    table_name: &str,
+) -> Result<Vec<ColumnInfo>> {
     conn.query_map(
         format!(
             "SELECT 
             column_name, 
             column_type, 
             column_id, 
-            column_index 
-        FROM table_columns 
-        WHERE table_name = '{}'",
+            column_index,
+            deprecated,
+            schema_version
+        FROM column_info
+        WHERE table_name = '{}'
+        ORDER BY column_index",
             table_name.to_string()
         ),
-        |row: (String, i32, u32, u32)| ColumnInfo {
+        |row: (String, i32, u32, u32, bool, u32)| ColumnInfo {
             column_name: row.0,
             column_type: row.1.into(),
             column_id: row.2,
             column_index: row.3,
+            deprecated: row.4,
+            schema_version: row.5,
         },
     )
     .map_err(|e| {
@@ -215,45 +764,173 @@ pub fn get_partition_count_per_day(conn: &mut PooledConn, table_name: &str) -> R
     }
 }
 
-pub fn update_storage_info(conn: &mut PooledConn, node_id: u32, used_disk_size: u64) -> Result<()> {
+pub fn get_partition_scheme(conn: &mut PooledConn, table_name: &str) -> Result<PartitionScheme> {
+    let row = conn.exec_first::<(u32, i32, i32), _, _>(
+        "SELECT partition_count_per_day, timestamp_unit, tz_offset_seconds FROM table_info WHERE table_name = :table_name",
+        params! {
+            "table_name" => table_name.to_string(),
+        },
+    )?;
+
+    let (partition_count_per_day, timestamp_unit, tz_offset_seconds) = match row {
+        Some(row) => row,
+        None => {
+            error_bail!(
+                "Table not found for partition scheme, table_name: {}",
+                table_name
+            );
+        }
+    };
+
+    let timestamp_unit = match TimestampUnit::try_from(timestamp_unit) {
+        Ok(timestamp_unit) => timestamp_unit,
+        Err(_) => {
+            error_bail!(
+                "Unknown timestamp unit: {}, table_name: {}",
+                timestamp_unit,
+                table_name
+            );
+        }
+    };
+
+    Ok(PartitionScheme::new(partition_count_per_day)
+        .with_timestamp_unit(timestamp_unit)
+        .with_tz_offset_seconds(tz_offset_seconds))
+}
+
+/// `DateTime` of a timestamp in seconds.
+fn get_datetime(seconds: u64) -> Result<DateTime<Utc>> {
+    match DateTime::from_timestamp(seconds as i64, 0) {
+        Some(datetime) => Ok(datetime),
+        None => {
+            error_bail!("Invalid timestamp in seconds: {}", seconds);
+        }
+    }
+}
+
+/// Save the reported storage info.
+///
+/// `total_disk_size` is kept in `worker_node_info`, and every report is inserted into
+/// `node_storage_info`.
+pub fn update_storage_info(
+    conn: &mut PooledConn,
+    node_id: u32,
+    used_disk_size: u64,
+    total_disk_size: u64,
+) -> Result<()> {
+    conn.exec_drop(
+        "UPDATE worker_node_info SET total_disk_size = :total_disk_size WHERE id = :node_id",
+        params! {
+            "total_disk_size" => total_disk_size,
+            "node_id" => node_id,
+        },
+    )?;
+
     conn.exec_drop(
-        "INSERT INTO node_storage_info (node_id, used_disk_size) VALUES (:node_id, :used_disk_size)",
+        "INSERT INTO node_storage_info (node_id, used_disk_size, total_disk_size) VALUES (:node_id, :used_disk_size, :total_disk_size)",
         params! {
             "node_id" => node_id,
             "used_disk_size" => used_disk_size,
+            "total_disk_size" => total_disk_size,
         }
     )?;
     Ok(())
 }
 
-/// Get partition infos by timestamp.
+/// Record the heartbeat time and status of the node, `timestamp` in seconds.
+pub fn update_node_heartbeat(
+    conn: &mut PooledConn,
+    node_id: u32,
+    node_status: NodeStatus,
+    timestamp: u64,
+) -> Result<()> {
+    let node = conn.query_first::<u32, _>(format!(
+        "SELECT id FROM worker_node_info WHERE id = {}",
+        node_id
+    ))?;
+
+    if node.is_none() {
+        error_bail!("Node not found, node_id: {}", node_id);
+    }
+
+    conn.exec_drop(
+        "UPDATE worker_node_info SET node_status = :node_status, last_heartbeat_at = FROM_UNIXTIME(:timestamp) WHERE id = :node_id",
+        params! {
+            "node_status" => node_status as i32,
+            "timestamp" => timestamp,
+            "node_id" => node_id,
+        }
+    )?;
+
+    Ok(())
+}
+
+/// Move nodes whose last heartbeat is before `unhealthy_before` from `Alive` to `Unhealthy`,
+/// and nodes whose last heartbeat is before `offline_before` to `Offline`.
+///
+/// Nodes never sent heartbeat use `created_at` as the last heartbeat.
+pub fn update_node_status(
+    conn: &mut PooledConn,
+    unhealthy_before: u64,
+    offline_before: u64,
+) -> Result<()> {
+    conn.exec_drop(
+        "UPDATE worker_node_info SET node_status = :offline
+        WHERE node_status != :offline
+        AND COALESCE(last_heartbeat_at, created_at) < FROM_UNIXTIME(:offline_before)",
+        params! {
+            "offline" => NodeStatus::Offline as i32,
+            "offline_before" => offline_before,
+        },
+    )?;
+
+    conn.exec_drop(
+        "UPDATE worker_node_info SET node_status = :unhealthy
+        WHERE node_status = :alive
+        AND COALESCE(last_heartbeat_at, created_at) < FROM_UNIXTIME(:unhealthy_before)",
+        params! {
+            "unhealthy" => NodeStatus::Unhealthy as i32,
+            "alive" => NodeStatus::Alive as i32,
+            "unhealthy_before" => unhealthy_before,
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Get partition infos by timestamp in the unit of the table.
 ///
 /// Return one PartitionInfo now. Maybe more in the future for better performance.
+///
+/// The partition is created on the node selected by `get_available_node` if not exists, and
+/// the existing partition is returned otherwise, so all the sinkers of a partition write to
+/// the same node, even after the available node changes.
 pub fn get_partition_infos(
     conn: &mut PooledConn,
     table_name: &str,
     timestamp: u64,
+    min_free_disk_size: u64,
 ) -> Result<Vec<PartitionInfo>> {
-    let partition_count_per_day = get_partition_count_per_day(conn, table_name)?;
-
-    let naive_datetime = DateTime::from_timestamp(timestamp as i64, 0)
-        .ok_or_else(|| anyhow::anyhow!(format!("Invalid timestamp: {}", timestamp)))?;
-    let seconds_in_day = naive_datetime.num_seconds_from_midnight();
-    let partition_index = (seconds_in_day as u32 * partition_count_per_day / 86400) as u32;
+    let partition_scheme = get_partition_scheme(conn, table_name)?;
 
-    let partition_date = naive_datetime.format("%Y%m%d").to_string().parse::<u32>()?;
+    let (partition_date, partition_index) = partition_scheme.get_partition(timestamp)?;
 
-    let time_span_in_seconds: i64 = 86400 / partition_count_per_day as i64;
+    if let Some(partition_info) =
+        get_partition_info(conn, table_name, partition_date, partition_index)?
+    {
+        return Ok(vec![partition_info]);
+    }
 
-    let midnight = naive_datetime - Duration::seconds(seconds_in_day.into());
-    let time_start = midnight + Duration::seconds(time_span_in_seconds * partition_index as i64);
-    let time_end = time_start + Duration::seconds(time_span_in_seconds);
+    // Boundaries of partitions are whole seconds.
+    let (time_start, time_end) = partition_scheme.get_time_range(timestamp)?;
+    let time_start = get_datetime(partition_scheme.to_seconds(time_start))?;
+    let time_end = get_datetime(partition_scheme.to_seconds(time_end))?;
 
-    let ts = naive_datetime - Duration::minutes(60);
-    let available_node = get_available_node(conn, ts)?;
+    let available_node = get_available_node(conn, min_free_disk_size)?;
+    let schema_version = get_schema_version(conn, table_name)?;
 
-    // Insert partition info into database.
-    let partition_id = insert_partition_info(
+    // Another sinker may create the partition at the same time, the first inserted one is used.
+    insert_partition_info(
         conn,
         table_name,
         partition_date,
@@ -261,29 +938,28 @@ pub fn get_partition_infos(
         available_node.node_id,
         &time_start,
         &time_end,
+        schema_version,
     )?;
 
-    let partition_info = PartitionInfo {
-        partition_id,
-        partition_date,
-        partition_index,
-        node_id: available_node.node_id,
-        node_name: available_node.node_name.to_string(),
-        node_ip: available_node.node_ip.to_string(),
-        node_port: available_node.node_port,
-        time_start: time_start.timestamp_millis() as u64,
-        time_end: time_end.timestamp_millis() as u64,
-    };
-
-    Ok(vec![partition_info])
+    match get_partition_info(conn, table_name, partition_date, partition_index)? {
+        Some(partition_info) => Ok(vec![partition_info]),
+        None => {
+            error_bail!(
+                "Failed to get partition info, table_name: {}, partition_date: {}, partition_index: {}",
+                table_name,
+                partition_date,
+                partition_index
+            );
+        }
+    }
 }
 
-/// Select the available node with the least disk usage.
+/// Select the alive node with the least disk usage ratio.
 ///
-/// We use sql to select the node, order by `update_at` desc and `disk_usage_ratio` asc.
-/// Accoding this rule we can select the node with the least disk usage.
-pub fn get_available_node(conn: &mut PooledConn, midnight: DateTime<Utc>) -> Result<NodeInfo> {
-    let node_usage = conn.query_first::<(u32, String, String, u32, f64), _>(format!(
+/// The latest record of each node in `node_storage_info` is used, and nodes with free disk size
+/// less than `min_free_disk_size` are skipped.
+pub fn get_available_node(conn: &mut PooledConn, min_free_disk_size: u64) -> Result<NodeInfo> {
+    let node_usage = conn.exec_first::<(u32, String, String, u32, f64), _, _>(
         "SELECT
             node_id,
             node_name,
@@ -292,35 +968,29 @@ pub fn get_available_node(conn: &mut PooledConn, midnight: DateTime<Utc>) -> Res
             disk_usage_ratio
         FROM (
             SELECT
-                node_id,
-                node_name,
-                node_ip,
-                node_port,
-                disk_usage_ratio
-            FROM (
-                SELECT
-                    a.node_id, 
-                    a.used_disk_size,
-                    a.used_disk_size / b.total_disk_size disk_usage_ratio,
-                    a.update_at,
-                    b.node_name,
-                    b.node_ip,
-                    b.node_port,
-                    row_number() over (order by a.node_id, a.update_at desc) rank
-                FROM node_storage_info a
-                JOIN worker_node_info b ON a.node_id = b.id
-                AND b.status = 1
-                AND a.update_at > '{}'
-                AND b.total_disk_size > 0
-                ORDER BY a.node_id, a.update_at DESC
-            ) t
-            WHERE t.rank = 1
-        ) t1
-        ORDER BY t1.disk_usage_ratio ASC
+                a.node_id,
+                a.used_disk_size,
+                a.used_disk_size / b.total_disk_size disk_usage_ratio,
+                b.node_name,
+                b.node_ip,
+                b.node_port,
+                b.total_disk_size,
+                row_number() over (partition by a.node_id order by a.updated_at desc, a.id desc) rn
+            FROM node_storage_info a
+            JOIN worker_node_info b ON a.node_id = b.id
+            WHERE b.node_status = :alive
+            AND b.total_disk_size > 0
+        ) t
+        WHERE t.rn = 1
+        AND t.total_disk_size >= t.used_disk_size + :min_free_disk_size
+        ORDER BY t.disk_usage_ratio ASC
         LIMIT 1
         ",
-        midnight.format("%Y-%m-%d").to_string()
-    ))?;
+        params! {
+            "alive" => NodeStatus::Alive as i32,
+            "min_free_disk_size" => min_free_disk_size,
+        },
+    )?;
 
     match node_usage {
         Some(node_usage) => Ok(NodeInfo {
@@ -329,6 +999,7 @@ pub fn get_available_node(conn: &mut PooledConn, midnight: DateTime<Utc>) -> Res
             node_ip: node_usage.2,
             node_port: node_usage.3,
             status: NodeStatus::Alive.into(),
+            ..Default::default()
         }),
         None => {
             error_bail!("No available node");
@@ -336,6 +1007,11 @@ pub fn get_available_node(conn: &mut PooledConn, midnight: DateTime<Utc>) -> Res
     }
 }
 
+/// Insert partition info with the schema version of the table when the partition is created.
+///
+/// Nothing is changed if the partition already exists, so the node of the partition is never
+/// changed. Return the id of the partition, either inserted or existing.
+#[allow(clippy::too_many_arguments)]
 pub fn insert_partition_info(
     conn: &mut PooledConn,
     table_name: &str,
@@ -344,11 +1020,13 @@ pub fn insert_partition_info(
     node_id: u32,
     time_start: &DateTime<Utc>,
     time_end: &DateTime<Utc>,
+    schema_version: u32,
 ) -> Result<u32> {
     conn.exec_drop(
         "INSERT INTO
-            partition_info (table_name, partition_date, partition_index, node_id, time_start, time_end)
-        VALUES (:table_name, :partition_date, :partition_index, :node_id, :time_start, :time_end)",
+            partition_info (table_name, partition_date, partition_index, node_id, time_start, time_end, schema_version)
+        VALUES (:table_name, :partition_date, :partition_index, :node_id, :time_start, :time_end, :schema_version)
+        ON DUPLICATE KEY UPDATE id = id",
         params! {
             "table_name" => table_name.to_string(),
             "partition_date" => partition_date,
@@ -356,6 +1034,7 @@ pub fn insert_partition_info(
             "node_id" => node_id,
             "time_start" => time_start.format("%Y-%m-%d %H:%M:%S").to_string(),
             "time_end" => time_end.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "schema_version" => schema_version,
         },
     )?;
 
@@ -363,11 +1042,10 @@ pub fn insert_partition_info(
         "SELECT
             id
         FROM partition_info
-        WHERE table_name = '{}' AND partition_date = {} AND partition_index = {} AND node_id = {}",
+        WHERE table_name = '{}' AND partition_date = {} AND partition_index = {}",
         table_name.to_string(),
         partition_date,
-        partition_index,
-        node_id
+        partition_index
     ))? {
         Some(partition_id) => Ok(partition_id),
         None => {
@@ -376,38 +1054,40 @@ pub fn insert_partition_info(
     }
 }
 
-/// Get partition paths for a table.
+/// Get sorted partition paths of a table which overlap with `[time_start, time_end)`.
+///
+/// Partitions are pruned by `time_start` and `time_end` in `partition_info`, and the paths are
+/// ordered by `time_start`, so reading them in order gives rows in order of `SampleKey`, even
+/// if they span multiple days.
 pub fn get_table_paths_by_time(
     conn: &mut PooledConn,
     table: &str,
     time_start: &NaiveDateTime,
     time_end: &NaiveDateTime,
 ) -> Result<Vec<String>> {
-    let _partition_count_per_day = get_partition_count_per_day(conn, table)?;
-
-    let partition_indexes = conn.query_map(
-        format!(
-            "SELECT
-                t.partition_index,
-                p.partition_date
-            FROM table_info t
-            JOIN partition_info p ON t.table_name = p.table_name
-            WHERE t.table_name = '{}'
-                AND p.time_start <= '{}'
-                AND p.time_end >= '{}'
-            ",
-            table.to_string(),
-            time_start.format("%Y-%m-%d %H:%M:%S").to_string(),
-            time_end.format("%Y-%m-%d %H:%M:%S").to_string(),
-        ),
+    let partitions = conn.exec_map(
+        "SELECT
+            partition_date,
+            partition_index
+        FROM partition_info
+        WHERE table_name = :table_name
+            AND partition_status = :active
+            AND time_start < :time_end
+            AND time_end > :time_start
+        ORDER BY time_start, partition_date, partition_index
+        ",
+        params! {
+            "table_name" => table.to_string(),
+            "active" => PartitionStatus::Active as i32,
+            "time_start" => time_start.format("%Y-%m-%d %H:%M:%S").to_string(),
+            "time_end" => time_end.format("%Y-%m-%d %H:%M:%S").to_string(),
+        },
         |row: (u32, u32)| (row.0, row.1),
     )?;
 
-    let partition_paths = partition_indexes
+    let partition_paths = partitions
         .iter()
-        .map(|(index, partition_date)| {
-            format!("/tmp/droplet/tables/{}/{}/{}", table, partition_date, index)
-        })
+        .map(|(partition_date, index)| get_sorted_partition_path(table, *partition_date, *index))
         .collect();
 
     Ok(partition_paths)
@@ -433,7 +1113,7 @@ pub fn get_table_paths_by_date(
 
     let partition_paths = partition_indexes
         .iter()
-        .map(|index| format!("/tmp/droplet/tables/{}/{}/{}", table, partition_date, index))
+        .map(|index| get_sorted_partition_path(table, partition_date, *index))
         .collect();
 
     Ok(partition_paths)
@@ -449,8 +1129,10 @@ pub fn get_server_endpoint_by_partition_index(
             concat(n.node_name, ':', n.node_port) endpoint
         FROM partition_info p
         JOIN worker_node_info n ON p.node_id = n.id
-        WHERE p.table_name = '{}' AND p.partition_index = {}",
-        table, partition_index
+        WHERE p.table_name = '{}' AND p.partition_index = {} AND p.partition_status = {}",
+        table,
+        partition_index,
+        PartitionStatus::Active as i32
     ))?
     .ok_or_else(|| {
         anyhow::anyhow!(
@@ -0,0 +1,438 @@
+//! Storage of meta info.
+//!
+//! `MetaStore` covers id mapping, nodes, tables, columns and partitions. `MysqlMetaStore` is
+//! used in production, `MemoryMetaStore` keeps everything in process, which is used for tests
+//! and running a single node without `mysql`.
+use anyhow::{bail, Result};
+use log::error;
+use std::sync::Arc;
+
+use crate::data_type::DataType;
+use crate::db::memory_store::MemoryMetaStore;
+use crate::db::mysql_store::MysqlMetaStore;
+use crate::droplet::{ColumnInfo, NodeInfo, NodeStatus, PartitionInfo, TableStats};
+use crate::error_bail;
+use crate::feature_info::FeatureInfo;
+use crate::grid_sample::{KeySchema, SampleKey, MAX_KEY_COLUMNS};
+use crate::partition::PartitionScheme;
+use crate::tool::get_env_u64;
+
+/// Environment variable to select the backend of `MetaStore`, `mysql` or `memory`.
+pub const META_STORE_ENV: &str = "DROPLET_META_STORE";
+
+/// Environment variable of the min free disk size in bytes for a node to get new partitions.
+pub const MIN_FREE_DISK_SIZE_ENV: &str = "DROPLET_MIN_FREE_DISK_SIZE";
+
+/// Default min free disk size, `10GB`.
+pub const DEFAULT_MIN_FREE_DISK_SIZE: u64 = 10 * 1024 * 1024 * 1024;
+
+/// Schema version of a new table.
+pub const INITIAL_SCHEMA_VERSION: u32 = 1;
+
+pub trait MetaStore: Send + Sync {
+    /// Get key id of the key string, `None` if not found.
+    fn get_key_id(&self, key_str: &str) -> Result<Option<u32>>;
+
+    /// Get key id of the key string, insert it if not found.
+    fn get_or_insert_key_id(&self, key_str: &str) -> Result<u32>;
+
+    /// Get key ids of the key strings, insert the keys not found.
+    fn get_key_ids(&self, keys: &[String]) -> Result<Vec<u32>> {
+        keys.iter()
+            .map(|key| self.get_or_insert_key_id(key))
+            .collect()
+    }
+
+    /// Save the features of `FeatureConfig`, the data type of an existing feature must not
+    /// change. The feature names are inserted into id mapping too.
+    fn update_feature_infos(&self, feature_infos: &[FeatureInfo]) -> Result<()>;
+
+    /// Insert the names of `SampleKey` into id mapping.
+    fn insert_sample_keys(&self) -> Result<()> {
+        for name in SampleKey::get_sample_key_names() {
+            self.get_or_insert_key_id(name)?;
+        }
+
+        Ok(())
+    }
+
+    /// Register node, return the existing node id if the node name already exists.
+    fn register_node(&self, node_name: &str, node_ip: &str, node_port: u32) -> Result<u32>;
+
+    fn get_worker_node_id(&self, node_name: &str) -> Result<u32>;
+
+    /// Get node info with the last heartbeat, the latest disk usage and the partition count.
+    fn get_worker_node_info(&self, node_id: u32) -> Result<NodeInfo>;
+
+    /// List nodes with the statuses ordered by node id, all nodes if `statuses` is empty.
+    fn list_nodes(&self, statuses: &[NodeStatus]) -> Result<Vec<NodeInfo>>;
+
+    /// Save the latest used and total disk size of the node in bytes.
+    fn update_storage_info(
+        &self,
+        node_id: u32,
+        used_disk_size: u64,
+        total_disk_size: u64,
+    ) -> Result<()>;
+
+    /// Record the heartbeat of the node, `timestamp` in seconds.
+    ///
+    /// The status is decided by `get_heartbeat_status`.
+    fn update_node_heartbeat(&self, node_id: u32, status: NodeStatus, timestamp: u64)
+        -> Result<()>;
+
+    /// Update the status of nodes by the last heartbeat, all in seconds.
+    ///
+    /// Nodes without heartbeat for `unhealthy_timeout` move from `Alive` to `Unhealthy`, and
+    /// nodes without heartbeat for `offline_timeout` move to `Offline`. Only `Alive` nodes are
+    /// used for new partitions.
+    fn update_node_status(
+        &self,
+        timestamp: u64,
+        unhealthy_timeout: u64,
+        offline_timeout: u64,
+    ) -> Result<()>;
+
+    /// Insert table info and columns, the schema version of new table is
+    /// `INITIAL_SCHEMA_VERSION`.
+    ///
+    /// Existing table and columns are ignored, but the type of existing columns must be the
+    /// same, checked by `check_column_types`. New columns of existing table are added by
+    /// `alter_table`.
+    ///
+    /// `key_columns` are checked by `check_key_columns`, empty means the default key columns.
+    /// The key columns of existing table cannot be changed, nor the timestamp unit and timezone,
+    /// checked by `check_partition_scheme`.
+    fn insert_table_info(
+        &self,
+        table_name: &str,
+        partition_scheme: &PartitionScheme,
+        key_columns: &[String],
+        columns: &[ColumnInfo],
+    ) -> Result<()>;
+
+    fn is_table_exist(&self, table_name: &str) -> Result<bool>;
+
+    /// Get names of all tables, ordered by name.
+    fn list_tables(&self) -> Result<Vec<String>>;
+
+    /// Add or deprecate columns of the table, return the schema version after the change.
+    ///
+    /// New columns are appended after the existing columns. Deprecated columns in `add_columns`
+    /// are added back. Types are checked by `check_column_types`. The schema version is
+    /// increased if any column is changed, and new columns are recorded with the new version.
+    fn alter_table(
+        &self,
+        table_name: &str,
+        add_columns: &[ColumnInfo],
+        deprecate_columns: &[String],
+    ) -> Result<u32>;
+
+    /// Current schema version of the table, recorded in new partitions.
+    fn get_schema_version(&self, table_name: &str) -> Result<u32>;
+
+    /// Names of the key columns of the table in order.
+    fn get_key_columns(&self, table_name: &str) -> Result<Vec<String>>;
+
+    /// Key columns of the table with global ids.
+    ///
+    /// The ids of the default key columns are fixed, see `SampleKey::get_sample_key_pair`. Other
+    /// key columns are inserted into id mapping when the table is created, so nothing is
+    /// inserted here.
+    fn get_key_schema(&self, table_name: &str) -> Result<KeySchema> {
+        let col_ids = self
+            .get_key_columns(table_name)?
+            .iter()
+            .map(|name| -> Result<u32> {
+                if let Some((_, id)) = SampleKey::get_sample_key_pair()
+                    .iter()
+                    .find(|(key_name, _)| key_name == name)
+                {
+                    return Ok(*id);
+                }
+
+                match self.get_key_id(name)? {
+                    Some(id) => Ok(id),
+                    None => {
+                        error_bail!(
+                            "Key column not found in id mapping, table_name: {}, key_column: {}",
+                            table_name,
+                            name
+                        );
+                    }
+                }
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        KeySchema::new(col_ids)
+    }
+
+    /// Drop the table and its columns, and mark its partitions `ToDelete`.
+    ///
+    /// Return the number of partitions marked.
+    fn drop_table(&self, table_name: &str) -> Result<u32>;
+
+    /// Get statistics of the active partitions of the table.
+    fn get_table_stats(&self, table_name: &str) -> Result<TableStats>;
+
+    /// Update the row count and byte size of the partition after it is merged.
+    fn update_partition_stats(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+        row_count: u64,
+        byte_size: u64,
+    ) -> Result<()>;
+
+    fn get_partition_count_per_day(&self, table_name: &str) -> Result<u32>;
+
+    /// Partition count, timestamp unit and timezone of the table.
+    fn get_partition_scheme(&self, table_name: &str) -> Result<PartitionScheme>;
+
+    fn get_table_column_infos(&self, table_name: &str) -> Result<Vec<ColumnInfo>>;
+
+    /// Get partition infos by timestamp in the unit of the table, assign a node to the partition
+    /// if needed.
+    ///
+    /// New partition records the current schema version of the table.
+    ///
+    /// The node is the `Alive` one with the least disk usage ratio, and free disk size no less
+    /// than the min free disk size. `time_start` and `time_end` of the result are in
+    /// milliseconds.
+    fn get_partition_infos(&self, table_name: &str, timestamp: u64) -> Result<Vec<PartitionInfo>>;
+
+    /// Schema version of the active partition when it's created, `None` if the partition is not
+    /// created yet.
+    fn get_partition_schema_version(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+    ) -> Result<Option<u32>>;
+
+    /// Get sorted paths of all partitions of the date.
+    fn get_table_paths_by_date(&self, table_name: &str, partition_date: u32)
+        -> Result<Vec<String>>;
+
+    /// Get sorted partition paths which overlap with `[start_ts, end_ts)`, timestamp in the unit
+    /// of the table.
+    ///
+    /// The paths are ordered by time.
+    fn get_table_paths_by_time(
+        &self,
+        table_name: &str,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<Vec<String>>;
+
+    fn get_server_endpoint_by_partition_index(
+        &self,
+        table_name: &str,
+        partition_index: u32,
+    ) -> Result<String>;
+}
+
+/// Check the types of `columns` to be added to the table with `existing_columns`.
+///
+/// The type must be a valid `DataType` other than `None`, and a column already in the table or
+/// appearing more than once must have the same type.
+pub fn check_column_types(existing_columns: &[ColumnInfo], columns: &[ColumnInfo]) -> Result<()> {
+    for (i, column) in columns.iter().enumerate() {
+        if let Err(e) = DataType::from_column_type(column.column_type) {
+            error_bail!(
+                "Invalid column type, column_name: {}, error: {}",
+                column.column_name,
+                e
+            );
+        }
+
+        let other = existing_columns
+            .iter()
+            .chain(columns[..i].iter())
+            .find(|x| x.column_name == column.column_name);
+
+        if let Some(other) = other {
+            if other.column_type != column.column_type {
+                error_bail!(
+                    "Column type mismatch, column_name: {}, column_type: {}, other column_type: {}",
+                    column.column_name,
+                    column.column_type,
+                    other.column_type
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Names of the default key columns, `[timestamp, user_id, item_id, request_id]`.
+pub fn get_default_key_columns() -> Vec<String> {
+    SampleKey::get_sample_key_names()
+        .iter()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+/// Check the key columns of a new table, empty means the default key columns.
+///
+/// The first key column must be `timestamp`, at most `MAX_KEY_COLUMNS` columns, and no
+/// duplicated names. Names must not contain `,`, they are joined by `,` in `table_info`.
+pub fn check_key_columns(key_columns: &[String]) -> Result<()> {
+    if key_columns.is_empty() {
+        return Ok(());
+    }
+
+    if key_columns.len() > MAX_KEY_COLUMNS {
+        error_bail!(
+            "Too many key columns, max: {}, key_columns: {:?}",
+            MAX_KEY_COLUMNS,
+            key_columns
+        );
+    }
+
+    if key_columns[0] != SampleKey::get_sample_key_names()[0] {
+        error_bail!(
+            "The first key column must be timestamp, key_columns: {:?}",
+            key_columns
+        );
+    }
+
+    for (i, name) in key_columns.iter().enumerate() {
+        if name.is_empty() || name.contains(',') || key_columns[..i].contains(name) {
+            error_bail!(
+                "Invalid key column: {}, key_columns: {:?}",
+                name,
+                key_columns
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Check the partition scheme of a table to be inserted, `existing` is the scheme of the table
+/// if it exists.
+///
+/// Partitions already created depend on the timestamp unit and timezone, so they must be the
+/// same as the existing table.
+pub fn check_partition_scheme(
+    table_name: &str,
+    existing: Option<&PartitionScheme>,
+    partition_scheme: &PartitionScheme,
+) -> Result<()> {
+    partition_scheme.check()?;
+
+    if let Some(existing) = existing {
+        if existing.timestamp_unit != partition_scheme.timestamp_unit
+            || existing.tz_offset_seconds != partition_scheme.tz_offset_seconds
+        {
+            error_bail!(
+                "Timestamp unit and timezone cannot be changed, table_name: {}, scheme: {:?}, new scheme: {:?}",
+                table_name,
+                existing,
+                partition_scheme
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Get statistics of partitions, `ToDelete` partitions must be filtered before.
+pub fn get_partitions_stats<'a>(partitions: impl Iterator<Item = &'a PartitionInfo>) -> TableStats {
+    let mut stats = TableStats::default();
+
+    for partition in partitions {
+        stats.partition_count += 1;
+        stats.row_count += partition.row_count;
+        stats.byte_size += partition.byte_size;
+
+        let is_earlier = stats
+            .earliest_partition
+            .as_ref()
+            .map_or(true, |x| partition.time_start < x.time_start);
+
+        if is_earlier {
+            stats.earliest_partition = Some(partition.clone());
+        }
+
+        let is_later = stats
+            .latest_partition
+            .as_ref()
+            .map_or(true, |x| partition.time_start > x.time_start);
+
+        if is_later {
+            stats.latest_partition = Some(partition.clone());
+        }
+    }
+
+    stats
+}
+
+/// Status of the node after heartbeat.
+///
+/// The node is `Alive` once it sends heartbeat, unless it reports itself `Unhealthy` or `Offline`.
+pub fn get_heartbeat_status(reported: NodeStatus) -> NodeStatus {
+    match reported {
+        NodeStatus::Unhealthy | NodeStatus::Offline => reported,
+        _ => NodeStatus::Alive,
+    }
+}
+
+/// Read min free disk size from `DROPLET_MIN_FREE_DISK_SIZE`, `DEFAULT_MIN_FREE_DISK_SIZE` if
+/// not set.
+pub fn get_min_free_disk_size() -> Result<u64> {
+    Ok(get_env_u64(MIN_FREE_DISK_SIZE_ENV)?.unwrap_or(DEFAULT_MIN_FREE_DISK_SIZE))
+}
+
+/// Backend of `MetaStore`.
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MetaStoreType {
+    #[default]
+    Mysql,
+    Memory,
+}
+
+impl MetaStoreType {
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "mysql" => Ok(Self::Mysql),
+            "memory" => Ok(Self::Memory),
+            _ => {
+                error_bail!("Unknown meta store type: {}", s);
+            }
+        }
+    }
+
+    /// Read from `DROPLET_META_STORE`, `Mysql` if not set.
+    pub fn from_env() -> Result<Self> {
+        match std::env::var(META_STORE_ENV) {
+            Ok(s) => Self::parse(s.as_str()),
+            Err(_) => Ok(Self::default()),
+        }
+    }
+}
+
+/// Create `MetaStore` of the backend.
+///
+/// All `Memory` stores in the process share the same data, so the components in one process
+/// see the same meta info, like they share one `mysql` database.
+pub fn new_meta_store(store_type: MetaStoreType) -> Result<Arc<dyn MetaStore>> {
+    match store_type {
+        MetaStoreType::Mysql => Ok(Arc::new(
+            MysqlMetaStore::new()?.with_min_free_disk_size(get_min_free_disk_size()?),
+        )),
+        MetaStoreType::Memory => {
+            let store = MemoryMetaStore::global();
+            store.set_min_free_disk_size(get_min_free_disk_size()?);
+            Ok(store)
+        }
+    }
+}
+
+/// Create `MetaStore` of the backend selected by `DROPLET_META_STORE`.
+pub fn new_default_meta_store() -> Result<Arc<dyn MetaStore>> {
+    new_meta_store(MetaStoreType::from_env()?)
+}
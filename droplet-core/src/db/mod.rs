@@ -2,4 +2,7 @@
 
 pub mod db;
 pub mod feature;
+pub mod memory_store;
 pub mod meta_info;
+pub mod meta_store;
+pub mod mysql_store;
@@ -0,0 +1,277 @@
+//! `MetaStore` backed by `mysql`.
+use anyhow::{bail, Result};
+use chrono::{DateTime, NaiveDateTime};
+use log::error;
+
+use crate::db::db::DB;
+use crate::db::feature::update_feature_infos;
+use crate::db::meta_info::{
+    alter_table, drop_table, get_key_columns, get_key_id, get_or_insert_key_id,
+    get_partition_count_per_day, get_partition_infos, get_partition_schema_version,
+    get_partition_scheme, get_schema_version, get_server_endpoint_by_partition_index,
+    get_table_column_infos, get_table_paths_by_date, get_table_paths_by_time, get_table_stats,
+    get_worker_node_id, get_worker_node_info, insert_table_info, is_table_exist, list_nodes,
+    list_tables, register_node, update_node_heartbeat, update_node_status, update_partition_stats,
+    update_storage_info,
+};
+use crate::db::meta_store::{get_heartbeat_status, MetaStore, DEFAULT_MIN_FREE_DISK_SIZE};
+use crate::droplet::{ColumnInfo, NodeInfo, NodeStatus, PartitionInfo, TableStats};
+use crate::error_bail;
+use crate::feature_info::FeatureInfo;
+use crate::partition::PartitionScheme;
+
+/// Each method gets a connection from the pool of `DB`, and calls the functions in
+/// `db::meta_info`.
+pub struct MysqlMetaStore {
+    db: DB,
+
+    /// Nodes with less free disk size in bytes are not used for new partitions.
+    min_free_disk_size: u64,
+}
+
+impl MysqlMetaStore {
+    pub fn new() -> Result<Self> {
+        Ok(Self::with_db(DB::new()?))
+    }
+
+    pub fn with_db(db: DB) -> Self {
+        Self {
+            db,
+            min_free_disk_size: DEFAULT_MIN_FREE_DISK_SIZE,
+        }
+    }
+
+    pub fn with_min_free_disk_size(mut self, min_free_disk_size: u64) -> Self {
+        self.min_free_disk_size = min_free_disk_size;
+        self
+    }
+
+    fn get_naive_datetime(timestamp: u64) -> Result<NaiveDateTime> {
+        match DateTime::from_timestamp(timestamp as i64, 0) {
+            Some(datetime) => Ok(datetime.naive_utc()),
+            None => {
+                error_bail!("Invalid timestamp: {}", timestamp);
+            }
+        }
+    }
+}
+
+impl MetaStore for MysqlMetaStore {
+    fn get_key_id(&self, key_str: &str) -> Result<Option<u32>> {
+        let mut conn = self.db.get_conn()?;
+        Ok(get_key_id(&mut conn, key_str))
+    }
+
+    fn get_or_insert_key_id(&self, key_str: &str) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        Ok(get_or_insert_key_id(&mut conn, key_str))
+    }
+
+    fn update_feature_infos(&self, feature_infos: &[FeatureInfo]) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        update_feature_infos(&mut conn, feature_infos)
+    }
+
+    fn register_node(&self, node_name: &str, node_ip: &str, node_port: u32) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        register_node(&mut conn, node_name, node_ip, node_port)
+    }
+
+    fn get_worker_node_id(&self, node_name: &str) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        get_worker_node_id(&mut conn, node_name)
+    }
+
+    fn get_worker_node_info(&self, node_id: u32) -> Result<NodeInfo> {
+        let mut conn = self.db.get_conn()?;
+        get_worker_node_info(&mut conn, node_id)
+    }
+
+    fn list_nodes(&self, statuses: &[NodeStatus]) -> Result<Vec<NodeInfo>> {
+        let mut conn = self.db.get_conn()?;
+        list_nodes(&mut conn, statuses)
+    }
+
+    fn update_storage_info(
+        &self,
+        node_id: u32,
+        used_disk_size: u64,
+        total_disk_size: u64,
+    ) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        update_storage_info(&mut conn, node_id, used_disk_size, total_disk_size)
+    }
+
+    fn update_node_heartbeat(
+        &self,
+        node_id: u32,
+        status: NodeStatus,
+        timestamp: u64,
+    ) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        update_node_heartbeat(&mut conn, node_id, get_heartbeat_status(status), timestamp)
+    }
+
+    fn update_node_status(
+        &self,
+        timestamp: u64,
+        unhealthy_timeout: u64,
+        offline_timeout: u64,
+    ) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        update_node_status(
+            &mut conn,
+            timestamp.saturating_sub(unhealthy_timeout),
+            timestamp.saturating_sub(offline_timeout),
+        )
+    }
+
+    fn insert_table_info(
+        &self,
+        table_name: &str,
+        partition_scheme: &PartitionScheme,
+        key_columns: &[String],
+        columns: &[ColumnInfo],
+    ) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        insert_table_info(
+            &mut conn,
+            table_name,
+            partition_scheme,
+            key_columns,
+            columns,
+        )
+    }
+
+    fn is_table_exist(&self, table_name: &str) -> Result<bool> {
+        let mut conn = self.db.get_conn()?;
+        is_table_exist(&mut conn, table_name)
+    }
+
+    fn list_tables(&self) -> Result<Vec<String>> {
+        let mut conn = self.db.get_conn()?;
+        list_tables(&mut conn)
+    }
+
+    fn alter_table(
+        &self,
+        table_name: &str,
+        add_columns: &[ColumnInfo],
+        deprecate_columns: &[String],
+    ) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        alter_table(&mut conn, table_name, add_columns, deprecate_columns)
+    }
+
+    fn get_schema_version(&self, table_name: &str) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        get_schema_version(&mut conn, table_name)
+    }
+
+    fn get_key_columns(&self, table_name: &str) -> Result<Vec<String>> {
+        let mut conn = self.db.get_conn()?;
+        get_key_columns(&mut conn, table_name)
+    }
+
+    fn drop_table(&self, table_name: &str) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        drop_table(&mut conn, table_name)
+    }
+
+    fn get_table_stats(&self, table_name: &str) -> Result<TableStats> {
+        let mut conn = self.db.get_conn()?;
+        get_table_stats(&mut conn, table_name)
+    }
+
+    fn update_partition_stats(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+        row_count: u64,
+        byte_size: u64,
+    ) -> Result<()> {
+        let mut conn = self.db.get_conn()?;
+        update_partition_stats(
+            &mut conn,
+            table_name,
+            partition_date,
+            partition_index,
+            row_count,
+            byte_size,
+        )
+    }
+
+    fn get_partition_count_per_day(&self, table_name: &str) -> Result<u32> {
+        let mut conn = self.db.get_conn()?;
+        get_partition_count_per_day(&mut conn, table_name)
+    }
+
+    fn get_partition_scheme(&self, table_name: &str) -> Result<PartitionScheme> {
+        let mut conn = self.db.get_conn()?;
+        get_partition_scheme(&mut conn, table_name)
+    }
+
+    fn get_table_column_infos(&self, table_name: &str) -> Result<Vec<ColumnInfo>> {
+        let mut conn = self.db.get_conn()?;
+        get_table_column_infos(&mut conn, table_name)
+    }
+
+    fn get_partition_infos(&self, table_name: &str, timestamp: u64) -> Result<Vec<PartitionInfo>> {
+        let mut conn = self.db.get_conn()?;
+        get_partition_infos(&mut conn, table_name, timestamp, self.min_free_disk_size)
+    }
+
+    fn get_partition_schema_version(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+        partition_index: u32,
+    ) -> Result<Option<u32>> {
+        let mut conn = self.db.get_conn()?;
+        get_partition_schema_version(&mut conn, table_name, partition_date, partition_index)
+    }
+
+    fn get_table_paths_by_date(
+        &self,
+        table_name: &str,
+        partition_date: u32,
+    ) -> Result<Vec<String>> {
+        let mut conn = self.db.get_conn()?;
+        get_table_paths_by_date(&mut conn, table_name, partition_date)
+    }
+
+    fn get_table_paths_by_time(
+        &self,
+        table_name: &str,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<Vec<String>> {
+        let mut conn = self.db.get_conn()?;
+
+        // Partitions of dropped tables are not used any more.
+        if !is_table_exist(&mut conn, table_name)? {
+            return Ok(Vec::new());
+        }
+
+        // `time_start` and `time_end` in `partition_info` are in seconds, `end_ts` is rounded up
+        // so the partition starting in its last second is not missed.
+        let partition_scheme = get_partition_scheme(&mut conn, table_name)?;
+        let time_start = Self::get_naive_datetime(partition_scheme.to_seconds(start_ts))?;
+        let time_end = Self::get_naive_datetime(
+            partition_scheme
+                .to_seconds(end_ts.saturating_add(partition_scheme.units_per_second() - 1)),
+        )?;
+
+        get_table_paths_by_time(&mut conn, table_name, &time_start, &time_end)
+    }
+
+    fn get_server_endpoint_by_partition_index(
+        &self,
+        table_name: &str,
+        partition_index: u32,
+    ) -> Result<String> {
+        let mut conn = self.db.get_conn()?;
+        get_server_endpoint_by_partition_index(&mut conn, table_name, partition_index)
+    }
+}
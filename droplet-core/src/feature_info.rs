@@ -2,11 +2,12 @@ use anyhow::{bail, Result};
 use log::error;
 use serde::{Deserialize, Serialize};
 use std::str::FromStr;
-use strum::{Display, EnumCount, EnumDiscriminants, EnumString, FromRepr};
 
-use crate::db::{db::DB, feature::update_feature_infos};
+use crate::db::meta_store::MetaStore;
 use crate::error_bail;
 
+pub use crate::data_type::DataType;
+
 /// `FeatureInfo` represents the information of a feature.
 ///
 /// All data are considered as `Feature`.
@@ -84,36 +85,6 @@ impl FeatureInfo {
     }
 }
 
-/// Data type.
-#[derive(
-    Default,
-    Clone,
-    FromRepr,
-    Debug,
-    PartialEq,
-    EnumCount,
-    EnumDiscriminants,
-    EnumString,
-    Deserialize,
-    Serialize,
-    Display,
-)]
-#[repr(u8)]
-pub enum DataType {
-    /// `u64`
-    #[default]
-    U64,
-
-    /// `f32`
-    F32,
-
-    /// `u64` list
-    U64List,
-
-    /// `f32` list
-    F32List,
-}
-
 /// Feature config in text file format.
 ///
 /// We use a self-defined config format to describe the feature information. Each line
@@ -167,8 +138,12 @@ impl FeatureConfig {
         Ok(Self::new(feature_infos))
     }
 
-    /// Write feature info to database.
-    pub fn write_to_db(&self, db: &DB) -> Result<()> {
-        update_feature_infos(db, &self.feature_infos)
+    pub fn feature_infos(&self) -> &[FeatureInfo] {
+        &self.feature_infos
+    }
+
+    /// Write feature info to `MetaStore`.
+    pub fn write_to_store(&self, store: &dyn MetaStore) -> Result<()> {
+        store.update_feature_infos(&self.feature_infos)
     }
 }
@@ -2,10 +2,13 @@ use anyhow::{bail, Result};
 use likely_stable::unlikely;
 use log::error;
 use std::cmp::{Eq, Ord, Ordering, PartialEq, PartialOrd};
+use std::sync::Arc;
 
 use gridbuffer::core::gridbuffer::{GridBuffer, GridCell};
 
+use crate::data_type::{self, encode_bool, encode_f64, encode_i64, encode_strings};
 use crate::error_bail;
+use crate::partition::PartitionScheme;
 use crate::tool::is_keys_equal;
 use crate::window_heap::HeapOrderKey;
 
@@ -16,7 +19,7 @@ use crate::window_heap::HeapOrderKey;
 /// and then get the ids from the table.
 ///
 /// Notice: for historical reason, the name of `request_id` is `llsid` in `SimpleFeatures` proto.
-#[derive(Eq)]
+#[derive(Default, Clone, Copy, Debug, Eq)]
 pub struct SampleKey {
     pub timestamp: u64,
     pub user_id: u64,
@@ -67,6 +70,39 @@ impl SampleKey {
     pub fn is_sample_key_ids(col_ids: &[u32]) -> bool {
         is_keys_equal(SampleKey::get_sample_key_ids(), col_ids)
     }
+
+    /// Whether the first four columns of the `GridBuffer` are the sample key ids.
+    pub fn is_valid_gridbuffer(gridbuffer: &GridBuffer) -> bool {
+        if unlikely(gridbuffer.num_cols() < 4) {
+            return false;
+        }
+
+        Self::is_sample_key_ids(&gridbuffer.col_ids()[0..4])
+    }
+
+    /// Get the `SampleKey` of the row, the first four columns must be the sample key ids.
+    ///
+    /// Missing values are `0`.
+    #[inline]
+    pub fn from_gridbuffer(gridbuffer: &GridBuffer, row: usize) -> Self {
+        Self::from_key_cols(gridbuffer, row, MAX_KEY_COLUMNS)
+    }
+
+    /// Get the `SampleKey` of the row from the first `num_key_cols` columns.
+    ///
+    /// The fields are filled by position, fields after `num_key_cols` and missing values are `0`.
+    #[inline]
+    pub fn from_key_cols(gridbuffer: &GridBuffer, row: usize, num_key_cols: usize) -> Self {
+        let get = |col: usize| {
+            if col < num_key_cols {
+                gridbuffer.get_u64(row, col).unwrap_or(0)
+            } else {
+                0
+            }
+        };
+
+        Self::new(get(0), get(1), get(2), get(3))
+    }
 }
 
 impl PartialEq for SampleKey {
@@ -94,20 +130,113 @@ impl PartialOrd for SampleKey {
     }
 }
 
-/// `GridRow` is a pointer to a row in a `GridBuffer`.
+/// The max number of key columns, `SampleKey` has four fields.
+pub const MAX_KEY_COLUMNS: usize = 4;
+
+/// The key columns of a table, declared in meta when the table is created.
+///
+/// The key columns are stored as the first columns of each `GridBuffer` in order, and rows are
+/// sorted by them. The first key column must be `timestamp`, which is used for partitioning and
+/// time range pruning. At most `MAX_KEY_COLUMNS` columns are allowed.
+///
+/// The values of the key columns are mapped to `SampleKey` by position, e.g. with key columns
+/// `[timestamp, user_id]`, `SampleKey.user_id` is the value of `user_id`, and `item_id`,
+/// `request_id` are always `0`.
+///
+/// The default is `[timestamp, user_id, item_id, request_id]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySchema {
+    /// Global ids of the key columns.
+    col_ids: Vec<u32>,
+}
+
+impl Default for KeySchema {
+    fn default() -> Self {
+        Self {
+            col_ids: SampleKey::get_sample_key_ids().to_vec(),
+        }
+    }
+}
+
+impl KeySchema {
+    pub fn new(col_ids: Vec<u32>) -> Result<Self> {
+        if col_ids.is_empty() || col_ids.len() > MAX_KEY_COLUMNS {
+            error_bail!(
+                "Invalid key columns, must be 1 to {} columns, col_ids: {:?}",
+                MAX_KEY_COLUMNS,
+                col_ids
+            );
+        }
+
+        if col_ids[0] != SampleKey::get_sample_key_ids()[0] {
+            error_bail!(
+                "Invalid key columns, the first key column must be timestamp, col_ids: {:?}",
+                col_ids
+            );
+        }
+
+        for (i, col_id) in col_ids.iter().enumerate() {
+            if col_ids[..i].contains(col_id) {
+                error_bail!("Duplicated key column, col_ids: {:?}", col_ids);
+            }
+        }
+
+        Ok(Self { col_ids })
+    }
+
+    /// Construct from the `key_column_ids` in requests, empty means the default key columns.
+    pub fn from_col_ids(col_ids: &[u32]) -> Result<Self> {
+        if col_ids.is_empty() {
+            Ok(Self::default())
+        } else {
+            Self::new(col_ids.to_vec())
+        }
+    }
+
+    #[inline]
+    pub fn col_ids(&self) -> &[u32] {
+        &self.col_ids
+    }
+
+    #[inline]
+    pub fn num_cols(&self) -> usize {
+        self.col_ids.len()
+    }
+
+    /// Whether the first columns of the `GridBuffer` are the key columns.
+    pub fn is_valid_gridbuffer(&self, gridbuffer: &GridBuffer) -> bool {
+        let col_ids = gridbuffer.col_ids();
+
+        if unlikely(col_ids.len() < self.col_ids.len()) {
+            return false;
+        }
+
+        col_ids[..self.col_ids.len()] == self.col_ids[..]
+    }
+
+    /// Get the `SampleKey` of the row, the first columns must be the key columns.
+    #[inline]
+    pub fn get_sample_key(&self, gridbuffer: &GridBuffer, row: usize) -> SampleKey {
+        SampleKey::from_key_cols(gridbuffer, row, self.col_ids.len())
+    }
+}
+
+/// `GridRow` is a reference to a row in a `GridBuffer`.
 ///
-/// It encapsulates a `GridRow` and provide `SampleKey` for easy access.
+/// It encapsulates a `GridRow` and provide `SampleKey` for easy access. The `GridBuffer` is
+/// shared by `Arc`, so the row is valid as long as it is alive, even if the `GridBuffer` is
+/// replaced by the reader.
 #[derive(Clone)]
 pub struct GridRow {
-    /// Reference to the `GridBuffer`.
-    gridbuffer_ptr: *const GridBuffer,
+    /// The shared `GridBuffer`.
+    gridbuffer: Arc<GridBuffer>,
 
     /// The index of the row.
     row: usize,
-}
 
-unsafe impl Sync for GridRow {}
-unsafe impl Send for GridRow {}
+    /// The number of key columns, see `KeySchema`.
+    num_key_cols: usize,
+}
 
 impl HeapOrderKey for GridRow {
     type Key = SampleKey;
@@ -139,38 +268,43 @@ impl PartialOrd for GridRow {
 
 impl GridRow {
     #[inline]
-    pub fn new(gridbuffer_ptr: *const GridBuffer, row: usize) -> Self {
+    pub fn new(gridbuffer: Arc<GridBuffer>, row: usize) -> Self {
+        Self::with_num_key_cols(gridbuffer, row, MAX_KEY_COLUMNS)
+    }
+
+    /// Construct a `GridRow` whose `SampleKey` is read from the first `num_key_cols` columns.
+    #[inline]
+    pub fn with_num_key_cols(gridbuffer: Arc<GridBuffer>, row: usize, num_key_cols: usize) -> Self {
         Self {
-            gridbuffer_ptr,
+            gridbuffer,
             row,
+            num_key_cols,
         }
     }
 
     #[inline]
     pub fn get_gridbuffer(&self) -> &GridBuffer {
-        unsafe { &*self.gridbuffer_ptr }
+        &self.gridbuffer
     }
 
-    /// The first four columns must be the sample key ids.
-    pub fn is_valid_sample(&self) -> bool {
-        let gridbuffer = self.get_gridbuffer();
+    #[inline]
+    pub fn get_shared_gridbuffer(&self) -> &Arc<GridBuffer> {
+        &self.gridbuffer
+    }
 
-        if unlikely(gridbuffer.num_cols() < 4) {
-            return false;
-        }
+    #[inline]
+    pub fn row(&self) -> usize {
+        self.row
+    }
 
-        SampleKey::is_sample_key_ids(&gridbuffer.col_ids()[0..4])
+    /// The first four columns must be the sample key ids.
+    pub fn is_valid_sample(&self) -> bool {
+        SampleKey::is_valid_gridbuffer(&self.gridbuffer)
     }
 
+    #[inline]
     pub fn get_sample_key(&self) -> SampleKey {
-        let gridbuffer = self.get_gridbuffer();
-
-        SampleKey::new(
-            gridbuffer.get_u64(self.row, 0).unwrap_or(0),
-            gridbuffer.get_u64(self.row, 1).unwrap_or(0),
-            gridbuffer.get_u64(self.row, 2).unwrap_or(0),
-            gridbuffer.get_u64(self.row, 3).unwrap_or(0),
-        )
+        SampleKey::from_key_cols(&self.gridbuffer, self.row, self.num_key_cols)
     }
 
     #[inline]
@@ -202,6 +336,26 @@ impl GridRow {
         let gridbuffer = self.get_gridbuffer();
         gridbuffer.get_cell(self.row, col)
     }
+
+    #[inline]
+    pub fn get_i64(&self, col: usize) -> Option<i64> {
+        data_type::get_i64(&self.gridbuffer, self.row, col)
+    }
+
+    #[inline]
+    pub fn get_f64(&self, col: usize) -> Option<f64> {
+        data_type::get_f64(&self.gridbuffer, self.row, col)
+    }
+
+    #[inline]
+    pub fn get_bool(&self, col: usize) -> Option<bool> {
+        data_type::get_bool(&self.gridbuffer, self.row, col)
+    }
+
+    #[inline]
+    pub fn get_string(&self, col: usize) -> Option<String> {
+        data_type::get_string(&self.gridbuffer, self.row, col)
+    }
 }
 
 /// A collection of `SampleRow`s.
@@ -252,30 +406,7 @@ impl GridRows {
 
         for (i, row) in self.rows.iter().enumerate() {
             for j in 0..num_cols {
-                let row_index = row.row;
-
-                // Be careful, we must use `push_u64_values`, cannot use `push_cell`, because the data is in `u64_values` or `f32_values`,
-                // the `cell` just contains the index.
-                match row.get_cell(j) {
-                    Some(cell) => match cell {
-                        GridCell::U64Cell(_cell) => {
-                            gridbuffer.push_u64_values(
-                                i,
-                                j,
-                                row.get_gridbuffer().get_u64_values(row_index, j),
-                            );
-                        }
-                        GridCell::F32Cell(_cell) => {
-                            gridbuffer.push_f32_values(
-                                i,
-                                j,
-                                row.get_gridbuffer().get_f32_values(row_index, j),
-                            );
-                        }
-                        _ => {}
-                    },
-                    _ => {}
-                }
+                copy_cell(row.get_gridbuffer(), row.row, j, &mut gridbuffer, i, j);
             }
         }
 
@@ -283,6 +414,133 @@ impl GridRows {
     }
 }
 
+/// Copy the values of a cell from `src` to `dst`.
+///
+/// Be careful, we must use `push_u64_values`, cannot use `push_cell`, because the data is in
+/// `u64_values` or `f32_values`, the `cell` just contains the index.
+#[inline]
+fn copy_cell(
+    src: &GridBuffer,
+    src_row: usize,
+    src_col: usize,
+    dst: &mut GridBuffer,
+    dst_row: usize,
+    dst_col: usize,
+) {
+    match src.get_cell(src_row, src_col) {
+        Some(GridCell::U64Cell(_cell)) => {
+            dst.push_u64_values(dst_row, dst_col, src.get_u64_values(src_row, src_col));
+        }
+        Some(GridCell::F32Cell(_cell)) => {
+            dst.push_f32_values(dst_row, dst_col, src.get_f32_values(src_row, src_col));
+        }
+        _ => {}
+    }
+}
+
+/// Copy `rows` of the `GridBuffer` in order to a new `GridBuffer` with the same `col_ids`.
+pub fn select_gridbuffer_rows(gridbuffer: &GridBuffer, rows: &[usize]) -> GridBuffer {
+    let mut res = GridBuffer::new_with_num_rows_col_ids_hash(
+        rows.len(),
+        gridbuffer.col_ids().clone(),
+        gridbuffer.col_ids_hash(),
+    );
+
+    for (dst_row, row) in rows.iter().enumerate() {
+        for col in 0..gridbuffer.num_cols() {
+            copy_cell(gridbuffer, *row, col, &mut res, dst_row, col);
+        }
+    }
+
+    res
+}
+
+/// Split the `GridBuffer` by the partitions of the timestamp of rows, computed by the
+/// `PartitionScheme` of the table.
+///
+/// Returns (`partition_date`, `partition_index`) and the rows of each partition, in the order of
+/// the first row of each partition. All rows of one partition are in one `GridBuffer` with their
+/// original order, even if they are not consecutive, e.g. `WindowHeap` only sorts rows within a
+/// window, so a later `GridBuffer` may still go back and forth across the partition boundary.
+/// The `GridBuffer` is not copied if all rows are in the same partition.
+pub fn split_gridbuffer_by_partition(
+    gridbuffer: GridBuffer,
+    partition_scheme: &PartitionScheme,
+) -> Result<Vec<((u32, u32), GridBuffer)>> {
+    // Partition and its rows. There are only a few partitions in one `GridBuffer`, so linear
+    // search is enough.
+    let mut groups: Vec<((u32, u32), Vec<usize>)> = Vec::new();
+
+    for row in 0..gridbuffer.num_rows() {
+        let timestamp = SampleKey::from_key_cols(&gridbuffer, row, 1).timestamp;
+        let partition = partition_scheme.get_partition(timestamp)?;
+
+        match groups.iter_mut().find(|(p, _)| *p == partition) {
+            Some((_, rows)) => rows.push(row),
+            None => groups.push((partition, vec![row])),
+        }
+    }
+
+    if groups.len() == 1 {
+        return Ok(vec![(groups[0].0, gridbuffer)]);
+    }
+
+    Ok(groups
+        .iter()
+        .map(|(partition, rows)| (*partition, select_gridbuffer_rows(&gridbuffer, rows)))
+        .collect())
+}
+
+/// Get the column ids kept by projection.
+///
+/// The key columns are always kept as the first columns, followed by `col_ids` in order.
+/// Duplicated ids are removed.
+pub fn get_projection_col_ids(key_schema: &KeySchema, col_ids: &[u32]) -> Vec<u32> {
+    let mut res = key_schema.col_ids().to_vec();
+
+    for col_id in col_ids.iter() {
+        if !res.contains(col_id) {
+            res.push(*col_id);
+        }
+    }
+
+    res
+}
+
+/// Project `gridbuffer` to the key columns and `col_ids`, copy the values of the kept columns
+/// to a new `GridBuffer`.
+///
+/// Columns in `col_ids` but not in `gridbuffer` are skipped, the reader decides how to handle
+/// them.
+pub fn project_gridbuffer(
+    gridbuffer: &GridBuffer,
+    key_schema: &KeySchema,
+    col_ids: &[u32],
+) -> Result<GridBuffer> {
+    if unlikely(!key_schema.is_valid_gridbuffer(gridbuffer)) {
+        error_bail!(
+            "Invalid gridbuffer, first columns are not key columns: {:?}",
+            key_schema.col_ids()
+        );
+    }
+
+    let (src_cols, dst_col_ids): (Vec<usize>, Vec<u32>) =
+        get_projection_col_ids(key_schema, col_ids)
+            .into_iter()
+            .filter_map(|col_id| gridbuffer.get_col_by_id(col_id).map(|col| (col, col_id)))
+            .unzip();
+
+    let mut res = GridBuffer::new_with_num_rows_col_ids(gridbuffer.num_rows(), dst_col_ids);
+
+    for row in 0..gridbuffer.num_rows() {
+        for (dst_col, src_col) in src_cols.iter().enumerate() {
+            copy_cell(gridbuffer, row, *src_col, &mut res, row, dst_col);
+        }
+    }
+
+    Ok(res)
+}
+
 /// GridSample encapsulates `GridBuffer` with some important information of data.
 ///
 /// It contains the following parts
@@ -296,10 +554,14 @@ impl GridRows {
 /// 4. request_id.
 ///
 /// The postion of key information in the row is fixed, they are stored in the first 4 columns
-/// of each row in the `GridBuffer`.
+/// of each row in the `GridBuffer`. Tables with other key columns are constructed with
+/// `KeySchema`, see `new_with_key_schema`.
 pub struct GridSample {
     /// The underlying data structure to store the data.
     pub gridbuffer: GridBuffer,
+
+    /// The number of key columns at the front of `gridbuffer`.
+    num_key_cols: usize,
 }
 
 impl GridSample {
@@ -307,7 +569,15 @@ impl GridSample {
     ///
     /// The sample key ids are fixed and added as the first four columns automatically.
     pub fn new(num_rows: usize, cols: &Vec<u32>) -> Self {
-        let all_cols = SampleKey::get_sample_key_ids()
+        Self::new_with_key_schema(num_rows, &KeySchema::default(), cols)
+    }
+
+    /// Construct a new `GridSample` with the key columns of `key_schema`.
+    ///
+    /// The key columns are added as the first columns automatically.
+    pub fn new_with_key_schema(num_rows: usize, key_schema: &KeySchema, cols: &Vec<u32>) -> Self {
+        let all_cols = key_schema
+            .col_ids()
             .iter()
             .chain(cols.iter())
             .cloned()
@@ -315,74 +585,94 @@ impl GridSample {
 
         let gridbuffer = GridBuffer::new_with_num_rows_col_ids(num_rows, all_cols);
 
-        Self { gridbuffer }
+        Self {
+            gridbuffer,
+            num_key_cols: key_schema.num_cols(),
+        }
     }
 
     /// Construct a new `GridSample` from a `GridBuffer`.
     ///
     /// The first four columns of `gridbuffer` must be the sample key ids.
     pub fn from_gridbuffer(gridbuffer: GridBuffer) -> Result<Self> {
-        if unlikely(!SampleKey::is_sample_key_ids(gridbuffer.col_ids())) {
-            error_bail!("Invalid gridbuffer, first four columns are not sample key ids");
+        Self::from_gridbuffer_with_key_schema(gridbuffer, &KeySchema::default())
+    }
+
+    /// Construct a new `GridSample` from a `GridBuffer`.
+    ///
+    /// The first columns of `gridbuffer` must be the key columns of `key_schema`.
+    pub fn from_gridbuffer_with_key_schema(
+        gridbuffer: GridBuffer,
+        key_schema: &KeySchema,
+    ) -> Result<Self> {
+        if unlikely(!key_schema.is_valid_gridbuffer(&gridbuffer)) {
+            error_bail!(
+                "Invalid gridbuffer, first columns are not key columns: {:?}",
+                key_schema.col_ids()
+            );
         }
 
-        Ok(Self { gridbuffer })
+        Ok(Self {
+            gridbuffer,
+            num_key_cols: key_schema.num_cols(),
+        })
     }
 
     /// Set the sample key of the row.
+    ///
+    /// Only the fields of the key columns are written.
     #[inline]
     pub fn set_sample_key(&mut self, row: usize, sample_key: &SampleKey) {
-        self.gridbuffer.push_u64(row, 0, sample_key.timestamp);
-        self.gridbuffer.push_u64(row, 1, sample_key.user_id);
-        self.gridbuffer.push_u64(row, 2, sample_key.item_id);
-        self.gridbuffer.push_u64(row, 3, sample_key.request_id);
+        let values = [
+            sample_key.timestamp,
+            sample_key.user_id,
+            sample_key.item_id,
+            sample_key.request_id,
+        ];
+
+        for (col, value) in values.iter().take(self.num_key_cols).enumerate() {
+            self.gridbuffer.push_u64(row, col, *value);
+        }
     }
 
     /// Get the sample key of the row.
     #[inline]
     pub fn get_sample_key(&self, row: usize) -> SampleKey {
-        SampleKey::new(
-            self.gridbuffer.get_u64(row, 0).unwrap_or(0),
-            self.gridbuffer.get_u64(row, 1).unwrap_or(0),
-            self.gridbuffer.get_u64(row, 2).unwrap_or(0),
-            self.gridbuffer.get_u64(row, 3).unwrap_or(0),
-        )
+        SampleKey::from_key_cols(&self.gridbuffer, row, self.num_key_cols)
     }
 
     /// Get timestamp of the row.
     #[inline]
     pub fn get_timestamp(&self, row: usize) -> u64 {
-        match self.gridbuffer.get_u64(row, 0) {
-            Some(timestamp) => timestamp,
-            None => 0,
-        }
+        self.get_key_value(row, 0)
     }
 
     /// Get user_id of the row.
     #[inline]
     pub fn get_user_id(&self, row: usize) -> u64 {
-        match self.gridbuffer.get_u64(row, 1) {
-            Some(user_id) => user_id,
-            None => 0,
-        }
+        self.get_key_value(row, 1)
     }
 
     /// Get item_id of the row.
     #[inline]
     pub fn get_item_id(&self, row: usize) -> u64 {
-        match self.gridbuffer.get_u64(row, 2) {
-            Some(item_id) => item_id,
-            None => 0,
-        }
+        self.get_key_value(row, 2)
     }
 
     /// Get request_id of the row.
     #[inline]
     pub fn get_request_id(&self, row: usize) -> u64 {
-        match self.gridbuffer.get_u64(row, 3) {
-            Some(request_id) => request_id,
-            None => 0,
+        self.get_key_value(row, 3)
+    }
+
+    /// Get the value of the `col`-th key column, `0` if it's not a key column or missing.
+    #[inline]
+    fn get_key_value(&self, row: usize, col: usize) -> u64 {
+        if col >= self.num_key_cols {
+            return 0;
         }
+
+        self.gridbuffer.get_u64(row, col).unwrap_or(0)
     }
 
     /// Insert u64 value into the grid buffer.
@@ -443,31 +733,313 @@ impl GridSample {
         self.gridbuffer.push_f32(row, col, value)
     }
 
+    /// Insert an `i64` value, `I32` is inserted as `i64` too.
+    ///
+    /// Values other than `u64` and `f32` are encoded into `u64`, see `data_type`.
+    #[inline]
+    pub fn push_i64(&mut self, row: usize, col: usize, value: i64) {
+        self.gridbuffer.push_u64(row, col, encode_i64(value))
+    }
+
+    #[inline]
+    pub fn push_i64_values(&mut self, row: usize, col: usize, values: &[i64]) {
+        let values = values.iter().map(|x| encode_i64(*x)).collect::<Vec<_>>();
+        self.gridbuffer.push_u64_values(row, col, &values)
+    }
+
+    #[inline]
+    pub fn push_f64(&mut self, row: usize, col: usize, value: f64) {
+        self.gridbuffer.push_u64(row, col, encode_f64(value))
+    }
+
+    #[inline]
+    pub fn push_f64_values(&mut self, row: usize, col: usize, values: &[f64]) {
+        let values = values.iter().map(|x| encode_f64(*x)).collect::<Vec<_>>();
+        self.gridbuffer.push_u64_values(row, col, &values)
+    }
+
+    #[inline]
+    pub fn push_bool(&mut self, row: usize, col: usize, value: bool) {
+        self.gridbuffer.push_u64(row, col, encode_bool(value))
+    }
+
+    #[inline]
+    pub fn push_bool_values(&mut self, row: usize, col: usize, values: &[bool]) {
+        let values = values.iter().map(|x| encode_bool(*x)).collect::<Vec<_>>();
+        self.gridbuffer.push_u64_values(row, col, &values)
+    }
+
+    #[inline]
+    pub fn push_string(&mut self, row: usize, col: usize, value: &str) {
+        self.gridbuffer
+            .push_u64_values(row, col, &encode_strings(&[value]))
+    }
+
+    #[inline]
+    pub fn push_string_values<S: AsRef<str>>(&mut self, row: usize, col: usize, values: &[S]) {
+        self.gridbuffer
+            .push_u64_values(row, col, &encode_strings(values))
+    }
+
     /// Sort the rows by `SampleKey`.
     pub fn sort_rows_by_sample_key(&mut self) {
         let u64_ptr = self.gridbuffer.u64_values().as_ptr();
         let u64_len = self.gridbuffer.u64_values().len();
 
+        let num_key_cols = self.num_key_cols;
+
         self.gridbuffer.sort_rows_by(|k: &Vec<GridCell>| {
-            if unlikely(k.len() < 4) {
+            if unlikely(k.len() < num_key_cols) {
                 error!("Invalid sample key, len: {:?}", k.len());
                 return SampleKey::new(0, 0, 0, 0);
             }
 
-            let timestamp = k[0].get_u64(u64_ptr, u64_len);
-            let user_id = k[1].get_u64(u64_ptr, u64_len);
-            let item_id = k[2].get_u64(u64_ptr, u64_len);
-            let request_id = k[3].get_u64(u64_ptr, u64_len);
+            let mut values = [0; MAX_KEY_COLUMNS];
 
-            match (timestamp, user_id, item_id, request_id) {
-                (Some(timestamp), Some(user_id), Some(item_id), Some(request_id)) => {
-                    SampleKey::new(timestamp, user_id, item_id, request_id)
-                }
-                _ => {
-                    error!("Invalid sample key");
-                    SampleKey::new(0, 0, 0, 0)
+            for (i, value) in values.iter_mut().take(num_key_cols).enumerate() {
+                match k[i].get_u64(u64_ptr, u64_len) {
+                    Some(v) => *value = v,
+                    None => {
+                        error!("Invalid sample key");
+                        return SampleKey::new(0, 0, 0, 0);
+                    }
                 }
             }
+
+            SampleKey::new(values[0], values[1], values[2], values[3])
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_gridbuffer() -> Result<()> {
+        let mut sample = GridSample::new(2, &vec![100, 101, 102]);
+
+        for row in 0..2 {
+            sample.set_sample_key(row, &SampleKey::new(row as u64, 1, 2, 3));
+            sample.push_u64_values(row, 4, &[row as u64, 10]);
+            sample.push_f32(row, 5, 0.5);
+            sample.push_u64(row, 6, 7);
+        }
+
+        // Sample key ids are ignored, missing ids are skipped.
+        let res = project_gridbuffer(
+            &sample.gridbuffer,
+            &KeySchema::default(),
+            &[101, 2, 100, 103],
+        )?;
+
+        assert_eq!(res.num_rows(), 2);
+        assert_eq!(res.col_ids(), &vec![2, 4, 5, 6, 101, 100]);
+
+        for row in 0..2 {
+            assert_eq!(
+                SampleKey::from_gridbuffer(&res, row),
+                SampleKey::new(row as u64, 1, 2, 3)
+            );
+            assert_eq!(res.get_f32(row, 4), Some(0.5));
+            assert_eq!(res.get_u64_values(row, 5), &[row as u64, 10]);
+        }
+
+        assert!(project_gridbuffer(
+            &GridBuffer::new_with_num_rows_col_ids(1, vec![100]),
+            &KeySchema::default(),
+            &[]
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_gridbuffer_by_partition() -> Result<()> {
+        // 2024-10-11 00:00:00 UTC.
+        let midnight = 1728604800;
+
+        let new_gridbuffer = |timestamps: &[u64]| {
+            let mut sample = GridSample::new(timestamps.len(), &vec![100]);
+
+            for (row, timestamp) in timestamps.iter().enumerate() {
+                sample.set_sample_key(row, &SampleKey::new(*timestamp, row as u64, 2, 3));
+                sample.push_f32(row, 4, row as f32);
+            }
+
+            sample.gridbuffer
+        };
+
+        // Rows of the same partition are not split.
+        let res = split_gridbuffer_by_partition(
+            new_gridbuffer(&[midnight, midnight + 10]),
+            &PartitionScheme::new(24),
+        )?;
+        assert_eq!(res.len(), 1);
+        assert_eq!(res[0].0, (20241011, 0));
+        assert_eq!(res[0].1.num_rows(), 2);
+
+        // Day boundary.
+        let timestamps = [midnight - 2, midnight - 1, midnight, midnight + 1];
+        let res =
+            split_gridbuffer_by_partition(new_gridbuffer(&timestamps), &PartitionScheme::new(24))?;
+
+        assert_eq!(
+            res.iter()
+                .map(|(p, gb)| (*p, gb.num_rows()))
+                .collect::<Vec<_>>(),
+            vec![((20241010, 23), 2), ((20241011, 0), 2)]
+        );
+
+        // Values and `col_ids` are copied.
+        let gridbuffer = &res[1].1;
+        assert_eq!(gridbuffer.col_ids(), &vec![2, 4, 5, 6, 100]);
+        assert_eq!(
+            SampleKey::from_gridbuffer(gridbuffer, 1),
+            SampleKey::new(midnight + 1, 3, 2, 3)
+        );
+        assert_eq!(gridbuffer.get_f32(1, 4), Some(3.0));
+
+        // Partitions of 12342 seconds, the last one is longer.
+        let timestamps = [
+            midnight + 12341,
+            midnight + 12342,
+            midnight + 12343,
+            midnight + 12342 * 2,
+            midnight + 86399,
+            midnight + 86400,
+        ];
+        let res =
+            split_gridbuffer_by_partition(new_gridbuffer(&timestamps), &PartitionScheme::new(7))?;
+
+        assert_eq!(
+            res.iter()
+                .map(|(p, gb)| (*p, gb.num_rows()))
+                .collect::<Vec<_>>(),
+            vec![
+                ((20241011, 0), 1),
+                ((20241011, 1), 2),
+                ((20241011, 2), 1),
+                ((20241011, 6), 1),
+                ((20241012, 0), 1)
+            ]
+        );
+
+        // Rows are not monotone around the boundary, each partition still gets one `GridBuffer`.
+        let timestamps = [
+            midnight - 1,
+            midnight,
+            midnight - 2,
+            midnight + 1,
+            midnight - 3,
+        ];
+        let res =
+            split_gridbuffer_by_partition(new_gridbuffer(&timestamps), &PartitionScheme::new(24))?;
+
+        assert_eq!(
+            res.iter()
+                .map(|(p, gb)| (*p, gb.num_rows()))
+                .collect::<Vec<_>>(),
+            vec![((20241010, 23), 3), ((20241011, 0), 2)]
+        );
+
+        // Rows keep their original order in each partition.
+        let values = res
+            .iter()
+            .map(|(_, gb)| {
+                (0..gb.num_rows())
+                    .map(|row| gb.get_f32(row, 4).unwrap())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![vec![0.0, 2.0, 4.0], vec![1.0, 3.0]]);
+
+        assert!(split_gridbuffer_by_partition(
+            new_gridbuffer(&[midnight]),
+            &PartitionScheme::new(0)
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_typed_values() -> Result<()> {
+        let mut sample = GridSample::new(1, &vec![100, 101, 102, 103, 104]);
+
+        sample.set_sample_key(0, &SampleKey::new(1, 2, 3, 4));
+        sample.push_i64(0, 4, -7);
+        sample.push_f64(0, 5, 0.125);
+        sample.push_bool(0, 6, true);
+        sample.push_string(0, 7, "droplet");
+        sample.push_string_values(0, 8, &["a", "", "bc"]);
+
+        // Encoded values are kept after sorting and projection.
+        sample.sort_rows_by_sample_key();
+        let gridbuffer = project_gridbuffer(
+            &sample.gridbuffer,
+            &KeySchema::default(),
+            &[100, 101, 102, 103, 104],
+        )?;
+
+        let row = GridRow::new(Arc::new(gridbuffer), 0);
+
+        assert_eq!(row.get_i64(4), Some(-7));
+        assert_eq!(row.get_f64(5), Some(0.125));
+        assert_eq!(row.get_bool(6), Some(true));
+        assert_eq!(row.get_string(7), Some("droplet".to_string()));
+        assert_eq!(
+            data_type::get_string_values(row.get_gridbuffer(), 0, 8)?,
+            vec!["a", "", "bc"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_key_schema() -> Result<()> {
+        assert_eq!(KeySchema::from_col_ids(&[])?, KeySchema::default());
+        assert!(KeySchema::new(vec![]).is_err());
+        assert!(KeySchema::new(vec![4, 2]).is_err());
+        assert!(KeySchema::new(vec![2, 4, 4]).is_err());
+        assert!(KeySchema::new(vec![2, 4, 5, 6, 7]).is_err());
+
+        // Key columns `[timestamp, 100]`.
+        let key_schema = KeySchema::new(vec![2, 100])?;
+        let mut sample = GridSample::new_with_key_schema(3, &key_schema, &vec![4]);
+
+        assert_eq!(sample.gridbuffer.col_ids(), &vec![2, 100, 4]);
+        assert!(key_schema.is_valid_gridbuffer(&sample.gridbuffer));
+        assert!(!SampleKey::is_valid_gridbuffer(&sample.gridbuffer));
+
+        for (row, (timestamp, value)) in [(2, 1), (1, 9), (1, 3)].iter().enumerate() {
+            sample.set_sample_key(row, &SampleKey::new(*timestamp, *value, 0, 0));
+            sample.push_u64(row, 2, row as u64);
+        }
+
+        sample.sort_rows_by_sample_key();
+
+        let keys = (0..3)
+            .map(|row| key_schema.get_sample_key(&sample.gridbuffer, row))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            keys,
+            vec![
+                SampleKey::new(1, 3, 0, 0),
+                SampleKey::new(1, 9, 0, 0),
+                SampleKey::new(2, 1, 0, 0)
+            ]
+        );
+
+        // Non-key column `4` is not part of the key.
+        assert_eq!(sample.get_item_id(0), 0);
+        assert_eq!(sample.gridbuffer.get_u64(0, 2), Some(2));
+
+        let res = project_gridbuffer(&sample.gridbuffer, &key_schema, &[])?;
+        assert_eq!(res.col_ids(), &vec![2, 100]);
+
+        Ok(())
+    }
+}
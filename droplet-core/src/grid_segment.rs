@@ -0,0 +1,905 @@
+//! Binary segment file format for `GridBuffer`s.
+//!
+//! Partition files used to store one `GridBuffer::to_base64()` per line. `base64` adds about
+//! 33% size and a full decode on every read, so partition files are now stored as binary
+//! segments.
+//!
+//! The layout of a segment file is as follows, all integers are little endian:
+//!
+//! ```text
+//! | magic: 8 bytes | version: u32 |
+//! | len: u32 | GridBuffer::to_bytes() |   <- block 0
+//! | len: u32 | GridBuffer::to_bytes() |   <- block 1
+//! ...
+//! | marker: u32::MAX |
+//! | BlockIndexEntry | BlockIndexEntry | ... |   <- one entry for each block, since version 2
+//! | num_blocks: u64 | num_rows: u64 | index_offset: u64 | end magic: 8 bytes |
+//! ```
+//!
+//! Version 1 has no block index, and its trailer is `| marker | num_blocks | num_rows | end magic |`.
+//! It's still readable, but cannot seek by time.
+//!
+//! The trailer is written only when the writer is finished, so a file without a valid trailer
+//! is considered truncated, e.g. the worker crashed while writing it.
+//!
+//! The legacy text format can still be read by `Base64GridReader`, and be converted to segment
+//! format by `convert_base64_file`.
+use anyhow::{bail, Result};
+use log::{error, info};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use crate::error_bail;
+use crate::grid_sample::{KeySchema, SampleKey};
+use crate::local_file_reader::LocalFileReader;
+
+/// Magic bytes at the beginning of segment file.
+pub const SEGMENT_MAGIC: &[u8; 8] = b"DRPLTSEG";
+
+/// Magic bytes at the end of segment file.
+pub const SEGMENT_END_MAGIC: &[u8; 8] = b"DRPLTEND";
+
+/// Current version of segment format.
+pub const SEGMENT_VERSION: u32 = 2;
+
+/// The length marker of trailer. No block could be that large.
+const TRAILER_MARKER: u32 = u32::MAX;
+
+/// Length of header: magic and version.
+const HEADER_LEN: u64 = 12;
+
+/// Length of trailer of version 1: marker, num_blocks, num_rows and end magic.
+const TRAILER_LEN_V1: u64 = 28;
+
+/// Length of trailer since version 2: num_blocks, num_rows, index_offset and end magic.
+const TRAILER_LEN: u64 = 32;
+
+/// Length of one serialized `BlockIndexEntry`.
+const BLOCK_INDEX_ENTRY_LEN: u64 = 76;
+
+/// Index of one block in segment file.
+///
+/// The rows in partition files are sorted by `SampleKey`, so are the blocks. We can binary
+/// search the index to find the blocks in a time range, without decoding all blocks.
+#[derive(Default, Clone, Debug, PartialEq)]
+pub struct BlockIndexEntry {
+    /// Offset of the block in file, pointing to the `len` of the block.
+    pub offset: u64,
+
+    /// Number of rows in the block.
+    pub num_rows: u32,
+
+    /// Min `SampleKey` of the rows in the block.
+    pub min_key: SampleKey,
+
+    /// Max `SampleKey` of the rows in the block.
+    pub max_key: SampleKey,
+}
+
+impl BlockIndexEntry {
+    /// Get the index entry of a `GridBuffer`, the `offset` is set to 0.
+    ///
+    /// If the `GridBuffer` is empty or not a valid sample, the keys are default.
+    pub fn from_gridbuffer(gridbuffer: &GridBuffer) -> Self {
+        Self::from_gridbuffer_with_key_schema(gridbuffer, &KeySchema::default())
+    }
+
+    /// Same as `from_gridbuffer`, but the keys are read from the key columns of `key_schema`.
+    pub fn from_gridbuffer_with_key_schema(
+        gridbuffer: &GridBuffer,
+        key_schema: &KeySchema,
+    ) -> Self {
+        let mut entry = Self {
+            num_rows: gridbuffer.num_rows() as u32,
+            ..Default::default()
+        };
+
+        if gridbuffer.num_rows() == 0 || !key_schema.is_valid_gridbuffer(gridbuffer) {
+            return entry;
+        }
+
+        for i in 0..gridbuffer.num_rows() {
+            let key = key_schema.get_sample_key(gridbuffer, i);
+
+            if i == 0 || key < entry.min_key {
+                entry.min_key = key;
+            }
+
+            if i == 0 || key > entry.max_key {
+                entry.max_key = key;
+            }
+        }
+
+        entry
+    }
+
+    fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_all(&self.offset.to_le_bytes())?;
+        writer.write_all(&self.num_rows.to_le_bytes())?;
+
+        for key in [&self.min_key, &self.max_key] {
+            writer.write_all(&key.timestamp.to_le_bytes())?;
+            writer.write_all(&key.user_id.to_le_bytes())?;
+            writer.write_all(&key.item_id.to_le_bytes())?;
+            writer.write_all(&key.request_id.to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != BLOCK_INDEX_ENTRY_LEN as usize {
+            error_bail!("invalid length of block index entry: {}", bytes.len());
+        }
+
+        let get_u64 =
+            |pos: usize| -> Result<u64> { Ok(u64::from_le_bytes(bytes[pos..pos + 8].try_into()?)) };
+
+        let get_key = |pos: usize| -> Result<SampleKey> {
+            Ok(SampleKey::new(
+                get_u64(pos)?,
+                get_u64(pos + 8)?,
+                get_u64(pos + 16)?,
+                get_u64(pos + 24)?,
+            ))
+        };
+
+        Ok(Self {
+            offset: get_u64(0)?,
+            num_rows: u32::from_le_bytes(bytes[8..12].try_into()?),
+            min_key: get_key(12)?,
+            max_key: get_key(44)?,
+        })
+    }
+}
+
+/// Find the blocks which may contain rows with timestamp in `[start_ts, end_ts)`.
+///
+/// Files are written from the output of `WindowHeap` which is only sorted in a window, so the
+/// time ranges of the blocks may overlap. All the blocks are checked, and the range is from the
+/// first to the last block overlapping with `[start_ts, end_ts)`, the blocks in between are
+/// read too.
+pub fn find_blocks_by_time(index: &[BlockIndexEntry], start_ts: u64, end_ts: u64) -> Range<usize> {
+    let is_overlapped = |entry: &BlockIndexEntry| {
+        entry.max_key.timestamp >= start_ts && entry.min_key.timestamp < end_ts
+    };
+
+    match index.iter().position(is_overlapped) {
+        Some(first) => {
+            let last = index.iter().rposition(is_overlapped).unwrap_or(first);
+            first..last + 1
+        }
+        None => {
+            let first = index
+                .iter()
+                .take_while(|entry| entry.max_key.timestamp < start_ts)
+                .count();
+            first..first
+        }
+    }
+}
+
+/// Write `GridBuffer`s to a segment file.
+///
+/// `finish` must be called after all `GridBuffer`s are written, otherwise the file has no
+/// trailer and cannot be opened by `GridSegmentReader`.
+pub struct GridSegmentWriter {
+    /// Filename.
+    filename: String,
+
+    /// Buffered writer of the file.
+    writer: BufWriter<File>,
+
+    /// Offset of next block.
+    offset: u64,
+
+    /// Total number of rows written.
+    num_rows: u64,
+
+    /// Index of all blocks written.
+    index: Vec<BlockIndexEntry>,
+
+    /// Key columns of the table, used to build the block index.
+    key_schema: KeySchema,
+}
+
+impl GridSegmentWriter {
+    /// Create a new segment file and write the header.
+    pub fn create(filename: &str) -> Result<Self> {
+        let file = File::create(filename)?;
+        let mut writer = BufWriter::new(file);
+
+        writer.write_all(SEGMENT_MAGIC)?;
+        writer.write_all(&SEGMENT_VERSION.to_le_bytes())?;
+
+        Ok(Self {
+            filename: filename.to_string(),
+            writer,
+            offset: HEADER_LEN,
+            num_rows: 0,
+            index: Vec::new(),
+            key_schema: KeySchema::default(),
+        })
+    }
+
+    /// Use the key columns of `key_schema` for the block index, default is the fixed sample key.
+    pub fn with_key_schema(mut self, key_schema: &KeySchema) -> Self {
+        self.key_schema = key_schema.clone();
+        self
+    }
+
+    /// Write one `GridBuffer` as a block.
+    pub fn write(&mut self, gridbuffer: &GridBuffer) -> Result<()> {
+        self.write_bytes(
+            &gridbuffer.to_bytes(),
+            BlockIndexEntry::from_gridbuffer_with_key_schema(gridbuffer, &self.key_schema),
+        )
+    }
+
+    /// Write the bytes of a `GridBuffer` as a block.
+    ///
+    /// `bytes` must be the result of `GridBuffer::to_bytes()`, and `entry` must be the index
+    /// entry of the same `GridBuffer`. The `offset` of `entry` is set by the writer.
+    pub fn write_bytes(&mut self, bytes: &[u8], mut entry: BlockIndexEntry) -> Result<()> {
+        if bytes.len() >= TRAILER_MARKER as usize {
+            error_bail!(
+                "block is too large, filename: {}, len: {}",
+                self.filename.clone(),
+                bytes.len()
+            );
+        }
+
+        self.writer.write_all(&(bytes.len() as u32).to_le_bytes())?;
+        self.writer.write_all(bytes)?;
+
+        entry.offset = self.offset;
+
+        self.offset += 4 + bytes.len() as u64;
+        self.num_rows += entry.num_rows as u64;
+        self.index.push(entry);
+
+        Ok(())
+    }
+
+    /// Write the block index and trailer, then flush the file.
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.write_all(&TRAILER_MARKER.to_le_bytes())?;
+
+        let index_offset = self.offset + 4;
+        for entry in self.index.iter() {
+            entry.write_to(&mut self.writer)?;
+        }
+
+        self.writer
+            .write_all(&(self.index.len() as u64).to_le_bytes())?;
+        self.writer.write_all(&self.num_rows.to_le_bytes())?;
+        self.writer.write_all(&index_offset.to_le_bytes())?;
+        self.writer.write_all(SEGMENT_END_MAGIC)?;
+
+        self.writer.flush()?;
+
+        Ok(())
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.index.len() as u64
+    }
+
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+}
+
+/// Read `GridBuffer`s from a segment file in order.
+pub struct GridSegmentReader {
+    /// Filename.
+    filename: String,
+
+    /// Buffered reader of the file.
+    reader: BufReader<File>,
+
+    /// Version of the file.
+    version: u32,
+
+    /// Number of blocks, read from trailer.
+    num_blocks: u64,
+
+    /// Total number of rows, read from trailer.
+    num_rows: u64,
+
+    /// Block index. `None` for version 1.
+    index: Option<Vec<BlockIndexEntry>>,
+
+    /// Index of next block to read.
+    cur_block: usize,
+
+    /// Stop reading at this block. All remaining blocks are read if it's `None`.
+    end_block: Option<usize>,
+
+    /// Offset of the trailer marker, where the blocks end.
+    blocks_end: u64,
+
+    /// Whether all blocks are read.
+    is_done: bool,
+}
+
+impl GridSegmentReader {
+    /// Open a segment file.
+    ///
+    /// Check the header and trailer, and load the block index. Return an error if the file is
+    /// not in segment format, or the file is truncated.
+    pub fn open(filename: &str) -> Result<Self> {
+        let mut file = File::open(filename)?;
+        let file_len = file.metadata()?.len();
+
+        if file_len < HEADER_LEN + TRAILER_LEN_V1 {
+            error_bail!(
+                "segment file is truncated, filename: {}, len: {}",
+                filename,
+                file_len
+            );
+        }
+
+        let mut header = [0u8; HEADER_LEN as usize];
+        file.read_exact(&mut header)?;
+
+        if &header[0..8] != SEGMENT_MAGIC {
+            error_bail!("not a segment file, filename: {}", filename);
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into()?);
+
+        let (num_blocks, num_rows, index) = match version {
+            1 => Self::read_trailer_v1(&mut file, filename)?,
+            SEGMENT_VERSION => Self::read_trailer(&mut file, filename, file_len)?,
+            _ => {
+                error_bail!(
+                    "unsupported segment version, filename: {}, version: {}",
+                    filename,
+                    version
+                );
+            }
+        };
+
+        let blocks_end = match version {
+            1 => file_len - TRAILER_LEN_V1,
+            _ => file_len - TRAILER_LEN - num_blocks * BLOCK_INDEX_ENTRY_LEN - 4,
+        };
+
+        file.seek(SeekFrom::Start(HEADER_LEN))?;
+
+        Ok(Self {
+            filename: filename.to_string(),
+            reader: BufReader::new(file),
+            version,
+            num_blocks,
+            num_rows,
+            index,
+            cur_block: 0,
+            end_block: None,
+            blocks_end,
+            is_done: false,
+        })
+    }
+
+    fn read_trailer_v1(
+        file: &mut File,
+        filename: &str,
+    ) -> Result<(u64, u64, Option<Vec<BlockIndexEntry>>)> {
+        let mut trailer = [0u8; TRAILER_LEN_V1 as usize];
+        file.seek(SeekFrom::End(-(TRAILER_LEN_V1 as i64)))?;
+        file.read_exact(&mut trailer)?;
+
+        let marker = u32::from_le_bytes(trailer[0..4].try_into()?);
+        if marker != TRAILER_MARKER || &trailer[20..28] != SEGMENT_END_MAGIC {
+            error_bail!("segment file has no valid trailer, filename: {}", filename);
+        }
+
+        let num_blocks = u64::from_le_bytes(trailer[4..12].try_into()?);
+        let num_rows = u64::from_le_bytes(trailer[12..20].try_into()?);
+
+        Ok((num_blocks, num_rows, None))
+    }
+
+    fn read_trailer(
+        file: &mut File,
+        filename: &str,
+        file_len: u64,
+    ) -> Result<(u64, u64, Option<Vec<BlockIndexEntry>>)> {
+        if file_len < HEADER_LEN + 4 + TRAILER_LEN {
+            error_bail!(
+                "segment file is truncated, filename: {}, len: {}",
+                filename,
+                file_len
+            );
+        }
+
+        let mut trailer = [0u8; TRAILER_LEN as usize];
+        file.seek(SeekFrom::End(-(TRAILER_LEN as i64)))?;
+        file.read_exact(&mut trailer)?;
+
+        if &trailer[24..32] != SEGMENT_END_MAGIC {
+            error_bail!("segment file has no valid trailer, filename: {}", filename);
+        }
+
+        let num_blocks = u64::from_le_bytes(trailer[0..8].try_into()?);
+        let num_rows = u64::from_le_bytes(trailer[8..16].try_into()?);
+        let index_offset = u64::from_le_bytes(trailer[16..24].try_into()?);
+
+        let index_len = num_blocks * BLOCK_INDEX_ENTRY_LEN;
+        if index_offset < HEADER_LEN + 4 || index_offset + index_len + TRAILER_LEN != file_len {
+            error_bail!(
+                "invalid block index, filename: {}, index_offset: {}, num_blocks: {}",
+                filename,
+                index_offset,
+                num_blocks
+            );
+        }
+
+        let mut bytes = vec![0u8; index_len as usize];
+        file.seek(SeekFrom::Start(index_offset))?;
+        file.read_exact(&mut bytes)?;
+
+        let index = bytes
+            .chunks(BLOCK_INDEX_ENTRY_LEN as usize)
+            .map(BlockIndexEntry::from_bytes)
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((num_blocks, num_rows, Some(index)))
+    }
+
+    /// Check whether the file starts with the segment magic.
+    pub fn is_segment_file(filename: &str) -> Result<bool> {
+        let mut file = File::open(filename)?;
+        let mut magic = [0u8; 8];
+
+        match file.read_exact(&mut magic) {
+            Ok(_) => Ok(&magic == SEGMENT_MAGIC),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Block index of the file. `None` if the file has no index.
+    pub fn block_index(&self) -> Option<&[BlockIndexEntry]> {
+        self.index.as_deref()
+    }
+
+    /// Only read the blocks in `range`.
+    ///
+    /// The file must have block index.
+    pub fn seek_block_range(&mut self, range: Range<usize>) -> Result<()> {
+        let offset = match self.index.as_ref() {
+            Some(index) => {
+                if range.end > index.len() || range.start > range.end {
+                    error_bail!(
+                        "invalid block range, filename: {}, range: {:?}, num_blocks: {}",
+                        self.filename.clone(),
+                        range,
+                        index.len()
+                    );
+                }
+
+                // Position of the trailer marker if the range is empty, or to the end.
+                index
+                    .get(range.start)
+                    .map(|entry| entry.offset)
+                    .unwrap_or(0)
+            }
+            None => {
+                error_bail!(
+                    "segment file has no block index, filename: {}",
+                    self.filename.clone()
+                );
+            }
+        };
+
+        self.cur_block = range.start;
+        self.end_block = Some(range.end);
+        self.is_done = range.is_empty();
+
+        if !self.is_done {
+            self.reader.seek(SeekFrom::Start(offset))?;
+        }
+
+        Ok(())
+    }
+
+    /// Only read the blocks which may contain rows with timestamp in `[start_ts, end_ts)`.
+    ///
+    /// Check the block index to find the blocks, see `find_blocks_by_time`. If the file has no
+    /// block index, all blocks are read. The rows out of range are not filtered.
+    pub fn seek_by_time(&mut self, start_ts: u64, end_ts: u64) -> Result<()> {
+        let range = match self.index.as_ref() {
+            Some(index) => find_blocks_by_time(index, start_ts, end_ts),
+            None => return Ok(()),
+        };
+
+        self.seek_block_range(range)
+    }
+
+    /// Read the bytes of next block without decoding.
+    ///
+    /// Return `None` if all blocks are read.
+    pub fn next_block_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        if self.is_done {
+            return Ok(None);
+        }
+
+        if let Some(end_block) = self.end_block {
+            if self.cur_block >= end_block {
+                self.is_done = true;
+                return Ok(None);
+            }
+        }
+
+        let mut len_bytes = [0u8; 4];
+        self.reader.read_exact(&mut len_bytes)?;
+
+        let len = u32::from_le_bytes(len_bytes);
+        if len == TRAILER_MARKER {
+            self.is_done = true;
+            return Ok(None);
+        }
+
+        // The len is checked before allocating, a corrupted len could be up to 4GB.
+        let pos = self.reader.stream_position()?;
+        if len as u64 > self.blocks_end.saturating_sub(pos) {
+            error_bail!(
+                "invalid block len, filename: {}, block: {}, len: {}, remaining: {}",
+                self.filename.clone(),
+                self.cur_block,
+                len,
+                self.blocks_end.saturating_sub(pos)
+            );
+        }
+
+        let mut bytes = vec![0u8; len as usize];
+        self.reader.read_exact(&mut bytes)?;
+
+        self.cur_block += 1;
+
+        Ok(Some(bytes))
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    pub fn num_rows(&self) -> u64 {
+        self.num_rows
+    }
+}
+
+impl Iterator for GridSegmentReader {
+    type Item = Result<GridBuffer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_block_bytes() {
+            Ok(Some(bytes)) => Some(GridBuffer::from_bytes(&bytes)),
+            Ok(None) => None,
+            Err(e) => {
+                // Stop reading the file after an error.
+                self.is_done = true;
+
+                error!(
+                    "read block from segment file failed, filename: {}, error: {}",
+                    self.filename.clone(),
+                    e
+                );
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Read `GridBuffer`s from lines in legacy text format, one `GridBuffer::to_base64()` per line.
+///
+/// Empty lines are skipped.
+pub struct Base64GridReader<T: Iterator<Item = Result<String>>> {
+    lines: T,
+}
+
+impl<T: Iterator<Item = Result<String>>> Base64GridReader<T> {
+    pub fn new(lines: T) -> Self {
+        Self { lines }
+    }
+}
+
+impl<T: Iterator<Item = Result<String>>> Iterator for Base64GridReader<T> {
+    type Item = Result<GridBuffer>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.lines.next()? {
+                Ok(line) => {
+                    let line = line.trim_end();
+
+                    if line.is_empty() {
+                        continue;
+                    }
+
+                    return Some(GridBuffer::from_base64(line));
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Convert a file in legacy text format to segment format.
+///
+/// Return the number of blocks written.
+pub fn convert_base64_file(src: &str, dst: &str) -> Result<u64> {
+    let reader = Base64GridReader::new(LocalFileReader::new(&vec![src.to_string()])?);
+    let mut writer = GridSegmentWriter::create(dst)?;
+
+    for gridbuffer in reader {
+        writer.write(&gridbuffer?)?;
+    }
+
+    let num_blocks = writer.num_blocks();
+    writer.finish()?;
+
+    Ok(num_blocks)
+}
+
+/// Rewrite a `.grid` file in legacy text format to segment format in place.
+///
+/// The result is first written to a temporary file, then renamed to `filename`. If the file is
+/// already in segment format, nothing is done and `false` is returned.
+pub fn rewrite_base64_file(filename: &str) -> Result<bool> {
+    if !Path::new(filename).exists() {
+        error_bail!("File not found: {}", filename);
+    }
+
+    if GridSegmentReader::is_segment_file(filename)? {
+        info!("already in segment format, skip, filename: {}", filename);
+        return Ok(false);
+    }
+
+    let tmp_filename = format!("{}.tmp", filename);
+
+    let num_blocks = convert_base64_file(filename, tmp_filename.as_str())?;
+    std::fs::rename(tmp_filename.as_str(), filename)?;
+
+    info!(
+        "rewrite file to segment format done, filename: {}, num_blocks: {}",
+        filename, num_blocks
+    );
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_sample::GridSample;
+    use crate::local_file_reader::get_test_gridbuffer_filenames;
+    use crate::tool::setup_log;
+
+    fn get_tmp_filename(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("droplet_{}_{}.grid", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn read_test_gridbuffers() -> Result<Vec<GridBuffer>> {
+        let filenames = get_test_gridbuffer_filenames(1);
+        Base64GridReader::new(LocalFileReader::new(&filenames)?).collect()
+    }
+
+    #[test]
+    fn test_write_and_read_segment() -> Result<()> {
+        setup_log();
+
+        let gridbuffers = read_test_gridbuffers()?;
+        let filename = get_tmp_filename("test_write_and_read_segment");
+
+        let mut writer = GridSegmentWriter::create(filename.as_str())?;
+        for gridbuffer in gridbuffers.iter() {
+            writer.write(gridbuffer)?;
+        }
+        writer.finish()?;
+
+        let reader = GridSegmentReader::open(filename.as_str())?;
+        assert_eq!(reader.num_blocks(), gridbuffers.len() as u64);
+        assert_eq!(
+            reader.num_rows(),
+            gridbuffers.iter().map(|x| x.num_rows() as u64).sum::<u64>()
+        );
+
+        let res = reader.collect::<Result<Vec<GridBuffer>>>()?;
+        assert_eq!(res.len(), gridbuffers.len());
+
+        for (a, b) in res.iter().zip(gridbuffers.iter()) {
+            assert_eq!(a.to_bytes(), b.to_bytes());
+        }
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    /// Each block has 4 rows, the timestamps of block `i` are `[i * 100, i * 100 + 4)`.
+    fn get_sorted_gridbuffers(num_blocks: usize) -> Vec<GridBuffer> {
+        (0..num_blocks)
+            .map(|i| {
+                let mut sample = GridSample::new(4, &vec![100]);
+
+                for row in 0..4 {
+                    let timestamp = (i * 100 + row) as u64;
+                    sample.set_sample_key(row, &SampleKey::new(timestamp, 1, 2, 3));
+                    sample.gridbuffer.push_u64(row, 4, row as u64);
+                }
+
+                sample.gridbuffer
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_block_index() -> Result<()> {
+        setup_log();
+
+        let gridbuffers = get_sorted_gridbuffers(4);
+        let filename = get_tmp_filename("test_block_index");
+
+        let mut writer = GridSegmentWriter::create(filename.as_str())?;
+        for gridbuffer in gridbuffers.iter() {
+            writer.write(gridbuffer)?;
+        }
+        writer.finish()?;
+
+        let mut reader = GridSegmentReader::open(filename.as_str())?;
+        assert_eq!(reader.version(), SEGMENT_VERSION);
+
+        let index = reader.block_index().unwrap().to_vec();
+        assert_eq!(index.len(), 4);
+        assert_eq!(index[0].offset, HEADER_LEN);
+        assert_eq!(index[1].min_key, SampleKey::new(100, 1, 2, 3));
+        assert_eq!(index[1].max_key, SampleKey::new(103, 1, 2, 3));
+        assert!(index.iter().all(|entry| entry.num_rows == 4));
+
+        assert_eq!(find_blocks_by_time(&index, 0, 1000), 0..4);
+        assert_eq!(find_blocks_by_time(&index, 102, 201), 1..3);
+        assert_eq!(find_blocks_by_time(&index, 104, 200), 2..2);
+        assert_eq!(find_blocks_by_time(&index, 1000, 2000), 4..4);
+
+        reader.seek_by_time(102, 201)?;
+        let res = reader.collect::<Result<Vec<GridBuffer>>>()?;
+        assert_eq!(res.len(), 2);
+        assert_eq!(res[0].to_bytes(), gridbuffers[1].to_bytes());
+        assert_eq!(res[1].to_bytes(), gridbuffers[2].to_bytes());
+
+        let mut reader = GridSegmentReader::open(filename.as_str())?;
+        reader.seek_by_time(104, 200)?;
+        assert!(reader.next().is_none());
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_overlapped_blocks() -> Result<()> {
+        setup_log();
+
+        let get_entry = |min_ts: u64, max_ts: u64| BlockIndexEntry {
+            offset: 0,
+            num_rows: 1,
+            min_key: SampleKey::new(min_ts, 1, 2, 3),
+            max_key: SampleKey::new(max_ts, 1, 2, 3),
+        };
+
+        // The second block ends before the third block, which still has rows after 150.
+        let index = vec![
+            get_entry(0, 100),
+            get_entry(90, 120),
+            get_entry(110, 200),
+            get_entry(300, 400),
+        ];
+
+        assert_eq!(find_blocks_by_time(&index, 150, 160), 2..3);
+        assert_eq!(find_blocks_by_time(&index, 95, 105), 0..2);
+        assert_eq!(find_blocks_by_time(&index, 0, 10), 0..1);
+        assert_eq!(find_blocks_by_time(&index, 250, 300), 3..3);
+        assert_eq!(find_blocks_by_time(&index, 500, 600), 4..4);
+
+        // The last block starts before the blocks before it.
+        let index = vec![get_entry(0, 100), get_entry(200, 300), get_entry(50, 250)];
+        assert_eq!(find_blocks_by_time(&index, 120, 150), 2..3);
+        assert_eq!(find_blocks_by_time(&index, 60, 210), 0..3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_truncated_segment() -> Result<()> {
+        setup_log();
+
+        let gridbuffers = read_test_gridbuffers()?;
+        let filename = get_tmp_filename("test_open_truncated_segment");
+
+        // Not finished, so there is no trailer.
+        let mut writer = GridSegmentWriter::create(filename.as_str())?;
+        writer.write(&gridbuffers[0])?;
+        drop(writer);
+
+        assert!(GridSegmentReader::is_segment_file(filename.as_str())?);
+        assert!(GridSegmentReader::open(filename.as_str()).is_err());
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_corrupted_block_len() -> Result<()> {
+        setup_log();
+
+        let gridbuffers = get_sorted_gridbuffers(2);
+        let filename = get_tmp_filename("test_read_corrupted_block_len");
+
+        let mut writer = GridSegmentWriter::create(filename.as_str())?;
+        for gridbuffer in gridbuffers.iter() {
+            writer.write(gridbuffer)?;
+        }
+        writer.finish()?;
+
+        // Trailer is valid, but the len of the first block is larger than the file.
+        let mut bytes = std::fs::read(filename.as_str())?;
+        let pos = HEADER_LEN as usize;
+        bytes[pos..pos + 4].copy_from_slice(&(u32::MAX - 1).to_le_bytes());
+        std::fs::write(filename.as_str(), bytes)?;
+
+        let mut reader = GridSegmentReader::open(filename.as_str())?;
+        assert!(reader.next_block_bytes().is_err());
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rewrite_base64_file() -> Result<()> {
+        setup_log();
+
+        let gridbuffers = read_test_gridbuffers()?;
+        let filename = get_tmp_filename("test_rewrite_base64_file");
+
+        std::fs::copy(&get_test_gridbuffer_filenames(1)[0], filename.as_str())?;
+
+        assert!(!GridSegmentReader::is_segment_file(filename.as_str())?);
+        assert!(rewrite_base64_file(filename.as_str())?);
+
+        // Already converted.
+        assert!(!rewrite_base64_file(filename.as_str())?);
+
+        let res =
+            GridSegmentReader::open(filename.as_str())?.collect::<Result<Vec<GridBuffer>>>()?;
+        assert_eq!(res.len(), gridbuffers.len());
+        assert_eq!(res[0].to_bytes(), gridbuffers[0].to_bytes());
+
+        std::fs::remove_file(filename)?;
+
+        Ok(())
+    }
+}
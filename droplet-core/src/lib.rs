@@ -1,12 +1,18 @@
 #![allow(dead_code)]
 #![feature(portable_simd)]
 
+pub mod data_converter;
+pub mod data_type;
 pub mod db;
 pub mod droplet;
 pub mod feature_info;
 pub mod grid_sample;
+pub mod grid_segment;
 pub mod grpc_util;
 pub mod id_mapping;
 pub mod local_file_reader;
+pub mod partition;
+pub mod predicate;
+pub mod sniper;
 pub mod tool;
 pub mod window_heap;
@@ -0,0 +1,514 @@
+//! Locations of table partitions.
+//!
+//! Each partition is a directory named by table, date and partition index. Workers write
+//! unsorted files under `TABLE_ROOT`, and the sorted files after `merge_sort` under
+//! `SORTED_TABLE_ROOT`, both named as `<n>.grid`.
+use anyhow::{bail, Result};
+use chrono::{DateTime, Datelike, NaiveDate, Timelike};
+use log::error;
+use std::path::Path;
+
+use crate::error_bail;
+
+pub use crate::droplet::TimestampUnit;
+
+/// Root path of unsorted partition files.
+pub const TABLE_ROOT: &str = "/tmp/droplet/tables";
+
+/// Root path of sorted partition files.
+pub const SORTED_TABLE_ROOT: &str = "/tmp/droplet_sorted/tables";
+
+/// Check the table name before it's used in the paths of partitions.
+///
+/// Table names come from requests, so names which could point outside of the table root are
+/// rejected: empty names, names with `/`, `\0` or `..`.
+pub fn check_table_name(table: &str) -> Result<()> {
+    if table.is_empty() || table.contains('/') || table.contains('\0') || table.contains("..") {
+        error_bail!("Invalid table name: {:?}", table);
+    }
+
+    Ok(())
+}
+
+/// Path of the table.
+pub fn get_table_path(table: &str) -> String {
+    format!("{}/{}", TABLE_ROOT, table)
+}
+
+/// Path of the unsorted files of a partition.
+pub fn get_partition_path(table: &str, partition_date: u32, partition_index: u32) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        TABLE_ROOT, table, partition_date, partition_index
+    )
+}
+
+/// Path of the sorted files of a partition.
+pub fn get_sorted_partition_path(table: &str, partition_date: u32, partition_index: u32) -> String {
+    format!(
+        "{}/{}/{}/{}",
+        SORTED_TABLE_ROOT, table, partition_date, partition_index
+    )
+}
+
+/// Parse table, partition date and partition index from the unsorted path of a partition.
+///
+/// It's the reverse of `get_partition_path`.
+pub fn parse_partition_path(path: &str) -> Result<(String, u32, u32)> {
+    parse_path_under_root(TABLE_ROOT, path)
+}
+
+/// Parse table, partition date and partition index from the sorted path of a partition.
+///
+/// It's the reverse of `get_sorted_partition_path`.
+pub fn parse_sorted_partition_path(path: &str) -> Result<(String, u32, u32)> {
+    parse_path_under_root(SORTED_TABLE_ROOT, path)
+}
+
+fn parse_path_under_root(root: &str, path: &str) -> Result<(String, u32, u32)> {
+    let rest = match path
+        .strip_prefix(root)
+        .and_then(|rest| rest.strip_prefix('/'))
+    {
+        Some(rest) => rest.trim_end_matches('/'),
+        None => {
+            error_bail!("Path is not under table root, path: {}", path);
+        }
+    };
+
+    let parts = rest.rsplitn(3, '/').collect::<Vec<_>>();
+    if parts.len() != 3 {
+        error_bail!("Invalid partition path, path: {}", path);
+    }
+
+    check_table_name(parts[2])?;
+
+    match (parts[1].parse::<u32>(), parts[0].parse::<u32>()) {
+        (Ok(partition_date), Ok(partition_index)) => {
+            Ok((parts[2].to_string(), partition_date, partition_index))
+        }
+        _ => {
+            error_bail!("Invalid partition date or index, path: {}", path);
+        }
+    }
+}
+
+/// Get the sorted path of an unsorted partition path.
+///
+/// Only the root is replaced, so table names are kept as they are.
+pub fn get_sorted_path(path: &str) -> String {
+    match path.strip_prefix(TABLE_ROOT) {
+        Some(rest) => format!("{}{}", SORTED_TABLE_ROOT, rest),
+        None => path.replacen("droplet", "droplet_sorted", 1),
+    }
+}
+
+/// Seconds of one day.
+const SECONDS_PER_DAY: i64 = 86400;
+
+/// Rule to compute the partition of a timestamp, from the `table_info` of the table.
+///
+/// Each day is split into `partition_count_per_day` partitions of the same length, the last
+/// one ends at midnight of next day. Days start at midnight of the timezone which is
+/// `tz_offset_seconds` east of UTC, and timestamps are in `timestamp_unit`.
+///
+/// All partition dates, indexes and time ranges must be computed by it, so sinkers and meta
+/// server always agree on the partition of a row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionScheme {
+    pub partition_count_per_day: u32,
+    pub timestamp_unit: TimestampUnit,
+    pub tz_offset_seconds: i32,
+}
+
+impl PartitionScheme {
+    /// Timestamps in seconds and partitions in UTC.
+    pub fn new(partition_count_per_day: u32) -> Self {
+        Self {
+            partition_count_per_day,
+            timestamp_unit: TimestampUnit::Second,
+            tz_offset_seconds: 0,
+        }
+    }
+
+    pub fn with_timestamp_unit(mut self, timestamp_unit: TimestampUnit) -> Self {
+        self.timestamp_unit = timestamp_unit;
+        self
+    }
+
+    pub fn with_tz_offset_seconds(mut self, tz_offset_seconds: i32) -> Self {
+        self.tz_offset_seconds = tz_offset_seconds;
+        self
+    }
+
+    /// `partition_count_per_day` must be positive and at most the seconds of one day, so each
+    /// partition is at least one second, and the timezone offset must be less than one day.
+    pub fn check(&self) -> Result<()> {
+        if self.partition_count_per_day == 0 {
+            error_bail!("partition_count_per_day must be positive");
+        }
+
+        if self.partition_count_per_day as i64 > SECONDS_PER_DAY {
+            error_bail!(
+                "partition_count_per_day must be at most {}, partition_count_per_day: {}",
+                SECONDS_PER_DAY,
+                self.partition_count_per_day
+            );
+        }
+
+        if (self.tz_offset_seconds as i64).abs() >= SECONDS_PER_DAY {
+            error_bail!(
+                "tz_offset_seconds must be less than one day, tz_offset_seconds: {}",
+                self.tz_offset_seconds
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Number of `timestamp_unit` in one second.
+    pub fn units_per_second(&self) -> u64 {
+        match self.timestamp_unit {
+            TimestampUnit::Second => 1,
+            TimestampUnit::Millisecond => 1000,
+            TimestampUnit::Microsecond => 1_000_000,
+        }
+    }
+
+    /// Convert a timestamp of the table to seconds, rounded down.
+    #[inline]
+    pub fn to_seconds(&self, timestamp: u64) -> u64 {
+        timestamp / self.units_per_second()
+    }
+
+    /// Convert a timestamp of the table to milliseconds, rounded down.
+    pub fn to_millis(&self, timestamp: u64) -> u64 {
+        match self.timestamp_unit {
+            TimestampUnit::Second => timestamp.saturating_mul(1000),
+            TimestampUnit::Millisecond => timestamp,
+            TimestampUnit::Microsecond => timestamp / 1000,
+        }
+    }
+
+    /// Convert seconds to a timestamp of the table, negative seconds are `0`.
+    #[inline]
+    fn seconds_to_timestamp(&self, seconds: i64) -> u64 {
+        (seconds.max(0) as u64).saturating_mul(self.units_per_second())
+    }
+
+    /// Seconds since epoch as if the local time of the timezone were UTC.
+    fn get_local_seconds(&self, timestamp: u64) -> Result<i64> {
+        match i64::try_from(self.to_seconds(timestamp)) {
+            Ok(seconds) => Ok(seconds + self.tz_offset_seconds as i64),
+            Err(_) => {
+                error_bail!("Invalid timestamp: {}", timestamp);
+            }
+        }
+    }
+
+    /// Get the partition date in format `YYYYMMDD` and partition index of a timestamp.
+    pub fn get_partition(&self, timestamp: u64) -> Result<(u32, u32)> {
+        self.check()?;
+
+        let datetime = match DateTime::from_timestamp(self.get_local_seconds(timestamp)?, 0) {
+            Some(datetime) => datetime,
+            None => {
+                error_bail!("Invalid timestamp: {}", timestamp);
+            }
+        };
+
+        let partition_date =
+            datetime.year() as u32 * 10000 + datetime.month() * 100 + datetime.day();
+
+        let time_span_in_seconds = SECONDS_PER_DAY as u32 / self.partition_count_per_day;
+        let partition_index = datetime.num_seconds_from_midnight() / time_span_in_seconds;
+
+        Ok((
+            partition_date,
+            partition_index.min(self.partition_count_per_day - 1),
+        ))
+    }
+
+    /// Get the time range `[time_start, time_end)` in `timestamp_unit` of the partition which
+    /// the timestamp belongs to.
+    pub fn get_time_range(&self, timestamp: u64) -> Result<(u64, u64)> {
+        let (partition_date, partition_index) = self.get_partition(timestamp)?;
+
+        self.get_partition_time_range(partition_date, partition_index)
+    }
+
+    /// Get the time range `[time_start, time_end)` in `timestamp_unit` of the partition, the
+    /// reverse of `get_partition`.
+    ///
+    /// The last partition of the day ends at midnight of next day, same as the clamp in
+    /// `get_partition`.
+    pub fn get_partition_time_range(
+        &self,
+        partition_date: u32,
+        partition_index: u32,
+    ) -> Result<(u64, u64)> {
+        self.check()?;
+
+        if partition_index >= self.partition_count_per_day {
+            error_bail!(
+                "partition_index out of range, partition_index: {}, partition_count_per_day: {}",
+                partition_index,
+                self.partition_count_per_day
+            );
+        }
+
+        let midnight = match NaiveDate::from_ymd_opt(
+            (partition_date / 10000) as i32,
+            partition_date / 100 % 100,
+            partition_date % 100,
+        )
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        {
+            Some(datetime) => datetime.and_utc().timestamp() - self.tz_offset_seconds as i64,
+            None => {
+                error_bail!("Invalid partition date: {}", partition_date);
+            }
+        };
+
+        let time_span_in_seconds = SECONDS_PER_DAY / self.partition_count_per_day as i64;
+
+        let time_start = midnight + time_span_in_seconds * partition_index as i64;
+        let time_end = if partition_index + 1 == self.partition_count_per_day {
+            midnight + SECONDS_PER_DAY
+        } else {
+            time_start + time_span_in_seconds
+        };
+
+        Ok((
+            self.seconds_to_timestamp(time_start),
+            self.seconds_to_timestamp(time_end),
+        ))
+    }
+}
+
+/// List all `<n>.grid` files under the partition path, ordered by `n`.
+///
+/// The sorted files of a partition are written in order of `n`, so reading them in this
+/// order gives rows in order of `SampleKey`.
+pub fn list_grid_files(path: &str) -> Result<Vec<String>> {
+    if !Path::new(path).is_dir() {
+        error_bail!("Partition path does not exist, path: {}", path);
+    }
+
+    let mut files = Vec::new();
+
+    for entry in std::fs::read_dir(path)? {
+        let file_path = entry?.path();
+
+        if file_path.extension().map_or(true, |ext| ext != "grid") {
+            continue;
+        }
+
+        let index = file_path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u32>().ok());
+
+        if let Some(index) = index {
+            files.push((index, file_path.to_string_lossy().to_string()));
+        }
+    }
+
+    files.sort();
+
+    Ok(files.into_iter().map(|(_, filename)| filename).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_sorted_path() {
+        assert_eq!(
+            get_sorted_path(get_partition_path("droplet_test", 20241011, 3).as_str()),
+            get_sorted_partition_path("droplet_test", 20241011, 3)
+        );
+    }
+
+    #[test]
+    fn test_check_table_name() {
+        assert!(check_table_name("droplet_test").is_ok());
+        assert!(check_table_name("droplet.test").is_ok());
+
+        assert!(check_table_name("").is_err());
+        assert!(check_table_name("..").is_err());
+        assert!(check_table_name("../etc").is_err());
+        assert!(check_table_name("a/b").is_err());
+        assert!(check_table_name("a\0b").is_err());
+    }
+
+    #[test]
+    fn test_parse_partition_path() -> Result<()> {
+        assert_eq!(
+            parse_partition_path(get_partition_path("droplet_test", 20241011, 3).as_str())?,
+            ("droplet_test".to_string(), 20241011, 3)
+        );
+
+        assert!(parse_partition_path("/tmp/other/droplet_test/20241011/3").is_err());
+        assert!(parse_partition_path(get_table_path("droplet_test").as_str()).is_err());
+        assert!(parse_partition_path(format!("{}/droplet_test/x/3", TABLE_ROOT).as_str()).is_err());
+        assert!(
+            parse_partition_path(format!("{}/../etc/20241011/3", TABLE_ROOT).as_str()).is_err()
+        );
+        assert!(
+            parse_partition_path(format!("{}/a/../../b/20241011/3", TABLE_ROOT).as_str()).is_err()
+        );
+
+        assert_eq!(
+            parse_sorted_partition_path(
+                get_sorted_partition_path("droplet_test", 20241011, 3).as_str()
+            )?,
+            ("droplet_test".to_string(), 20241011, 3)
+        );
+        assert!(parse_sorted_partition_path(
+            get_partition_path("droplet_test", 20241011, 3).as_str()
+        )
+        .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_partition() -> Result<()> {
+        // 2024-10-11 00:00:00 UTC.
+        let midnight = 1728604800;
+
+        let scheme = PartitionScheme::new(24);
+        assert_eq!(scheme.get_partition(midnight)?, (20241011, 0));
+        assert_eq!(
+            scheme.get_partition(midnight + 3600 * 5 + 10)?,
+            (20241011, 5)
+        );
+
+        let scheme = PartitionScheme::new(7);
+        assert_eq!(scheme.get_partition(midnight + 86399)?, (20241011, 6));
+        assert_eq!(scheme.get_partition(midnight + 86400)?, (20241012, 0));
+
+        assert!(PartitionScheme::new(0).get_partition(midnight).is_err());
+
+        // Partitions of one second are the finest, more would be partitions of zero seconds.
+        let scheme = PartitionScheme::new(86400);
+        assert!(scheme.check().is_ok());
+        assert_eq!(scheme.get_partition(midnight + 86399)?, (20241011, 86399));
+        assert!(PartitionScheme::new(86401).check().is_err());
+        assert!(PartitionScheme::new(86401).get_partition(midnight).is_err());
+        assert!(PartitionScheme::new(u32::MAX)
+            .get_time_range(midnight)
+            .is_err());
+
+        assert_eq!(
+            PartitionScheme::new(24).get_time_range(midnight + 3600 * 5 + 10)?,
+            (midnight + 3600 * 5, midnight + 3600 * 6)
+        );
+        assert_eq!(
+            PartitionScheme::new(7).get_time_range(midnight + 86399)?,
+            (midnight + 12342 * 6, midnight + 86400)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partition_scheme_timestamp_unit_and_timezone() -> Result<()> {
+        // 2024-10-11 00:00:00 UTC.
+        let midnight = 1728604800;
+
+        let millis = PartitionScheme::new(24).with_timestamp_unit(TimestampUnit::Millisecond);
+        assert_eq!(
+            millis.get_partition((midnight + 3600 * 5 + 10) * 1000 + 999)?,
+            (20241011, 5)
+        );
+        assert_eq!(
+            millis.get_time_range((midnight + 3600 * 5) * 1000)?,
+            ((midnight + 3600 * 5) * 1000, (midnight + 3600 * 6) * 1000)
+        );
+        assert_eq!(millis.to_millis(midnight * 1000), midnight * 1000);
+
+        let micros = PartitionScheme::new(24).with_timestamp_unit(TimestampUnit::Microsecond);
+        assert_eq!(
+            micros.get_partition((midnight + 86399) * 1_000_000)?,
+            (20241011, 23)
+        );
+        assert_eq!(
+            micros.to_millis(midnight * 1_000_000 + 1500),
+            midnight * 1000 + 1
+        );
+
+        // UTC+8, the day starts at 16:00 UTC of the day before.
+        let utc8 = PartitionScheme::new(24).with_tz_offset_seconds(8 * 3600);
+        assert_eq!(utc8.get_partition(midnight - 8 * 3600 - 1)?, (20241010, 23));
+        assert_eq!(utc8.get_partition(midnight - 8 * 3600)?, (20241011, 0));
+        assert_eq!(utc8.get_partition(midnight)?, (20241011, 8));
+        assert_eq!(
+            utc8.get_time_range(midnight - 1)?,
+            (midnight - 3600, midnight)
+        );
+
+        // UTC-5 in milliseconds.
+        let utc_minus_5 = PartitionScheme::new(7)
+            .with_timestamp_unit(TimestampUnit::Millisecond)
+            .with_tz_offset_seconds(-5 * 3600);
+        assert_eq!(
+            utc_minus_5.get_partition((midnight + 5 * 3600 - 1) * 1000)?,
+            (20241010, 6)
+        );
+        assert_eq!(
+            utc_minus_5.get_time_range((midnight + 5 * 3600 - 1) * 1000)?,
+            (
+                (midnight + 5 * 3600 - 86400 + 12342 * 6) * 1000,
+                (midnight + 5 * 3600) * 1000
+            )
+        );
+
+        assert_eq!(
+            utc_minus_5.get_partition_time_range(20241010, 6)?,
+            utc_minus_5.get_time_range((midnight + 5 * 3600 - 1) * 1000)?
+        );
+        assert!(utc_minus_5.get_partition_time_range(20241010, 7).is_err());
+        assert!(utc_minus_5.get_partition_time_range(20241032, 0).is_err());
+
+        assert!(PartitionScheme::new(24)
+            .with_tz_offset_seconds(86400)
+            .get_partition(midnight)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_grid_files() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!(
+                "droplet_test_list_grid_files_{}",
+                std::process::id()
+            ))
+            .to_string_lossy()
+            .to_string();
+
+        std::fs::create_dir_all(path.as_str())?;
+
+        for name in ["10.grid", "2.grid", "0.grid", "SUCCESS", "1.grid.tmp"] {
+            std::fs::File::create(format!("{}/{}", path, name))?;
+        }
+
+        let files = list_grid_files(path.as_str())?;
+        assert_eq!(
+            files,
+            vec![
+                format!("{}/0.grid", path),
+                format!("{}/2.grid", path),
+                format!("{}/10.grid", path),
+            ]
+        );
+
+        std::fs::remove_dir_all(path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,788 @@
+//! Row predicate used to filter rows when reading.
+//!
+//! A predicate is parsed from a string, for example:
+//!
+//! ```text
+//! label_0 == 1 AND item_id IN (1, 2, 3) AND NOT has(ExtractSparse3)
+//! ```
+//!
+//! The supported expressions are:
+//! 1. comparisons `==`, `!=`, `<`, `<=`, `>`, `>=` between a column and a literal.
+//! 2. `col IN (v1, v2, ...)`.
+//! 3. `has(col)`, whether the column has value in the row.
+//! 4. `AND`, `OR`, `NOT` and parentheses. `NOT` binds tightest, then `AND`, then `OR`.
+//!
+//! Keywords are case insensitive. Literals are numbers, `true`, `false`, or strings quoted by
+//! `'` or `"` where `\` escapes the next character. Numbers with `.` are `f64`, negative
+//! numbers are `i64`, others are `u64`.
+//!
+//! A column may have a list of values. Comparisons and `IN` are true if any value of the list
+//! matches. Comparisons and `IN` on a missing column are false.
+//!
+//! Columns are parsed as names, and must be resolved to global ids and `DataType`s by `resolve`
+//! before `eval`. The literals are cast to the type of the column when resolved, e.g. `1` is
+//! `1.0` for a `f32` column, and a literal which cannot be cast is an error, e.g. a string for
+//! a `u64` column. The values of the column are decoded by its type, see `data_type`.
+use anyhow::{bail, Result};
+use log::error;
+
+use gridbuffer::core::gridbuffer::GridCell;
+
+use crate::data_type::{decode_bool, decode_f64, decode_i64, decode_strings, DataType};
+use crate::error_bail;
+use crate::grid_sample::GridRow;
+
+/// Literal value in predicate.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    U64(u64),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    Bool(bool),
+    String(String),
+}
+
+impl Value {
+    /// Cast the literal to the element type of `data_type`.
+    ///
+    /// Numbers can be cast to any number type if the value fits, `bool` and string are only
+    /// for `Bool` and `String` columns.
+    pub fn cast(&self, data_type: DataType) -> Result<Value> {
+        let value = match (data_type.element_type(), self) {
+            (DataType::U64 | DataType::U32, Value::U64(v)) => Some(Value::U64(*v)),
+            (DataType::U64 | DataType::U32, Value::I64(v)) => {
+                u64::try_from(*v).ok().map(Value::U64)
+            }
+            (DataType::I64 | DataType::I32, Value::U64(v)) => {
+                i64::try_from(*v).ok().map(Value::I64)
+            }
+            (DataType::I64 | DataType::I32, Value::I64(v)) => Some(Value::I64(*v)),
+            (DataType::F32, Value::U64(v)) => Some(Value::F32(*v as f32)),
+            (DataType::F32, Value::I64(v)) => Some(Value::F32(*v as f32)),
+            (DataType::F32, Value::F32(v)) => Some(Value::F32(*v)),
+            (DataType::F32, Value::F64(v)) => Some(Value::F32(*v as f32)),
+            (DataType::F64, Value::U64(v)) => Some(Value::F64(*v as f64)),
+            (DataType::F64, Value::I64(v)) => Some(Value::F64(*v as f64)),
+            (DataType::F64, Value::F32(v)) => Some(Value::F64(*v as f64)),
+            (DataType::F64, Value::F64(v)) => Some(Value::F64(*v)),
+            (DataType::Bool, Value::Bool(v)) => Some(Value::Bool(*v)),
+            (DataType::String, Value::String(v)) => Some(Value::String(v.clone())),
+            _ => None,
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => {
+                error_bail!(
+                    "Invalid literal {:?} for column of type {}",
+                    self,
+                    data_type
+                );
+            }
+        }
+    }
+}
+
+/// Comparison operators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    #[inline]
+    fn apply<T: PartialOrd>(&self, lhs: T, rhs: T) -> bool {
+        match self {
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+        }
+    }
+
+    /// Compare values of the same type, values of different types never match.
+    #[inline]
+    fn compare(&self, lhs: &Value, rhs: &Value) -> bool {
+        match (lhs, rhs) {
+            (Value::U64(a), Value::U64(b)) => self.apply(a, b),
+            (Value::I64(a), Value::I64(b)) => self.apply(a, b),
+            (Value::F32(a), Value::F32(b)) => self.apply(a, b),
+            (Value::F64(a), Value::F64(b)) => self.apply(a, b),
+            (Value::Bool(a), Value::Bool(b)) => self.apply(a, b),
+            (Value::String(a), Value::String(b)) => self.apply(a, b),
+            _ => false,
+        }
+    }
+}
+
+/// Column in predicate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Column {
+    /// Column name.
+    pub name: String,
+
+    /// Global id of the column, `None` before resolved.
+    pub id: Option<u32>,
+
+    /// Type of the column, `None` before resolved.
+    pub data_type: Option<DataType>,
+}
+
+impl Column {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            id: None,
+            data_type: None,
+        }
+    }
+
+    /// Resolve the id and type of the column by name, return the type.
+    fn resolve<F: FnMut(&str) -> Result<(u32, DataType)>>(
+        &mut self,
+        resolver: &mut F,
+    ) -> Result<DataType> {
+        let (id, data_type) = resolver(self.name.as_str())?;
+
+        self.id = Some(id);
+        self.data_type = Some(data_type);
+
+        Ok(data_type)
+    }
+}
+
+/// Predicate AST.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Predicate {
+    Compare(Column, CompareOp, Value),
+    In(Column, Vec<Value>),
+    Has(Column),
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+}
+
+impl Predicate {
+    /// Parse predicate from string.
+    pub fn parse(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+
+        let mut parser = Parser { tokens, pos: 0 };
+        let predicate = parser.parse_or()?;
+
+        if parser.pos < parser.tokens.len() {
+            error_bail!(
+                "Unexpected token in predicate: {:?}, predicate: {}",
+                parser.tokens[parser.pos],
+                s
+            );
+        }
+
+        Ok(predicate)
+    }
+
+    /// All columns in the predicate.
+    pub fn columns(&self) -> Vec<&Column> {
+        let mut res = Vec::new();
+        self.collect_columns(&mut res);
+        res
+    }
+
+    fn collect_columns<'a>(&'a self, res: &mut Vec<&'a Column>) {
+        match self {
+            Predicate::Compare(column, _, _)
+            | Predicate::In(column, _)
+            | Predicate::Has(column) => res.push(column),
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                a.collect_columns(res);
+                b.collect_columns(res);
+            }
+            Predicate::Not(a) => a.collect_columns(res),
+        }
+    }
+
+    /// Names of all columns in the predicate, without duplication.
+    pub fn column_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = Vec::new();
+
+        for column in self.columns() {
+            if !names.contains(&column.name) {
+                names.push(column.name.clone());
+            }
+        }
+
+        names
+    }
+
+    /// Ids of all resolved columns in the predicate, without duplication.
+    pub fn column_ids(&self) -> Vec<u32> {
+        let mut ids = Vec::new();
+
+        for id in self.columns().iter().filter_map(|column| column.id) {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+
+        ids
+    }
+
+    /// Resolve the column names to global ids and types, and cast the literals to the types of
+    /// the columns.
+    pub fn resolve<F: FnMut(&str) -> Result<(u32, DataType)>>(
+        &mut self,
+        resolver: &mut F,
+    ) -> Result<()> {
+        match self {
+            Predicate::Compare(column, _, value) => {
+                let data_type = column.resolve(resolver)?;
+                *value = value.cast(data_type)?;
+            }
+            Predicate::In(column, values) => {
+                let data_type = column.resolve(resolver)?;
+
+                for value in values.iter_mut() {
+                    *value = value.cast(data_type)?;
+                }
+            }
+            Predicate::Has(column) => {
+                column.resolve(resolver)?;
+            }
+            Predicate::And(a, b) | Predicate::Or(a, b) => {
+                a.resolve(resolver)?;
+                b.resolve(resolver)?;
+            }
+            Predicate::Not(a) => a.resolve(resolver)?,
+        }
+
+        Ok(())
+    }
+
+    /// Evaluate the predicate on the row.
+    ///
+    /// Unresolved columns are treated as missing.
+    pub fn eval(&self, row: &GridRow) -> bool {
+        match self {
+            Predicate::Compare(column, op, value) => {
+                Self::any_value(row, column, |v| op.compare(v, value))
+            }
+            Predicate::In(column, values) => Self::any_value(row, column, |v| {
+                values.iter().any(|x| CompareOp::Eq.compare(v, x))
+            }),
+            Predicate::Has(column) => Self::any_value(row, column, |_| true),
+            Predicate::And(a, b) => a.eval(row) && b.eval(row),
+            Predicate::Or(a, b) => a.eval(row) || b.eval(row),
+            Predicate::Not(a) => !a.eval(row),
+        }
+    }
+
+    #[inline]
+    fn get_col(row: &GridRow, column: &Column) -> Option<usize> {
+        column
+            .id
+            .and_then(|id| row.get_gridbuffer().get_col_by_id(id))
+    }
+
+    /// Whether any value of the column matches `f`, the values are decoded by the type of the
+    /// column.
+    ///
+    /// False if the column is missing, or the cell does not match the type of the column.
+    #[inline]
+    fn any_value<F: Fn(&Value) -> bool>(row: &GridRow, column: &Column, f: F) -> bool {
+        let (col, data_type) = match (Self::get_col(row, column), column.data_type) {
+            (Some(col), Some(data_type)) => (col, data_type.element_type()),
+            _ => return false,
+        };
+
+        match row.get_cell(col) {
+            Some(GridCell::F32Cell(_)) if data_type == DataType::F32 => {
+                row.get_f32_values(col).iter().any(|v| f(&Value::F32(*v)))
+            }
+            Some(GridCell::U64Cell(_)) => {
+                let values = row.get_u64_values(col);
+
+                match data_type {
+                    DataType::U64 | DataType::U32 => values.iter().any(|v| f(&Value::U64(*v))),
+                    DataType::I64 | DataType::I32 => {
+                        values.iter().any(|v| f(&Value::I64(decode_i64(*v))))
+                    }
+                    DataType::F64 => values.iter().any(|v| f(&Value::F64(decode_f64(*v)))),
+                    DataType::Bool => values.iter().any(|v| f(&Value::Bool(decode_bool(*v)))),
+                    DataType::String => match decode_strings(values) {
+                        Ok(strings) => strings.into_iter().any(|v| f(&Value::String(v))),
+                        Err(e) => {
+                            error!(
+                                "Failed to decode strings of column {}, error: {}",
+                                column.name, e
+                            );
+                            false
+                        }
+                    },
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Op(CompareOp),
+    And,
+    Or,
+    Not,
+    In,
+    Has,
+    LeftParen,
+    RightParen,
+    Comma,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let chars = s.chars().collect::<Vec<char>>();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '(' || c == ')' || c == ',' {
+            tokens.push(match c {
+                '(' => Token::LeftParen,
+                ')' => Token::RightParen,
+                _ => Token::Comma,
+            });
+            i += 1;
+        } else if "=!<>".contains(c) {
+            let next = chars.get(i + 1).cloned();
+
+            let (op, len) = match (c, next) {
+                ('=', Some('=')) => (CompareOp::Eq, 2),
+                ('!', Some('=')) => (CompareOp::Ne, 2),
+                ('<', Some('=')) => (CompareOp::Le, 2),
+                ('>', Some('=')) => (CompareOp::Ge, 2),
+                ('<', _) => (CompareOp::Lt, 1),
+                ('>', _) => (CompareOp::Gt, 1),
+                _ => {
+                    error_bail!("Invalid operator at {} in predicate: {}", i, s);
+                }
+            };
+
+            tokens.push(Token::Op(op));
+            i += len;
+        } else if c.is_ascii_digit() || c == '-' || c == '.' {
+            let start = i;
+            i += 1;
+
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+
+            let text = chars[start..i].iter().collect::<String>();
+
+            let value = if text.contains('.') {
+                text.parse::<f64>().ok().map(Value::F64)
+            } else if text.starts_with('-') {
+                text.parse::<i64>().ok().map(Value::I64)
+            } else {
+                text.parse::<u64>().ok().map(Value::U64)
+            };
+
+            match value {
+                Some(value) => tokens.push(Token::Literal(value)),
+                None => {
+                    error_bail!("Invalid number: {} in predicate: {}", text, s);
+                }
+            }
+        } else if c == '\'' || c == '"' {
+            let start = i;
+            let mut text = String::new();
+            i += 1;
+
+            while i < chars.len() && chars[i] != c {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+
+                if let Some(next) = chars.get(i) {
+                    text.push(*next);
+                }
+
+                i += 1;
+            }
+
+            if i >= chars.len() {
+                error_bail!("Unterminated string at {} in predicate: {}", start, s);
+            }
+
+            tokens.push(Token::Literal(Value::String(text)));
+            i += 1;
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            let word = chars[start..i].iter().collect::<String>();
+
+            tokens.push(match word.to_uppercase().as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                "IN" => Token::In,
+                "HAS" => Token::Has,
+                "TRUE" => Token::Literal(Value::Bool(true)),
+                "FALSE" => Token::Literal(Value::Bool(false)),
+                _ => Token::Ident(word),
+            });
+        } else {
+            error_bail!("Invalid character: {} at {} in predicate: {}", c, i, s);
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser of predicate.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_token(&mut self) -> Result<Token> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.clone())
+            }
+            None => {
+                error_bail!("Unexpected end of predicate");
+            }
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<()> {
+        let token = self.next_token()?;
+
+        if token != expected {
+            error_bail!("Expect {:?}, but got {:?} in predicate", expected, token);
+        }
+
+        Ok(())
+    }
+
+    fn parse_or(&mut self) -> Result<Predicate> {
+        let mut res = self.parse_and()?;
+
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            res = Predicate::Or(Box::new(res), Box::new(self.parse_and()?));
+        }
+
+        Ok(res)
+    }
+
+    fn parse_and(&mut self) -> Result<Predicate> {
+        let mut res = self.parse_unary()?;
+
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            res = Predicate::And(Box::new(res), Box::new(self.parse_unary()?));
+        }
+
+        Ok(res)
+    }
+
+    fn parse_unary(&mut self) -> Result<Predicate> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Predicate::Not(Box::new(self.parse_unary()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_value(&mut self) -> Result<Value> {
+        match self.next_token()? {
+            Token::Literal(value) => Ok(value),
+            token => {
+                error_bail!("Expect literal, but got {:?} in predicate", token);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Predicate> {
+        match self.next_token()? {
+            Token::LeftParen => {
+                let res = self.parse_or()?;
+                self.expect(Token::RightParen)?;
+                Ok(res)
+            }
+            Token::Has => {
+                self.expect(Token::LeftParen)?;
+
+                let res = match self.next_token()? {
+                    Token::Ident(name) => Predicate::Has(Column::new(name.as_str())),
+                    token => {
+                        error_bail!("Expect column name in has(), but got {:?}", token);
+                    }
+                };
+
+                self.expect(Token::RightParen)?;
+                Ok(res)
+            }
+            Token::Ident(name) => match self.next_token()? {
+                Token::Op(op) => Ok(Predicate::Compare(
+                    Column::new(name.as_str()),
+                    op,
+                    self.parse_value()?,
+                )),
+                Token::In => {
+                    self.expect(Token::LeftParen)?;
+
+                    let mut values = vec![self.parse_value()?];
+                    while self.peek() == Some(&Token::Comma) {
+                        self.pos += 1;
+                        values.push(self.parse_value()?);
+                    }
+
+                    self.expect(Token::RightParen)?;
+                    Ok(Predicate::In(Column::new(name.as_str()), values))
+                }
+                token => {
+                    error_bail!(
+                        "Expect operator after column {}, but got {:?} in predicate",
+                        name,
+                        token
+                    );
+                }
+            },
+            token => {
+                error_bail!("Unexpected token {:?} in predicate", token);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid_sample::{GridSample, SampleKey};
+    use crate::tool::setup_log;
+    use std::sync::Arc;
+
+    fn resolve(predicate: &mut Predicate) -> Result<()> {
+        predicate.resolve(&mut |name: &str| match name {
+            "item_id" => Ok((5, DataType::U64)),
+            "label_0" => Ok((100, DataType::U64)),
+            "ctr" => Ok((101, DataType::F32)),
+            "tags" => Ok((102, DataType::U64List)),
+            "missing" => Ok((103, DataType::U64)),
+            "score" => Ok((104, DataType::I64)),
+            "price" => Ok((105, DataType::F64)),
+            "is_click" => Ok((106, DataType::Bool)),
+            "city" => Ok((107, DataType::String)),
+            "topics" => Ok((108, DataType::StringList)),
+            _ => bail!("unknown column: {}", name),
+        })
+    }
+
+    #[test]
+    fn test_parse_predicate() -> Result<()> {
+        setup_log();
+
+        let predicate = Predicate::parse("label_0 == 1 AND item_id in (3, 4) or NOT has(ctr)")?;
+
+        let expected = Predicate::Or(
+            Box::new(Predicate::And(
+                Box::new(Predicate::Compare(
+                    Column::new("label_0"),
+                    CompareOp::Eq,
+                    Value::U64(1),
+                )),
+                Box::new(Predicate::In(
+                    Column::new("item_id"),
+                    vec![Value::U64(3), Value::U64(4)],
+                )),
+            )),
+            Box::new(Predicate::Not(Box::new(Predicate::Has(Column::new("ctr"))))),
+        );
+
+        assert_eq!(predicate, expected);
+        assert_eq!(predicate.column_names(), vec!["label_0", "item_id", "ctr"]);
+
+        assert!(Predicate::parse("label_0 == ").is_err());
+        assert!(Predicate::parse("label_0 = 1").is_err());
+        assert!(Predicate::parse("(label_0 == 1").is_err());
+        assert!(Predicate::parse("label_0 == 1 item_id == 2").is_err());
+
+        let predicate = Predicate::parse(
+            "score > -3 AND price <= 1.5 AND is_click == TRUE OR city IN ('a\\'b', \"c\")",
+        )?;
+
+        let expected = Predicate::Or(
+            Box::new(Predicate::And(
+                Box::new(Predicate::And(
+                    Box::new(Predicate::Compare(
+                        Column::new("score"),
+                        CompareOp::Gt,
+                        Value::I64(-3),
+                    )),
+                    Box::new(Predicate::Compare(
+                        Column::new("price"),
+                        CompareOp::Le,
+                        Value::F64(1.5),
+                    )),
+                )),
+                Box::new(Predicate::Compare(
+                    Column::new("is_click"),
+                    CompareOp::Eq,
+                    Value::Bool(true),
+                )),
+            )),
+            Box::new(Predicate::In(
+                Column::new("city"),
+                vec![
+                    Value::String("a'b".to_string()),
+                    Value::String("c".to_string()),
+                ],
+            )),
+        );
+
+        assert_eq!(predicate, expected);
+        assert!(Predicate::parse("city == 'abc").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_predicate() -> Result<()> {
+        setup_log();
+
+        let mut sample = GridSample::new(2, &vec![100, 101, 102]);
+
+        sample.set_sample_key(0, &SampleKey::new(1, 1, 3, 1));
+        sample.push_u64(0, 4, 1);
+        sample.push_f32(0, 5, 0.25);
+        sample.push_u64_values(0, 6, &[7, 8]);
+
+        sample.set_sample_key(1, &SampleKey::new(2, 1, 5, 2));
+        sample.push_u64(1, 4, 0);
+        sample.push_u64_values(1, 6, &[9]);
+
+        let gridbuffer = Arc::new(sample.gridbuffer);
+        let rows = [
+            GridRow::new(gridbuffer.clone(), 0),
+            GridRow::new(gridbuffer.clone(), 1),
+        ];
+
+        let cases = [
+            ("label_0 == 1", [true, false]),
+            ("label_0 != 1 AND item_id IN (3, 5)", [false, true]),
+            ("ctr > 0.1", [true, false]),
+            ("has(ctr)", [true, false]),
+            ("tags == 8", [true, false]),
+            ("tags >= 9 OR NOT has(ctr)", [false, true]),
+            ("missing == 0 OR has(missing)", [false, false]),
+            ("NOT (label_0 == 1 OR item_id == 5)", [false, false]),
+        ];
+
+        for (s, expected) in cases.iter() {
+            let mut predicate = Predicate::parse(s)?;
+            resolve(&mut predicate)?;
+
+            for (row, expected) in rows.iter().zip(expected.iter()) {
+                assert_eq!(predicate.eval(row), *expected, "predicate: {}", s);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eval_typed_predicate() -> Result<()> {
+        setup_log();
+
+        let mut sample = GridSample::new(2, &vec![104, 105, 106, 107, 108]);
+
+        sample.set_sample_key(0, &SampleKey::new(1, 1, 3, 1));
+        sample.push_i64(0, 4, -5);
+        sample.push_f64(0, 5, 1.25);
+        sample.push_bool(0, 6, true);
+        sample.push_string(0, 7, "beijing");
+        sample.push_string_values(0, 8, &["sports", "music"]);
+
+        sample.set_sample_key(1, &SampleKey::new(2, 1, 5, 2));
+        sample.push_i64(1, 4, 7);
+        sample.push_f64(1, 5, -2.5);
+        sample.push_bool(1, 6, false);
+        sample.push_string(1, 7, "shanghai");
+
+        let gridbuffer = Arc::new(sample.gridbuffer);
+        let rows = [
+            GridRow::new(gridbuffer.clone(), 0),
+            GridRow::new(gridbuffer.clone(), 1),
+        ];
+
+        let cases = [
+            ("score < 0", [true, false]),
+            ("score >= -5 AND score != 7", [true, false]),
+            ("score IN (-5, 7)", [true, true]),
+            ("price > 1.0", [true, false]),
+            ("price < -1", [false, true]),
+            ("is_click == true", [true, false]),
+            ("is_click != TRUE", [false, true]),
+            ("city == 'beijing'", [true, false]),
+            ("city IN (\"shanghai\", 'shenzhen')", [false, true]),
+            ("city > 'c'", [false, true]),
+            ("topics == 'music'", [true, false]),
+            ("has(topics)", [true, false]),
+            ("ctr == 1", [false, false]),
+        ];
+
+        for (s, expected) in cases.iter() {
+            let mut predicate = Predicate::parse(s)?;
+            resolve(&mut predicate)?;
+
+            for (row, expected) in rows.iter().zip(expected.iter()) {
+                assert_eq!(predicate.eval(row), *expected, "predicate: {}", s);
+            }
+        }
+
+        let invalid = [
+            "label_0 == 'a'",
+            "label_0 == -1",
+            "label_0 == 1.5",
+            "score == true",
+            "is_click == 1",
+            "city == 1",
+            "city IN ('a', 1)",
+        ];
+
+        for s in invalid.iter() {
+            let mut predicate = Predicate::parse(s)?;
+            assert!(resolve(&mut predicate).is_err(), "predicate: {}", s);
+        }
+
+        Ok(())
+    }
+}
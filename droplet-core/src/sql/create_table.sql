@@ -9,6 +9,9 @@ CREATE TABLE IF NOT EXISTS id_mapping (
     KEY (key_str)
 );
 
+-- reserve the fixed ids of the sample keys, see SampleKey::get_sample_key_pair, new ids start after them
+INSERT IGNORE INTO id_mapping (key_id, key_str) VALUES (2, 'timestamp'), (4, 'user_id'), (5, 'item_id'), (6, 'request_id');
+
 -- create table for feature info
 CREATE TABLE IF NOT EXISTS feature_info (
     feature_name VARCHAR(255) NOT NULL PRIMARY KEY,
@@ -21,16 +24,31 @@ CREATE TABLE worker_node_info (
     node_name VARCHAR(255) NOT NULL COMMENT 'node name',
     node_ip VARCHAR(255) NOT NULL COMMENT 'node ip',
     node_port INT NOT NULL COMMENT 'node port',
-    node_status INT NOT NULL COMMENT 'node status, 0 for down, 1 for alive',
+    node_status INT NOT NULL COMMENT 'node status, same as NodeStatus: 1 for alive, 3 for unhealthy, 4 for offline',
+    last_heartbeat_at TIMESTAMP NULL DEFAULT NULL COMMENT 'last heartbeat time',
+    total_disk_size BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'total disk size of data roots in bytes, 0 if not reported',
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP COMMENT 'created time',
     updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP COMMENT 'updated time',
     UNIQUE KEY (node_name)
 );
 
+CREATE TABLE node_storage_info (
+    id INT AUTO_INCREMENT PRIMARY KEY,
+    node_id INT NOT NULL COMMENT 'node id',
+    used_disk_size BIGINT UNSIGNED NOT NULL COMMENT 'used disk size in bytes',
+    total_disk_size BIGINT UNSIGNED NOT NULL COMMENT 'total disk size in bytes',
+    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP COMMENT 'report time',
+    KEY (node_id, updated_at)
+);
+
 CREATE TABLE table_info (
     id INT AUTO_INCREMENT PRIMARY KEY,
     table_name VARCHAR(255) NOT NULL COMMENT 'table name',
     partition_count_per_day INT NOT NULL COMMENT 'partition count per day',
+    timestamp_unit INT NOT NULL DEFAULT 0 COMMENT 'unit of timestamp, 0 for seconds, 1 for milliseconds, 2 for microseconds',
+    tz_offset_seconds INT NOT NULL DEFAULT 0 COMMENT 'timezone of partitions in seconds east of UTC',
+    schema_version INT NOT NULL DEFAULT 1 COMMENT 'increased by each change of columns',
+    key_columns VARCHAR(255) NOT NULL DEFAULT '' COMMENT 'key columns separated by comma, empty means default',
     UNIQUE KEY (table_name)
 );
 
@@ -41,7 +59,9 @@ CREATE TABLE column_info (
     column_type INT NOT NULL COMMENT 'column type',
     column_index INT NOT NULL COMMENT 'column index',
     column_id INT NOT NULL COMMENT 'global unique id for column according to id_mapping',
-    column_comment VARCHAR(255) NOT NULL COMMENT 'column comment',
+    column_comment VARCHAR(255) NOT NULL DEFAULT '' COMMENT 'column comment',
+    deprecated TINYINT NOT NULL DEFAULT 0 COMMENT 'whether the column is deprecated, 1 for deprecated',
+    schema_version INT NOT NULL DEFAULT 1 COMMENT 'schema version of the table when the column is added',
     UNIQUE KEY (table_name, column_name, column_id)
 );
 
@@ -53,5 +73,24 @@ CREATE TABLE partition_info (
     node_id INT NOT NULL COMMENT 'node id',
     time_start TIMESTAMP NOT NULL COMMENT 'time start',
     time_end TIMESTAMP NOT NULL COMMENT 'time end',
+    partition_status INT NOT NULL DEFAULT 0 COMMENT 'partition status, same as PartitionStatus: 0 for active, 1 for to delete',
+    row_count BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'row count after merge',
+    byte_size BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'byte size of files after merge',
+    schema_version INT NOT NULL DEFAULT 1 COMMENT 'schema version of the table when the partition is created',
     UNIQUE KEY (table_name, partition_date, partition_index)
+);
+
+CREATE TABLE partition_info_to_delete (
+    id INT PRIMARY KEY COMMENT 'id in partition_info',
+    table_name VARCHAR(255) NOT NULL COMMENT 'table name',
+    partition_date INT NOT NULL COMMENT 'partition date, format: YYYYMMDD',
+    partition_index INT NOT NULL COMMENT 'partition index',
+    node_id INT NOT NULL COMMENT 'node id',
+    time_start TIMESTAMP NOT NULL COMMENT 'time start',
+    time_end TIMESTAMP NOT NULL COMMENT 'time end',
+    partition_status INT NOT NULL DEFAULT 1 COMMENT 'partition status, always 1 for to delete',
+    row_count BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'row count after merge',
+    byte_size BIGINT UNSIGNED NOT NULL DEFAULT 0 COMMENT 'byte size of files after merge',
+    schema_version INT NOT NULL DEFAULT 1 COMMENT 'schema version of the table when the partition is created',
+    KEY (table_name)
 );
\ No newline at end of file
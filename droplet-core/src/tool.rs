@@ -1,5 +1,5 @@
 #![allow(unused)]
-use anyhow::bail;
+use anyhow::{bail, Result};
 use likely_stable::unlikely;
 
 use log::{error, info};
@@ -81,6 +81,25 @@ pub fn is_keys_equal(a: &[u32], b: &[u32]) -> bool {
     true
 }
 
+/// Current unix timestamp in seconds.
+pub fn get_timestamp_secs() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+/// Read a `u64` from the environment variable, `None` if not set, error if it's not a valid
+/// `u64`.
+pub fn get_env_u64(name: &str) -> Result<Option<u64>> {
+    match std::env::var(name) {
+        Ok(s) => match s.parse::<u64>() {
+            Ok(value) => Ok(Some(value)),
+            Err(e) => {
+                error_bail!("Invalid value of {}: {}, error: {}", name, s, e);
+            }
+        },
+        Err(_) => Ok(None),
+    }
+}
+
 async fn wait_for_signal_impl() {
     // Infos here:
     // https://www.gnu.org/software/libc/manual/html_node/Termination-Signals.html
@@ -105,6 +124,24 @@ pub async fn wait_for_signal() {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_get_env_u64() -> Result<()> {
+        let name = "DROPLET_TEST_GET_ENV_U64";
+
+        std::env::remove_var(name);
+        assert_eq!(get_env_u64(name)?, None);
+
+        std::env::set_var(name, "42");
+        assert_eq!(get_env_u64(name)?, Some(42));
+
+        std::env::set_var(name, "-1");
+        assert!(get_env_u64(name).is_err());
+
+        std::env::remove_var(name);
+
+        Ok(())
+    }
+
     #[test]
     fn test_is_keys_equal() {
         // Test equal keys
@@ -2,11 +2,12 @@ use anyhow::{bail, Result};
 use gridbuffer::core::gridbuffer::GridBuffer;
 use likely_stable::unlikely;
 use log::error;
-use std::cmp::Reverse;
+use std::cmp::{Ordering, Reverse};
 use std::collections::BinaryHeap;
+use std::sync::Arc;
 
 use crate::error_bail;
-use crate::grid_sample::{GridRow, GridRows};
+use crate::grid_sample::{GridRow, GridRows, KeySchema, SampleKey, MAX_KEY_COLUMNS};
 
 /// The key type to compare the elements.
 pub trait HeapOrderKey {
@@ -15,8 +16,13 @@ pub trait HeapOrderKey {
     fn key(&self) -> Self::Key;
 }
 
-#[derive(Eq, Ord, PartialEq, PartialOrd)]
 pub struct WindowHeapItem {
+    /// The `SampleKey` of the row.
+    ///
+    /// Computed once when the item is created, so comparing items in the heap does not need to
+    /// read the `GridBuffer`.
+    pub key: SampleKey,
+
     /// The row of the element in gridbuffer.
     pub gridrow: GridRow,
 
@@ -29,15 +35,12 @@ pub struct WindowHeapItem {
 
 impl WindowHeapItem {
     pub fn new(gridrow: GridRow, index: usize) -> Self {
-        Self {
-            gridrow,
-            index,
-            reader_index: 0,
-        }
+        Self::with_reader_index(gridrow, index, 0)
     }
 
     pub fn with_reader_index(gridrow: GridRow, index: usize, reader_index: usize) -> Self {
         Self {
+            key: gridrow.get_sample_key(),
             gridrow,
             index,
             reader_index,
@@ -45,6 +48,26 @@ impl WindowHeapItem {
     }
 }
 
+impl PartialEq for WindowHeapItem {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for WindowHeapItem {}
+
+impl Ord for WindowHeapItem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.key, self.index, self.reader_index).cmp(&(other.key, other.index, other.reader_index))
+    }
+}
+
+impl PartialOrd for WindowHeapItem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 /// A min-heap to maintain the top `window_size` elements.
 ///
 /// To achieve better performance, we need to avoid `malloc` and `copy` as much as possible.
@@ -73,8 +96,10 @@ pub struct WindowHeap {
 
     /// The elements in the heap.
     ///
-    /// All elements must have same column ids, which means same `cols` in `GridBuffer`.
-    elements: Vec<GridBuffer>,
+    /// All elements must have same column ids, which means same `cols` in `GridBuffer`. They are
+    /// shared with the `GridRow`s, so an element can be replaced while its rows are still waiting
+    /// in `gridrows`.
+    elements: Vec<Arc<GridBuffer>>,
 
     /// The number of rows in each result `GridBuffer`.
     batch_size: usize,
@@ -105,6 +130,9 @@ pub struct WindowHeap {
 
     /// The hash of `col_ids`.
     col_ids_hash: u32,
+
+    /// The number of key columns the rows are ordered by, see `KeySchema`.
+    num_key_cols: usize,
 }
 
 impl WindowHeap {
@@ -124,9 +152,16 @@ impl WindowHeap {
             gridrows: GridRows::new(),
             col_ids: Vec::new(),
             col_ids_hash: 0,
+            num_key_cols: MAX_KEY_COLUMNS,
         }
     }
 
+    /// Order the rows by the key columns of `key_schema`, default is the fixed sample key.
+    pub fn with_key_schema(mut self, key_schema: &KeySchema) -> Self {
+        self.num_key_cols = key_schema.num_cols();
+        self
+    }
+
     /// Push a new element into the heap.
     ///
     /// The `cols` of all elements must be same.
@@ -171,6 +206,8 @@ impl WindowHeap {
             );
         }
 
+        let gridbuffer = Arc::new(gridbuffer);
+
         match self.available_positions.pop() {
             Some(index) => {
                 if index < self.elements.len() {
@@ -188,7 +225,11 @@ impl WindowHeap {
                 self.num_rows_left[index] = self.elements[index].num_rows();
 
                 for i in 0..self.elements[index].num_rows() {
-                    let row = GridRow::new(&self.elements[index], i);
+                    let row = GridRow::with_num_key_cols(
+                        self.elements[index].clone(),
+                        i,
+                        self.num_key_cols,
+                    );
                     let item = WindowHeapItem::with_reader_index(row, index, reader_index);
 
                     self.heap.push(Reverse(item));
@@ -223,8 +264,6 @@ impl WindowHeap {
 
         let index = item.index;
 
-        // Must convert to `GridBuffer` before drop the element, because the `GridRow` contains the
-        // pointer of `GridBuffer`.
         if self.gridrows.len() >= self.batch_size {
             // For simplicity, we use the last reader index of item as the next reader to be used.
             self.out_reader_indexes.push(item.reader_index);
@@ -274,6 +313,38 @@ impl WindowHeap {
         &self.out_reader_indexes
     }
 
+    /// The min timestamp of the rows not taken by `get_out_gridbuffer` yet, including the rows
+    /// in the heap, the rows popped but not output, and the output `GridBuffer`s. `None` if no
+    /// row is held.
+    ///
+    /// Rows are only sorted within the window, a `GridBuffer` output later may be earlier than
+    /// the ones output before, but not earlier than the rows still held, unless the rows pushed
+    /// later are earlier. So it's a conservative event-time watermark of the sorted output.
+    pub fn min_timestamp(&self) -> Option<u64> {
+        let heap_min = self.heap.peek().map(|Reverse(item)| item.key.timestamp);
+
+        let gridrows_min = self
+            .gridrows
+            .rows
+            .iter()
+            .map(|row| row.get_sample_key().timestamp)
+            .min();
+
+        let out_min = self
+            .out_gridbuffers
+            .iter()
+            .flat_map(|gridbuffer| {
+                (0..gridbuffer.num_rows())
+                    .map(move |row| SampleKey::from_key_cols(gridbuffer, row, 1).timestamp)
+            })
+            .min();
+
+        [heap_min, gridrows_min, out_min]
+            .into_iter()
+            .flatten()
+            .min()
+    }
+
     pub fn len(&self) -> usize {
         self.heap.len()
     }
@@ -291,6 +362,7 @@ impl WindowHeap {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::db::memory_store::MemoryMetaStore;
     use crate::id_mapping::IDMapping;
     use crate::{grid_sample::SampleKey, tool::setup_log};
     use anyhow::Result;
@@ -299,7 +371,7 @@ mod tests {
     fn create_test_gridbuffer(num_rows: usize) -> Result<GridBuffer> {
         setup_log();
 
-        let id_mapping = IDMapping::new()?;
+        let id_mapping = IDMapping::with_store(Arc::new(MemoryMetaStore::new()));
 
         let feature_names = vec![
             "ExtractSparse0".to_string(),
@@ -404,4 +476,90 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_window_heap_min_timestamp() -> Result<()> {
+        let new_gridbuffer = |timestamps: &[u64]| {
+            let mut gb = GridBuffer::new_with_num_rows_col_ids(
+                timestamps.len(),
+                SampleKey::get_sample_key_ids().to_vec(),
+            );
+
+            for (row, timestamp) in timestamps.iter().enumerate() {
+                gb.push_u64(row, 0, *timestamp);
+                for col in 1..4 {
+                    gb.push_u64(row, col, 0);
+                }
+            }
+
+            gb
+        };
+
+        // Rows in the heap.
+        let mut heap = WindowHeap::new(2, 3);
+        assert_eq!(heap.min_timestamp(), None);
+
+        heap.push(new_gridbuffer(&[5, 6]))?;
+        assert_eq!(heap.min_timestamp(), Some(5));
+
+        heap.push(new_gridbuffer(&[3, 4]))?;
+        assert_eq!(heap.min_timestamp(), Some(3));
+
+        // Rows popped from the heap but not enough for a batch.
+        heap.process_remain_data();
+        assert!(heap.out_gridbuffers().is_empty());
+        assert_eq!(heap.min_timestamp(), Some(3));
+
+        // Output `GridBuffer`s not taken yet.
+        let mut heap = WindowHeap::new(2, 2);
+        heap.push(new_gridbuffer(&[5, 6]))?;
+        heap.push(new_gridbuffer(&[3, 4]))?;
+        heap.process_remain_data();
+
+        assert_eq!(heap.out_gridbuffers().len(), 1);
+        assert_eq!(heap.min_timestamp(), Some(3));
+
+        heap.get_out_gridbuffer();
+        assert_eq!(heap.min_timestamp(), Some(5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_window_heap_key_schema() -> Result<()> {
+        // Key columns `[timestamp, 100]`, column `4` is not part of the key.
+        let key_schema = KeySchema::new(vec![2, 100])?;
+        let mut heap = WindowHeap::new(2, 2).with_key_schema(&key_schema);
+
+        for values in [
+            [(1, 5, 0), (2, 1, 0)],
+            [(1, 3, 9), (2, 0, 9)],
+            [(3, 0, 0), (3, 1, 0)],
+        ] {
+            let mut gb = GridBuffer::new_with_num_rows_col_ids(2, vec![2, 100, 4]);
+
+            for (row, (timestamp, key, value)) in values.iter().enumerate() {
+                gb.push_u64(row, 0, *timestamp);
+                gb.push_u64(row, 1, *key);
+                gb.push_u64(row, 2, *value);
+            }
+
+            heap.push(gb)?;
+        }
+
+        assert_eq!(heap.out_gridbuffers().len(), 1);
+
+        let out_gb = heap.get_out_gridbuffer().unwrap();
+        let keys: Vec<SampleKey> = (0..out_gb.num_rows())
+            .map(|row| key_schema.get_sample_key(&out_gb, row))
+            .collect();
+
+        assert_eq!(
+            keys,
+            vec![SampleKey::new(1, 3, 0, 0), SampleKey::new(1, 5, 0, 0)]
+        );
+        assert_eq!(out_gb.get_u64(0, 2), Some(9));
+
+        Ok(())
+    }
 }
@@ -1,6 +1,7 @@
 use droplet_core::{
-    db::{db::DB, feature::insert_sample_keys},
-    feature_info::FeatureConfig,
+    db::{memory_store::MemoryMetaStore, meta_store::MetaStore},
+    feature_info::{DataType, FeatureConfig, FeatureInfo},
+    grid_sample::SampleKey,
     local_file_reader::LocalFileReader,
     tool::setup_log,
 };
@@ -43,8 +44,27 @@ fn update_feature_config_to_db() -> Result<()> {
     let feature_config_path = "resources/dsp_ctr_simple_features.config";
     let feature_config = FeatureConfig::from_config_file(feature_config_path)?;
 
-    let db = DB::new()?;
-    feature_config.write_to_db(&db)?;
+    let store = MemoryMetaStore::new();
+    feature_config.write_to_store(&store)?;
+
+    // Writing the same config again is fine.
+    feature_config.write_to_store(&store)?;
+
+    assert!(!feature_config.feature_infos().is_empty());
+
+    for feature_info in feature_config.feature_infos() {
+        assert!(store.get_key_id(&feature_info.feature_name)?.is_some());
+    }
+
+    // The data type of an existing feature cannot change.
+    let feature_name = feature_config.feature_infos()[0].feature_name.as_str();
+    let data_type = match feature_config.feature_infos()[0].data_type {
+        DataType::F32 => DataType::U64,
+        _ => DataType::F32,
+    };
+
+    let changed = FeatureConfig::new(vec![FeatureInfo::new(feature_name, data_type)]);
+    assert!(changed.write_to_store(&store).is_err());
 
     Ok(())
 }
@@ -53,8 +73,13 @@ fn update_feature_config_to_db() -> Result<()> {
 fn test_insert_sample_keys() -> Result<()> {
     setup_log();
 
-    let db = DB::new()?;
-    insert_sample_keys(&db)?;
+    let store = MemoryMetaStore::new();
+    store.insert_sample_keys()?;
+    store.insert_sample_keys()?;
+
+    for name in SampleKey::get_sample_key_names() {
+        assert!(store.get_key_id(name)?.is_some());
+    }
 
     Ok(())
 }
@@ -1,73 +1,204 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use droplet_core::droplet::ColumnInfo;
 use gethostname::gethostname;
-
-use std::sync::Arc;
-
-use droplet_core::db::db::DB;
-
-use droplet_core::db::meta_info::{
-    get_key_ids, get_or_insert_key_id, get_partition_count_per_day,
-    get_server_endpoint_by_partition_index, get_table_paths_by_date, insert_table_info,
-    is_table_exist,
-};
+use log::error;
 
 use droplet_core::droplet::meta_client::MetaClient;
+use droplet_core::droplet::{
+    GetPartitionInfoRequest, GetPartitionPathsRequest, GetPartitionPathsResponse,
+    GetServerEndpointRequest, GetTableInfoRequest, InsertTableInfoRequest, IsTableExistRequest,
+    PartitionInfo, ResolveKeyIdsRequest, TimestampUnit,
+};
+use droplet_core::error_bail;
+use droplet_core::grid_sample::KeySchema;
+use droplet_core::partition::{get_table_path, PartitionScheme};
 use droplet_meta_server::tool::{get_meta_server_client, get_meta_server_default_client};
 use droplet_server::tool::DROPPLET_SERVER_PORT;
 
+/// Wrapper of grpc meta client.
+///
+/// All meta info is got from meta server, so the users don't need to access the database.
 pub struct MetaClientWrapper {
-    db: Arc<DB>,
     client: MetaClient<tonic::transport::Channel>,
 }
 
 impl MetaClientWrapper {
-    pub async fn new(db: Arc<DB>, meta_server_endpoint: &String) -> Result<Self> {
+    pub async fn new(meta_server_endpoint: &String) -> Result<Self> {
         let client = get_meta_server_client(meta_server_endpoint).await?;
-        Ok(Self { db, client })
+        Ok(Self { client })
     }
 
     pub async fn get_default_client() -> Result<Self> {
-        let db = Arc::new(DB::new()?);
         let client = get_meta_server_default_client().await?;
 
-        Ok(Self { db, client })
+        Ok(Self { client })
     }
 
-    /// Get the paths for a given table and partition date.
+    /// Get the paths for a given table and partition date, with the schema version of each
+    /// partition.
     ///
     /// Other method to get paths would be supported in the future.
-    pub fn get_paths_by_date(&mut self, table: &str, partition_date: u32) -> Result<Vec<String>> {
-        let mut conn = self.db.get_conn()?;
+    pub async fn get_paths_by_date(
+        &mut self,
+        table: &str,
+        partition_date: u32,
+    ) -> Result<GetPartitionPathsResponse> {
+        let req = GetPartitionPathsRequest {
+            table_name: table.to_string(),
+            partition_date,
+            time_start: 0,
+            time_end: 0,
+        };
+
+        Ok(self.client.get_partition_paths(req).await?.into_inner())
+    }
+
+    /// Get the sorted partition paths which overlap with `[start_ts, end_ts)`, timestamp in the
+    /// unit of the table.
+    ///
+    /// The paths are ordered by time, and may span multiple days. The schema version of each
+    /// partition is returned with the paths.
+    pub async fn get_paths_by_time(
+        &mut self,
+        table: &str,
+        start_ts: u64,
+        end_ts: u64,
+    ) -> Result<GetPartitionPathsResponse> {
+        let req = GetPartitionPathsRequest {
+            table_name: table.to_string(),
+            partition_date: 0,
+            time_start: start_ts,
+            time_end: end_ts,
+        };
+
+        Ok(self.client.get_partition_paths(req).await?.into_inner())
+    }
 
-        get_table_paths_by_date(&mut conn, table, partition_date)
+    pub async fn get_or_insert_key_id(&mut self, key: &str) -> Result<u32> {
+        let key_ids = self.get_key_ids(&vec![key.to_string()]).await?;
+
+        match key_ids.first() {
+            Some(key_id) => Ok(*key_id),
+            None => Err(anyhow::anyhow!("Failed to get key id, key: {}", key)),
+        }
+    }
+
+    pub async fn get_key_ids(&mut self, keys: &Vec<String>) -> Result<Vec<u32>> {
+        let req = ResolveKeyIdsRequest { keys: keys.clone() };
+
+        Ok(self.client.resolve_key_ids(req).await?.into_inner().key_ids)
     }
 
-    pub fn get_or_insert_key_id(&mut self, key: &str) -> Result<u32> {
-        let mut conn = self.db.get_conn()?;
-        Ok(get_or_insert_key_id(&mut conn, key))
+    pub async fn get_partition_count_per_day(&mut self, table: &str) -> Result<u32> {
+        let req = GetTableInfoRequest {
+            table_name: table.to_string(),
+        };
+
+        Ok(self
+            .client
+            .get_table_info(req)
+            .await?
+            .into_inner()
+            .partition_count_per_day)
     }
 
-    pub fn get_key_ids(&mut self, keys: &Vec<String>) -> Result<Vec<u32>> {
-        let mut conn = self.db.get_conn()?;
+    /// Get the partition count, timestamp unit and timezone of the table.
+    pub async fn get_partition_scheme(&mut self, table: &str) -> Result<PartitionScheme> {
+        let req = GetTableInfoRequest {
+            table_name: table.to_string(),
+        };
+
+        let table_info = self.client.get_table_info(req).await?.into_inner();
+
+        let timestamp_unit = match TimestampUnit::try_from(table_info.timestamp_unit) {
+            Ok(timestamp_unit) => timestamp_unit,
+            Err(_) => {
+                error_bail!(
+                    "Unknown timestamp unit: {}, table: {}",
+                    table_info.timestamp_unit,
+                    table
+                );
+            }
+        };
+
+        Ok(PartitionScheme::new(table_info.partition_count_per_day)
+            .with_timestamp_unit(timestamp_unit)
+            .with_tz_offset_seconds(table_info.tz_offset_seconds))
+    }
+
+    /// Get the key columns of the table.
+    pub async fn get_key_schema(&mut self, table: &str) -> Result<KeySchema> {
+        let req = GetTableInfoRequest {
+            table_name: table.to_string(),
+        };
+
+        let key_column_ids = self
+            .client
+            .get_table_info(req)
+            .await?
+            .into_inner()
+            .key_column_ids;
 
-        get_key_ids(&mut conn, keys)
+        KeySchema::from_col_ids(&key_column_ids)
     }
 
-    pub fn get_partition_count_per_day(&mut self, table: &str) -> Result<u32> {
-        let mut conn = self.db.get_conn()?;
+    /// Get the columns of the table, including deprecated columns.
+    pub async fn get_table_columns(&mut self, table: &str) -> Result<Vec<ColumnInfo>> {
+        let req = GetTableInfoRequest {
+            table_name: table.to_string(),
+        };
 
-        get_partition_count_per_day(&mut conn, table)
+        Ok(self.client.get_table_info(req).await?.into_inner().columns)
     }
 
-    pub fn get_server_endpoint_by_partition_index(
+    pub async fn get_server_endpoint_by_partition_index(
         &mut self,
         table: &str,
         partition_index: u32,
     ) -> Result<String> {
-        let mut conn = self.db.get_conn()?;
+        let req = GetServerEndpointRequest {
+            table_name: table.to_string(),
+            partition_index,
+        };
+
+        Ok(self
+            .client
+            .get_server_endpoint(req)
+            .await?
+            .into_inner()
+            .endpoint)
+    }
 
-        get_server_endpoint_by_partition_index(&mut conn, table, partition_index)
+    /// Get the partition of the timestamp in seconds.
+    ///
+    /// The partition is created and placed on a node by meta server if not exists.
+    pub async fn get_partition_info(
+        &mut self,
+        table: &str,
+        timestamp: u64,
+    ) -> Result<PartitionInfo> {
+        let req = GetPartitionInfoRequest {
+            table_name: table.to_string(),
+            timestamp,
+        };
+
+        let partition_infos = self
+            .client
+            .get_partition_info(req)
+            .await?
+            .into_inner()
+            .partition_infos;
+
+        match partition_infos.into_iter().next() {
+            Some(partition_info) => Ok(partition_info),
+            None => {
+                error_bail!(
+                    "Partition info not found, table: {}, timestamp: {}",
+                    table,
+                    timestamp
+                );
+            }
+        }
     }
 
     /// Use local as the default server endpoint.
@@ -77,23 +208,39 @@ impl MetaClientWrapper {
     }
 
     pub fn get_path_by_table(&mut self, table: &str) -> String {
-        format!("/tmp/droplet/tables/{}", table)
+        get_table_path(table)
     }
 
-    pub fn is_table_exist(&mut self, table: &str) -> Result<bool> {
-        let mut conn = self.db.get_conn()?;
+    pub async fn is_table_exist(&mut self, table: &str) -> Result<bool> {
+        let req = IsTableExistRequest {
+            table_name: table.to_string(),
+        };
 
-        is_table_exist(&mut conn, table)
+        Ok(self.client.is_table_exist(req).await?.into_inner().exist)
     }
 
-    pub fn insert_table_info(
+    /// Insert table info, `key_columns` empty means the default key columns.
+    ///
+    /// The timestamp unit and timezone of `partition_scheme` cannot be changed once the table is
+    /// created.
+    pub async fn insert_table_info(
         &mut self,
         table: &str,
-        partition_count_per_day: u32,
+        partition_scheme: &PartitionScheme,
+        key_columns: &Vec<String>,
         columns: &Vec<ColumnInfo>,
     ) -> Result<()> {
-        let mut conn = self.db.get_conn()?;
-
-        insert_table_info(&mut conn, table, partition_count_per_day, columns)
+        let req = InsertTableInfoRequest {
+            table_name: table.to_string(),
+            partition_count_per_day: partition_scheme.partition_count_per_day,
+            columns: columns.clone(),
+            key_columns: key_columns.clone(),
+            timestamp_unit: partition_scheme.timestamp_unit.into(),
+            tz_offset_seconds: partition_scheme.tz_offset_seconds,
+        };
+
+        self.client.insert_table_info(req).await?;
+
+        Ok(())
     }
 }
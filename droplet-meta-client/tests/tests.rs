@@ -2,6 +2,7 @@ use anyhow::Result;
 use log::info;
 
 use droplet_core::droplet::ColumnInfo;
+use droplet_core::partition::{PartitionScheme, TimestampUnit};
 use droplet_core::{droplet::DataType, tool::setup_log};
 use droplet_meta_client::client::MetaClientWrapper;
 
@@ -12,7 +13,9 @@ async fn test_insert_table_info() -> Result<()> {
     let mut meta_client = MetaClientWrapper::get_default_client().await?;
 
     let table = "test_grid_sinker";
-    let partition_count_per_day = 24 * 12;
+    // Timestamps of the test samples are in milliseconds.
+    let partition_scheme =
+        PartitionScheme::new(24 * 12).with_timestamp_unit(TimestampUnit::Millisecond);
 
     let sparse_count = 76;
     let dense_count = 5;
@@ -21,7 +24,7 @@ async fn test_insert_table_info() -> Result<()> {
         .map(|i| format!("ExtractSparse{}", i))
         .collect::<Vec<String>>();
 
-    let sparse_feature_ids = meta_client.get_key_ids(&sparse_feature_names)?;
+    let sparse_feature_ids = meta_client.get_key_ids(&sparse_feature_names).await?;
 
     let sparse_features = sparse_feature_names
         .iter()
@@ -31,13 +34,15 @@ async fn test_insert_table_info() -> Result<()> {
             column_type: DataType::I32Array.into(),
             column_id: sparse_feature_ids[i],
             column_index: i as u32,
+            deprecated: false,
+            schema_version: 0,
         });
 
     let dense_feature_names = (0..dense_count)
         .map(|i| format!("ExtractDense{}", i + sparse_count))
         .collect::<Vec<String>>();
 
-    let dense_feature_ids = meta_client.get_key_ids(&dense_feature_names)?;
+    let dense_feature_ids = meta_client.get_key_ids(&dense_feature_names).await?;
 
     let dense_features = dense_feature_names
         .iter()
@@ -47,11 +52,15 @@ async fn test_insert_table_info() -> Result<()> {
             column_type: DataType::F32Array.into(),
             column_id: dense_feature_ids[i],
             column_index: (i + sparse_count) as u32,
+            deprecated: false,
+            schema_version: 0,
         });
 
     let mut columns = sparse_features.chain(dense_features).collect();
 
-    meta_client.insert_table_info(table, partition_count_per_day, &columns)?;
+    meta_client
+        .insert_table_info(table, &partition_scheme, &vec![], &columns)
+        .await?;
 
     Ok(())
 }
@@ -5,8 +5,7 @@ use log::info;
 use local_ip_address::local_ip;
 use tonic::transport::Server;
 
-use droplet_core::db::db::DB;
-use std::sync::Arc;
+use droplet_core::db::meta_store::new_default_meta_store;
 
 use droplet_core::droplet::meta_server::MetaServer;
 use droplet_core::tool::wait_for_signal;
@@ -14,6 +13,7 @@ use droplet_core::tool::wait_for_signal;
 use droplet_core::tool::MESSAGE_LIMIT;
 use droplet_meta_server::tool::META_SERVER_PORT;
 
+use droplet_meta_server::node_status::{run_node_status_checker, NodeStatusConfig};
 use droplet_meta_server::request_handler::MetaServerImpl;
 
 async fn serve() -> Result<()> {
@@ -23,9 +23,14 @@ async fn serve() -> Result<()> {
         .parse()
         .unwrap();
 
-    let db = Arc::new(DB::new()?);
+    let store = new_default_meta_store()?;
 
-    let meta_server = MetaServerImpl::new(db);
+    tokio::spawn(run_node_status_checker(
+        store.clone(),
+        NodeStatusConfig::from_env()?,
+    ));
+
+    let meta_server = MetaServerImpl::new(store);
 
     let signal = wait_for_signal();
 
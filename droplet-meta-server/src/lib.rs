@@ -1,4 +1,5 @@
 #![allow(dead_code)]
 
+pub mod node_status;
 pub mod request_handler;
 pub mod tool;
@@ -0,0 +1,81 @@
+//! Track the status of worker nodes by heartbeat.
+use anyhow::{bail, Result};
+use log::{error, info};
+use std::sync::Arc;
+use std::time::Duration;
+
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::error_bail;
+use droplet_core::tool::{get_env_u64, get_timestamp_secs};
+
+/// Environment variable of seconds without heartbeat before a node is `Unhealthy`.
+pub const NODE_UNHEALTHY_TIMEOUT_ENV: &str = "DROPLET_NODE_UNHEALTHY_TIMEOUT";
+
+/// Environment variable of seconds without heartbeat before a node is `Offline`.
+pub const NODE_OFFLINE_TIMEOUT_ENV: &str = "DROPLET_NODE_OFFLINE_TIMEOUT";
+
+/// Timeouts of node status, all in seconds.
+#[derive(Clone, Debug)]
+pub struct NodeStatusConfig {
+    pub unhealthy_timeout: u64,
+    pub offline_timeout: u64,
+
+    /// Interval to check the status of all nodes.
+    pub check_interval: u64,
+}
+
+impl Default for NodeStatusConfig {
+    fn default() -> Self {
+        Self {
+            unhealthy_timeout: 30,
+            offline_timeout: 300,
+            check_interval: 10,
+        }
+    }
+}
+
+impl NodeStatusConfig {
+    /// Read timeouts from environment variables, default values are used if not set.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(timeout) = get_env_u64(NODE_UNHEALTHY_TIMEOUT_ENV)? {
+            config.unhealthy_timeout = timeout;
+        }
+
+        if let Some(timeout) = get_env_u64(NODE_OFFLINE_TIMEOUT_ENV)? {
+            config.offline_timeout = timeout;
+        }
+
+        if config.unhealthy_timeout >= config.offline_timeout {
+            error_bail!(
+                "unhealthy_timeout must be less than offline_timeout, unhealthy_timeout: {}, offline_timeout: {}",
+                config.unhealthy_timeout,
+                config.offline_timeout
+            );
+        }
+
+        Ok(config)
+    }
+}
+
+/// Check the status of all nodes every `check_interval` seconds.
+///
+/// Runs until the process exits. Errors are logged, and the next check goes on.
+pub async fn run_node_status_checker(store: Arc<dyn MetaStore>, config: NodeStatusConfig) {
+    info!("Start node status checker, config: {:?}", config);
+
+    let mut interval = tokio::time::interval(Duration::from_secs(config.check_interval));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = store.update_node_status(
+            get_timestamp_secs(),
+            config.unhealthy_timeout,
+            config.offline_timeout,
+        ) {
+            error!("Failed to update node status, error: {}", e);
+        }
+    }
+}
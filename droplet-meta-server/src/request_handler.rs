@@ -1,61 +1,92 @@
 use anyhow::Result;
-use log::{error, info};
+use log::error;
 
 use std::sync::Arc;
 
-use mysql::*;
-
 use tonic::{Request, Response, Status};
 
 use droplet_core::droplet::meta_server::Meta;
 use droplet_core::droplet::{
-    GetPartitionInfoRequest, GetPartitionInfoResponse, GetTableInfoRequest, GetTableInfoResponse,
-    GetWorkerNodeIdRequest, GetWorkerNodeIdResponse, HeartbeatRequest, HeartbeatResponse,
-    InsertTableInfoRequest, InsertTableInfoResponse, RegisterNodeRequest, RegisterNodeResponse,
-    ReportStorageInfoRequest, ReportStorageInfoResponse,
+    AlterTableRequest, AlterTableResponse, DescribeTableRequest, DescribeTableResponse,
+    DropTableRequest, DropTableResponse, GetPartitionInfoRequest, GetPartitionInfoResponse,
+    GetPartitionPathsRequest, GetPartitionPathsResponse, GetServerEndpointRequest,
+    GetServerEndpointResponse, GetTableInfoRequest, GetTableInfoResponse, GetWorkerNodeIdRequest,
+    GetWorkerNodeIdResponse, GetWorkerNodeInfoRequest, GetWorkerNodeInfoResponse, HeartbeatRequest,
+    HeartbeatResponse, InsertTableInfoRequest, InsertTableInfoResponse, IsTableExistRequest,
+    IsTableExistResponse, ListNodesRequest, ListNodesResponse, ListTablesRequest,
+    ListTablesResponse, NodeStatus, RegisterNodeRequest, RegisterNodeResponse,
+    ReportStorageInfoRequest, ReportStorageInfoResponse, ResolveKeyIdsRequest,
+    ResolveKeyIdsResponse, TimestampUnit,
 };
 
-use droplet_core::db::db::DB;
-use droplet_core::db::meta_info::get_partition_infos;
-use droplet_core::db::meta_info::insert_table_info;
-use droplet_core::db::meta_info::{
-    get_partition_count_per_day, get_table_column_infos, get_worker_node_id, register_node,
-    update_storage_info,
-};
-use droplet_core::grpc_util::get_error_status;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::grpc_util::{get_error_status, send_bad_request_error};
+use droplet_core::partition::{check_table_name, parse_sorted_partition_path, PartitionScheme};
 use droplet_core::print_and_send_error_status;
+use droplet_core::tool::get_timestamp_secs;
 
 pub struct MetaServerImpl {
-    /// Db for meta server.
-    db: Arc<DB>,
+    /// Store of meta info.
+    store: Arc<dyn MetaStore>,
 }
 
 impl MetaServerImpl {
-    pub fn new(db: Arc<DB>) -> Self {
-        Self { db }
+    pub fn new(store: Arc<dyn MetaStore>) -> Self {
+        Self { store }
     }
 
-    /// Get db connection.
-    ///
-    /// For more clear log, define as method of MetaServerImpl.
-    fn get_db_conn(&self) -> Result<PooledConn, Status> {
-        match self.db.get_conn() {
-            Ok(conn) => Ok(conn),
-            Err(e) => Err(get_error_status(format!(
-                "Failed to get db connection for meta server: {}",
-                e
+    /// Return `NotFound` if the table does not exist, other errors are `Internal`.
+    fn check_table_exist(&self, table_name: &str) -> Result<(), Status> {
+        match self.store.is_table_exist(table_name) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(Status::not_found(format!(
+                "Table not found, table_name: {}",
+                table_name
             ))),
+            Err(e) => {
+                error!("Failed to check table exist: {}", e);
+                Err(get_error_status(format!(
+                    "Failed to check table exist: {}",
+                    e
+                )))
+            }
         }
     }
+
+    /// Schema version of the partition of each sorted path, `0` if the partition is not created.
+    fn get_schema_versions(&self, paths: &[String]) -> Result<Vec<u32>> {
+        paths
+            .iter()
+            .map(|path| -> Result<u32> {
+                let (table, partition_date, partition_index) = parse_sorted_partition_path(path)?;
+
+                let schema_version = self.store.get_partition_schema_version(
+                    table.as_str(),
+                    partition_date,
+                    partition_index,
+                )?;
+
+                Ok(schema_version.unwrap_or(0))
+            })
+            .collect()
+    }
 }
 
 #[tonic::async_trait]
 impl Meta for MetaServerImpl {
     async fn heartbeat(
         &self,
-        _request: Request<HeartbeatRequest>,
+        request: Request<HeartbeatRequest>,
     ) -> Result<Response<HeartbeatResponse>, Status> {
-        info!("heartbeat");
+        let req = request.into_inner();
+
+        let status = NodeStatus::try_from(req.status).unwrap_or(NodeStatus::Unknown);
+
+        self.store
+            .update_node_heartbeat(req.node_id, status, get_timestamp_secs())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to update node heartbeat: {}", e);
+            })?;
 
         let response = HeartbeatResponse { acknowledged: true };
 
@@ -71,17 +102,12 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<RegisterNodeResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
-
-        let node_id = register_node(
-            &mut conn,
-            req.node_name.as_str(),
-            req.node_ip.as_str(),
-            req.node_port,
-        )
-        .map_err(|e| {
-            print_and_send_error_status!("Failed to register node: {}", e);
-        })?;
+        let node_id = self
+            .store
+            .register_node(req.node_name.as_str(), req.node_ip.as_str(), req.node_port)
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to register node: {}", e);
+            })?;
 
         let response = RegisterNodeResponse {
             node_id,
@@ -98,11 +124,12 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<GetWorkerNodeIdResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
-
-        let node_id = get_worker_node_id(&mut conn, req.node_name.as_str()).map_err(|e| {
-            print_and_send_error_status!("Failed to get worker node id: {}", e);
-        })?;
+        let node_id = self
+            .store
+            .get_worker_node_id(req.node_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get worker node id: {}", e);
+            })?;
 
         let response = GetWorkerNodeIdResponse {
             node_id,
@@ -118,17 +145,34 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<InsertTableInfoResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
+        if let Err(e) = check_table_name(req.table_name.as_str()) {
+            return send_bad_request_error("table_name", e.to_string());
+        }
 
-        insert_table_info(
-            &mut conn,
-            req.table_name.as_str(),
-            req.partition_count_per_day,
-            &req.columns,
-        )
-        .map_err(|e| {
-            print_and_send_error_status!("Failed to insert table info: {}", e);
-        })?;
+        let timestamp_unit = match TimestampUnit::try_from(req.timestamp_unit) {
+            Ok(timestamp_unit) => timestamp_unit,
+            Err(_) => {
+                return send_bad_request_error(
+                    "timestamp_unit",
+                    format!("Invalid timestamp unit: {}", req.timestamp_unit),
+                );
+            }
+        };
+
+        let partition_scheme = PartitionScheme::new(req.partition_count_per_day)
+            .with_timestamp_unit(timestamp_unit)
+            .with_tz_offset_seconds(req.tz_offset_seconds);
+
+        self.store
+            .insert_table_info(
+                req.table_name.as_str(),
+                &partition_scheme,
+                &req.key_columns,
+                &req.columns,
+            )
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to insert table info: {}", e);
+            })?;
 
         let response = InsertTableInfoResponse {
             success: true,
@@ -144,20 +188,49 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<GetTableInfoResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
+        let columns = self
+            .store
+            .get_table_column_infos(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get table columns: {}", e);
+            })?;
 
-        let columns = get_table_column_infos(&mut conn, req.table_name.as_str()).map_err(|e| {
-            print_and_send_error_status!("Failed to get table columns: {}", e);
-        })?;
+        let partition_scheme = self
+            .store
+            .get_partition_scheme(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get partition scheme: {}", e);
+            })?;
 
-        let partition_count_per_day =
-            get_partition_count_per_day(&mut conn, req.table_name.as_str()).map_err(|e| {
-                print_and_send_error_status!("Failed to get partition count per day: {}", e);
+        let schema_version = self
+            .store
+            .get_schema_version(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get schema version: {}", e);
+            })?;
+
+        let key_columns = self
+            .store
+            .get_key_columns(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get key columns: {}", e);
+            })?;
+
+        let key_schema = self
+            .store
+            .get_key_schema(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get key schema: {}", e);
             })?;
 
         let response = GetTableInfoResponse {
             columns,
-            partition_count_per_day,
+            partition_count_per_day: partition_scheme.partition_count_per_day,
+            schema_version,
+            key_columns,
+            key_column_ids: key_schema.col_ids().to_vec(),
+            timestamp_unit: partition_scheme.timestamp_unit.into(),
+            tz_offset_seconds: partition_scheme.tz_offset_seconds,
         };
 
         Ok(Response::new(response))
@@ -169,11 +242,11 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<ReportStorageInfoResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
-
-        update_storage_info(&mut conn, req.node_id, req.used_disk_size).map_err(|e| {
-            print_and_send_error_status!("Failed to update storage info: {}", e);
-        })?;
+        self.store
+            .update_storage_info(req.node_id, req.used_disk_size, req.total_disk_size)
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to update storage info: {}", e);
+            })?;
 
         let response = ReportStorageInfoResponse { success: true };
 
@@ -186,17 +259,256 @@ impl Meta for MetaServerImpl {
     ) -> Result<Response<GetPartitionInfoResponse>, Status> {
         let req = request.into_inner();
 
-        let mut conn = self.get_db_conn()?;
-
-        let partition_infos =
-            get_partition_infos(&mut conn, req.table_name.as_str(), req.timestamp).map_err(
-                |e| {
-                    print_and_send_error_status!("Failed to get partition info: {}", e);
-                },
-            )?;
+        let partition_infos = self
+            .store
+            .get_partition_infos(req.table_name.as_str(), req.timestamp)
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get partition info: {}", e);
+            })?;
 
         let response = GetPartitionInfoResponse { partition_infos };
 
         Ok(Response::new(response))
     }
+
+    async fn resolve_key_ids(
+        &self,
+        request: Request<ResolveKeyIdsRequest>,
+    ) -> Result<Response<ResolveKeyIdsResponse>, Status> {
+        let req = request.into_inner();
+
+        let key_ids = self.store.get_key_ids(&req.keys).map_err(|e| {
+            print_and_send_error_status!("Failed to resolve key ids: {}", e);
+        })?;
+
+        let response = ResolveKeyIdsResponse { key_ids };
+
+        Ok(Response::new(response))
+    }
+
+    /// Get partition paths by time range if `time_end > time_start`, else by partition date.
+    async fn get_partition_paths(
+        &self,
+        request: Request<GetPartitionPathsRequest>,
+    ) -> Result<Response<GetPartitionPathsResponse>, Status> {
+        let req = request.into_inner();
+
+        let paths = if req.time_end > req.time_start {
+            self.store.get_table_paths_by_time(
+                req.table_name.as_str(),
+                req.time_start,
+                req.time_end,
+            )
+        } else {
+            self.store
+                .get_table_paths_by_date(req.table_name.as_str(), req.partition_date)
+        }
+        .map_err(|e| {
+            print_and_send_error_status!("Failed to get partition paths: {}", e);
+        })?;
+
+        let schema_versions = self.get_schema_versions(&paths).map_err(|e| {
+            print_and_send_error_status!("Failed to get schema versions of partitions: {}", e);
+        })?;
+
+        let response = GetPartitionPathsResponse {
+            paths,
+            schema_versions,
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_server_endpoint(
+        &self,
+        request: Request<GetServerEndpointRequest>,
+    ) -> Result<Response<GetServerEndpointResponse>, Status> {
+        let req = request.into_inner();
+
+        let endpoint = self
+            .store
+            .get_server_endpoint_by_partition_index(req.table_name.as_str(), req.partition_index)
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to get server endpoint: {}", e);
+            })?;
+
+        let response = GetServerEndpointResponse { endpoint };
+
+        Ok(Response::new(response))
+    }
+
+    async fn is_table_exist(
+        &self,
+        request: Request<IsTableExistRequest>,
+    ) -> Result<Response<IsTableExistResponse>, Status> {
+        let req = request.into_inner();
+
+        let exist = self
+            .store
+            .is_table_exist(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to check table exist: {}", e);
+            })?;
+
+        let response = IsTableExistResponse { exist };
+
+        Ok(Response::new(response))
+    }
+
+    async fn get_worker_node_info(
+        &self,
+        request: Request<GetWorkerNodeInfoRequest>,
+    ) -> Result<Response<GetWorkerNodeInfoResponse>, Status> {
+        let req = request.into_inner();
+
+        let node_info = self.store.get_worker_node_info(req.node_id).map_err(|e| {
+            print_and_send_error_status!("Failed to get worker node info: {}", e);
+        })?;
+
+        let response = GetWorkerNodeInfoResponse {
+            node_info: Some(node_info),
+        };
+
+        Ok(Response::new(response))
+    }
+
+    /// List nodes with the statuses in request, all nodes if no status is given.
+    async fn list_nodes(
+        &self,
+        request: Request<ListNodesRequest>,
+    ) -> Result<Response<ListNodesResponse>, Status> {
+        let req = request.into_inner();
+
+        let mut statuses = Vec::with_capacity(req.statuses.len());
+
+        for status in req.statuses {
+            match NodeStatus::try_from(status) {
+                Ok(status) => statuses.push(status),
+                Err(_) => {
+                    return send_bad_request_error(
+                        "statuses",
+                        format!("Invalid node status: {}", status),
+                    );
+                }
+            }
+        }
+
+        let node_infos = self.store.list_nodes(&statuses).map_err(|e| {
+            print_and_send_error_status!("Failed to list nodes: {}", e);
+        })?;
+
+        let response = ListNodesResponse { node_infos };
+
+        Ok(Response::new(response))
+    }
+
+    async fn list_tables(
+        &self,
+        _request: Request<ListTablesRequest>,
+    ) -> Result<Response<ListTablesResponse>, Status> {
+        let table_names = self.store.list_tables().map_err(|e| {
+            print_and_send_error_status!("Failed to list tables: {}", e);
+        })?;
+
+        let response = ListTablesResponse { table_names };
+
+        Ok(Response::new(response))
+    }
+
+    async fn describe_table(
+        &self,
+        request: Request<DescribeTableRequest>,
+    ) -> Result<Response<DescribeTableResponse>, Status> {
+        let req = request.into_inner();
+        let table_name = req.table_name.as_str();
+
+        self.check_table_exist(table_name)?;
+
+        let columns = self.store.get_table_column_infos(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get table columns: {}", e);
+        })?;
+
+        let partition_scheme = self.store.get_partition_scheme(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get partition scheme: {}", e);
+        })?;
+
+        let stats = self.store.get_table_stats(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get table stats: {}", e);
+        })?;
+
+        let schema_version = self.store.get_schema_version(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get schema version: {}", e);
+        })?;
+
+        let key_columns = self.store.get_key_columns(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get key columns: {}", e);
+        })?;
+
+        let key_schema = self.store.get_key_schema(table_name).map_err(|e| {
+            print_and_send_error_status!("Failed to get key schema: {}", e);
+        })?;
+
+        let response = DescribeTableResponse {
+            table_name: req.table_name.clone(),
+            columns,
+            partition_count_per_day: partition_scheme.partition_count_per_day,
+            stats: Some(stats),
+            schema_version,
+            key_columns,
+            key_column_ids: key_schema.col_ids().to_vec(),
+            timestamp_unit: partition_scheme.timestamp_unit.into(),
+            tz_offset_seconds: partition_scheme.tz_offset_seconds,
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn alter_table(
+        &self,
+        request: Request<AlterTableRequest>,
+    ) -> Result<Response<AlterTableResponse>, Status> {
+        let req = request.into_inner();
+
+        let schema_version = self
+            .store
+            .alter_table(
+                req.table_name.as_str(),
+                &req.add_columns,
+                &req.deprecate_columns,
+            )
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to alter table: {}", e);
+            })?;
+
+        let response = AlterTableResponse {
+            success: true,
+            error_message: String::new(),
+            schema_version,
+        };
+
+        Ok(Response::new(response))
+    }
+
+    async fn drop_table(
+        &self,
+        request: Request<DropTableRequest>,
+    ) -> Result<Response<DropTableResponse>, Status> {
+        let req = request.into_inner();
+
+        self.check_table_exist(req.table_name.as_str())?;
+
+        let partition_count = self
+            .store
+            .drop_table(req.table_name.as_str())
+            .map_err(|e| {
+                print_and_send_error_status!("Failed to drop table: {}", e);
+            })?;
+
+        let response = DropTableResponse {
+            success: true,
+            partition_count,
+        };
+
+        Ok(Response::new(response))
+    }
 }
@@ -1,9 +1,22 @@
 use anyhow::Result;
 use log::info;
+use std::sync::Arc;
+use tonic::{Code, Request};
 
+use droplet_meta_server::request_handler::MetaServerImpl;
 use droplet_meta_server::tool::get_meta_server_default_client;
 
+use droplet_core::db::memory_store::MemoryMetaStore;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::droplet::meta_server::Meta;
 use droplet_core::droplet::ColumnInfo;
+use droplet_core::droplet::{
+    AlterTableRequest, DescribeTableRequest, DropTableRequest, GetPartitionInfoRequest,
+    GetPartitionPathsRequest, GetServerEndpointRequest, GetTableInfoRequest,
+    GetWorkerNodeInfoRequest, InsertTableInfoRequest, IsTableExistRequest, ListNodesRequest,
+    ListTablesRequest, RegisterNodeRequest, ReportStorageInfoRequest, ResolveKeyIdsRequest,
+    TimestampUnit,
+};
 use droplet_core::{
     droplet::{DataType, HeartbeatRequest, HeartbeatResponse, NodeStatus},
     tool::setup_log,
@@ -15,8 +28,18 @@ async fn test_meta_server_heartbeat() -> Result<()> {
 
     let mut meta_client = get_meta_server_default_client().await?;
 
+    let node_id = meta_client
+        .register_node(RegisterNodeRequest {
+            node_name: "droplet_test_node".to_string(),
+            node_ip: "127.0.0.1".to_string(),
+            node_port: 50052,
+        })
+        .await?
+        .into_inner()
+        .node_id;
+
     let request = HeartbeatRequest {
-        node_id: 0,
+        node_id,
         status: NodeStatus::Healthy.into(),
     };
 
@@ -26,3 +49,445 @@ async fn test_meta_server_heartbeat() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_meta_server_memory_store() -> Result<()> {
+    setup_log();
+
+    let meta_server = MetaServerImpl::new(Arc::new(MemoryMetaStore::new()));
+
+    let node_id = meta_server
+        .register_node(Request::new(RegisterNodeRequest {
+            node_name: "node_a".to_string(),
+            node_ip: "127.0.0.1".to_string(),
+            node_port: 50052,
+        }))
+        .await?
+        .into_inner()
+        .node_id;
+
+    meta_server
+        .report_storage_info(Request::new(ReportStorageInfoRequest {
+            node_id,
+            used_disk_size: 0,
+            total_disk_size: 1 << 40,
+        }))
+        .await?;
+
+    let columns = vec![ColumnInfo {
+        column_name: "label".to_string(),
+        column_type: DataType::F32.into(),
+        column_id: 0,
+        column_index: 0,
+        deprecated: false,
+        schema_version: 0,
+    }];
+
+    meta_server
+        .insert_table_info(Request::new(InsertTableInfoRequest {
+            table_name: "droplet_test".to_string(),
+            partition_count_per_day: 24,
+            columns,
+            key_columns: vec![],
+            ..Default::default()
+        }))
+        .await?;
+
+    let table_info = meta_server
+        .get_table_info(Request::new(GetTableInfoRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await?
+        .into_inner();
+
+    assert_eq!(table_info.partition_count_per_day, 24);
+    assert_eq!(table_info.columns.len(), 1);
+    assert_eq!(table_info.key_column_ids, vec![2, 4, 5, 6]);
+
+    let partition_infos = meta_server
+        .get_partition_info(Request::new(GetPartitionInfoRequest {
+            table_name: "droplet_test".to_string(),
+            timestamp: 1728604800 + 3600,
+        }))
+        .await?
+        .into_inner()
+        .partition_infos;
+
+    assert_eq!(partition_infos.len(), 1);
+    assert_eq!(partition_infos[0].partition_index, 1);
+    assert_eq!(partition_infos[0].node_id, node_id);
+
+    let heartbeat = |node_id: u32| HeartbeatRequest {
+        node_id,
+        status: NodeStatus::Healthy.into(),
+    };
+
+    assert!(
+        meta_server
+            .heartbeat(Request::new(heartbeat(node_id)))
+            .await?
+            .into_inner()
+            .acknowledged
+    );
+    assert!(meta_server
+        .heartbeat(Request::new(heartbeat(node_id + 1)))
+        .await
+        .is_err());
+
+    let node_info = meta_server
+        .get_worker_node_info(Request::new(GetWorkerNodeInfoRequest { node_id }))
+        .await?
+        .into_inner()
+        .node_info
+        .unwrap_or_default();
+
+    assert_eq!(node_info.node_name, "node_a");
+    assert_eq!(node_info.status, NodeStatus::Alive as i32);
+    assert_eq!(node_info.total_disk_size, 1 << 40);
+    assert_eq!(node_info.partition_count, 1);
+
+    let list_nodes = |statuses: Vec<NodeStatus>| ListNodesRequest {
+        statuses: statuses.into_iter().map(|status| status.into()).collect(),
+    };
+
+    let node_infos = meta_server
+        .list_nodes(Request::new(list_nodes(vec![NodeStatus::Alive])))
+        .await?
+        .into_inner()
+        .node_infos;
+
+    assert_eq!(node_infos.len(), 1);
+    assert_eq!(node_infos[0].node_id, node_id);
+
+    assert!(meta_server
+        .list_nodes(Request::new(list_nodes(vec![NodeStatus::Offline])))
+        .await?
+        .into_inner()
+        .node_infos
+        .is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_meta_server_resolve_meta_info() -> Result<()> {
+    setup_log();
+
+    let meta_server = MetaServerImpl::new(Arc::new(MemoryMetaStore::new()));
+
+    let keys = vec!["uid".to_string(), "item_id".to_string(), "uid".to_string()];
+
+    let key_ids = meta_server
+        .resolve_key_ids(Request::new(ResolveKeyIdsRequest { keys }))
+        .await?
+        .into_inner()
+        .key_ids;
+
+    assert_eq!(key_ids.len(), 3);
+    assert_ne!(key_ids[0], key_ids[1]);
+    assert_eq!(key_ids[0], key_ids[2]);
+
+    let node_id = meta_server
+        .register_node(Request::new(RegisterNodeRequest {
+            node_name: "node_a".to_string(),
+            node_ip: "127.0.0.1".to_string(),
+            node_port: 50052,
+        }))
+        .await?
+        .into_inner()
+        .node_id;
+
+    meta_server
+        .report_storage_info(Request::new(ReportStorageInfoRequest {
+            node_id,
+            used_disk_size: 0,
+            total_disk_size: 1 << 40,
+        }))
+        .await?;
+
+    let exist = meta_server
+        .is_table_exist(Request::new(IsTableExistRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await?
+        .into_inner()
+        .exist;
+
+    assert!(!exist);
+
+    meta_server
+        .insert_table_info(Request::new(InsertTableInfoRequest {
+            table_name: "droplet_test".to_string(),
+            partition_count_per_day: 24,
+            columns: vec![],
+            key_columns: vec![],
+            ..Default::default()
+        }))
+        .await?;
+
+    let exist = meta_server
+        .is_table_exist(Request::new(IsTableExistRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await?
+        .into_inner()
+        .exist;
+
+    assert!(exist);
+
+    // 2024-10-11 01:00:00 UTC.
+    let timestamp = 1728604800 + 3600;
+
+    meta_server
+        .get_partition_info(Request::new(GetPartitionInfoRequest {
+            table_name: "droplet_test".to_string(),
+            timestamp,
+        }))
+        .await?;
+
+    let res = meta_server
+        .get_partition_paths(Request::new(GetPartitionPathsRequest {
+            table_name: "droplet_test".to_string(),
+            partition_date: 20241011,
+            time_start: 0,
+            time_end: 0,
+        }))
+        .await?
+        .into_inner();
+
+    let paths_by_date = res.paths;
+
+    assert_eq!(paths_by_date.len(), 24);
+
+    // Only partition 1 is created.
+    assert_eq!(res.schema_versions.len(), 24);
+    assert_eq!(res.schema_versions[0], 0);
+    assert_eq!(res.schema_versions[1], 1);
+
+    let paths_by_time = meta_server
+        .get_partition_paths(Request::new(GetPartitionPathsRequest {
+            table_name: "droplet_test".to_string(),
+            partition_date: 0,
+            time_start: timestamp,
+            time_end: timestamp + 60,
+        }))
+        .await?
+        .into_inner()
+        .paths;
+
+    assert_eq!(paths_by_time.len(), 1);
+    assert_eq!(paths_by_time[0], paths_by_date[1]);
+
+    let endpoint = meta_server
+        .get_server_endpoint(Request::new(GetServerEndpointRequest {
+            table_name: "droplet_test".to_string(),
+            partition_index: 1,
+        }))
+        .await?
+        .into_inner()
+        .endpoint;
+
+    assert_eq!(endpoint, "node_a:50052");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_meta_server_table_lifecycle() -> Result<()> {
+    setup_log();
+
+    let store = Arc::new(MemoryMetaStore::new());
+    let meta_server = MetaServerImpl::new(store.clone());
+
+    let node_id = meta_server
+        .register_node(Request::new(RegisterNodeRequest {
+            node_name: "node_a".to_string(),
+            node_ip: "127.0.0.1".to_string(),
+            node_port: 50052,
+        }))
+        .await?
+        .into_inner()
+        .node_id;
+
+    meta_server
+        .report_storage_info(Request::new(ReportStorageInfoRequest {
+            node_id,
+            used_disk_size: 0,
+            total_disk_size: 1 << 40,
+        }))
+        .await?;
+
+    let column = |name: &str, column_type: DataType, column_index: u32| ColumnInfo {
+        column_name: name.to_string(),
+        column_type: column_type.into(),
+        column_id: 0,
+        column_index,
+        deprecated: false,
+        schema_version: 0,
+    };
+
+    meta_server
+        .insert_table_info(Request::new(InsertTableInfoRequest {
+            table_name: "droplet_test".to_string(),
+            partition_count_per_day: 24,
+            columns: vec![
+                column("label", DataType::F32, 0),
+                column("uid", DataType::I64, 1),
+            ],
+            key_columns: vec!["timestamp".to_string(), "device_id".to_string()],
+            timestamp_unit: TimestampUnit::Millisecond.into(),
+            tz_offset_seconds: 8 * 3600,
+        }))
+        .await?;
+
+    let table_names = meta_server
+        .list_tables(Request::new(ListTablesRequest {}))
+        .await?
+        .into_inner()
+        .table_names;
+
+    assert_eq!(table_names, vec!["droplet_test".to_string()]);
+
+    // 2024-10-11 01:00:00 in UTC+8, in milliseconds.
+    let timestamp = (1728604800 + 3600 - 8 * 3600) * 1000;
+
+    let partition_info = meta_server
+        .get_partition_info(Request::new(GetPartitionInfoRequest {
+            table_name: "droplet_test".to_string(),
+            timestamp,
+        }))
+        .await?
+        .into_inner()
+        .partition_infos[0]
+        .clone();
+
+    assert_eq!(partition_info.partition_date, 20241011);
+    assert_eq!(partition_info.partition_index, 1);
+    assert_eq!(partition_info.time_start, timestamp);
+
+    store.update_partition_stats("droplet_test", 20241011, 1, 100, 4096)?;
+
+    meta_server
+        .alter_table(Request::new(AlterTableRequest {
+            table_name: "droplet_test".to_string(),
+            add_columns: vec![column("item_id", DataType::I64, 0)],
+            deprecate_columns: vec!["uid".to_string()],
+        }))
+        .await?;
+
+    assert!(meta_server
+        .alter_table(Request::new(AlterTableRequest {
+            table_name: "droplet_test".to_string(),
+            add_columns: vec![column("label", DataType::I64, 0)],
+            deprecate_columns: vec![],
+        }))
+        .await
+        .is_err());
+
+    let describe = meta_server
+        .describe_table(Request::new(DescribeTableRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await?
+        .into_inner();
+
+    assert_eq!(describe.partition_count_per_day, 24);
+    assert_eq!(describe.timestamp_unit(), TimestampUnit::Millisecond);
+    assert_eq!(describe.tz_offset_seconds, 8 * 3600);
+    assert_eq!(describe.key_columns, vec!["timestamp", "device_id"]);
+    assert_eq!(
+        describe.key_column_ids,
+        vec![2, store.get_or_insert_key_id("device_id")?]
+    );
+
+    let columns = describe
+        .columns
+        .iter()
+        .map(|x| (x.column_name.as_str(), x.column_index, x.deprecated))
+        .collect::<Vec<_>>();
+
+    assert_eq!(
+        columns,
+        vec![("label", 0, false), ("uid", 1, true), ("item_id", 2, false)]
+    );
+
+    let stats = describe.stats.unwrap_or_default();
+    assert_eq!(stats.partition_count, 1);
+    assert_eq!(stats.row_count, 100);
+    assert_eq!(stats.byte_size, 4096);
+    assert_eq!(stats.earliest_partition.map(|x| x.partition_index), Some(1));
+    assert_eq!(stats.latest_partition.map(|x| x.partition_index), Some(1));
+
+    let partition_count = meta_server
+        .drop_table(Request::new(DropTableRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await?
+        .into_inner()
+        .partition_count;
+
+    assert_eq!(partition_count, 1);
+
+    assert!(meta_server
+        .list_tables(Request::new(ListTablesRequest {}))
+        .await?
+        .into_inner()
+        .table_names
+        .is_empty());
+
+    assert!(
+        !meta_server
+            .is_table_exist(Request::new(IsTableExistRequest {
+                table_name: "droplet_test".to_string(),
+            }))
+            .await?
+            .into_inner()
+            .exist
+    );
+
+    let describe_status = meta_server
+        .describe_table(Request::new(DescribeTableRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await
+        .err()
+        .map(|status| status.code());
+    assert_eq!(describe_status, Some(Code::NotFound));
+
+    let drop_status = meta_server
+        .drop_table(Request::new(DropTableRequest {
+            table_name: "droplet_test".to_string(),
+        }))
+        .await
+        .err()
+        .map(|status| status.code());
+    assert_eq!(drop_status, Some(Code::NotFound));
+
+    // Created again, the dropped partitions don't block the new ones.
+    meta_server
+        .insert_table_info(Request::new(InsertTableInfoRequest {
+            table_name: "droplet_test".to_string(),
+            partition_count_per_day: 24,
+            columns: vec![column("label", DataType::F32, 0)],
+            timestamp_unit: TimestampUnit::Millisecond.into(),
+            tz_offset_seconds: 8 * 3600,
+            ..Default::default()
+        }))
+        .await?;
+
+    let new_partition_info = meta_server
+        .get_partition_info(Request::new(GetPartitionInfoRequest {
+            table_name: "droplet_test".to_string(),
+            timestamp,
+        }))
+        .await?
+        .into_inner()
+        .partition_infos[0]
+        .clone();
+
+    assert_eq!(new_partition_info.partition_index, 1);
+    assert_ne!(new_partition_info.partition_id, partition_info.partition_id);
+    assert_eq!(store.get_table_stats("droplet_test")?.row_count, 0);
+
+    Ok(())
+}
@@ -2,17 +2,18 @@ use anyhow::Result;
 use log::info;
 
 use local_ip_address::local_ip;
-use std::sync::Arc;
 use tonic::transport::Server;
 
-use droplet_core::db::db::DB;
+use droplet_core::db::meta_store::new_default_meta_store;
 use droplet_core::droplet::droplet_server::DropletServer;
 use droplet_core::tool::init_log;
 use droplet_core::tool::wait_for_signal;
 use droplet_core::tool::MESSAGE_LIMIT;
 use droplet_server::request_handler::DropletServerImpl;
-use droplet_server::tool::register_node_to_meta_server;
+use droplet_server::storage::run_storage_report_to_meta_server;
 use droplet_server::tool::DROPPLET_SERVER_PORT;
+use droplet_server::tool::{register_node_to_meta_server, run_heartbeat_to_meta_server};
+use droplet_server::watermark::SealConfig;
 
 async fn serve() -> Result<()> {
     let my_local_ip = local_ip().unwrap();
@@ -21,9 +22,12 @@ async fn serve() -> Result<()> {
         .parse()
         .unwrap();
 
-    let db = Arc::new(DB::new()?);
+    let store = new_default_meta_store()?;
 
-    let droplet_server = DropletServerImpl::new(db);
+    let seal_config = SealConfig::from_env()?;
+    let check_interval = seal_config.check_interval;
+
+    let droplet_server = DropletServerImpl::new(store).with_seal_config(seal_config);
 
     let signal = wait_for_signal();
 
@@ -32,7 +36,10 @@ async fn serve() -> Result<()> {
         my_local_ip, DROPPLET_SERVER_PORT
     );
 
-    register_node_to_meta_server().await?;
+    let node_id = register_node_to_meta_server().await?;
+    tokio::spawn(run_heartbeat_to_meta_server(node_id));
+    tokio::spawn(run_storage_report_to_meta_server(node_id));
+    tokio::spawn(droplet_server.sealer().run_seal_checker(check_interval));
 
     Server::builder()
         .add_service(
@@ -1,5 +1,10 @@
 #![allow(dead_code)]
 
+pub mod partition_reader;
+pub mod partition_sealer;
 pub mod request_handler;
 pub mod sample_saver;
+pub mod sink_schema;
+pub mod storage;
 pub mod tool;
+pub mod watermark;
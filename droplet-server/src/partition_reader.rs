@@ -0,0 +1,113 @@
+use anyhow::Result;
+use log::info;
+use tokio::sync::mpsc::Sender;
+use tonic::Status;
+
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use droplet_core::droplet::{ReadPartitionRequest, ReadPartitionResponse};
+use droplet_core::grid_sample::{project_gridbuffer, KeySchema};
+use droplet_core::grid_segment::GridSegmentReader;
+use droplet_core::partition::{get_sorted_partition_path, list_grid_files};
+
+/// `PartitionReader` reads the sorted files of one partition for `ReadPartition` requests.
+///
+/// The blocks are read in order, so the order of rows is the same as the order of `SampleKey`.
+/// If `column_ids` is empty, the blocks are sent as they are in the files, without decoding.
+/// Otherwise the blocks are projected to the key columns and `column_ids` before sent,
+/// so the columns not needed never go over the wire.
+pub struct PartitionReader {
+    /// Path of the sorted partition.
+    path: String,
+
+    /// Sorted files of the partition.
+    filenames: Vec<String>,
+
+    /// Only read blocks in `[start_ts, end_ts)` if set.
+    time_range: Option<(u64, u64)>,
+
+    /// Column ids to project. All columns are sent if empty.
+    column_ids: Vec<u32>,
+
+    /// Key columns of the table, always kept by projection.
+    key_schema: KeySchema,
+}
+
+impl PartitionReader {
+    pub fn new(req: &ReadPartitionRequest) -> Result<Self> {
+        let path = get_sorted_partition_path(
+            req.table_name.as_str(),
+            req.partition_date,
+            req.partition_index,
+        );
+
+        let filenames = list_grid_files(path.as_str())?;
+
+        let time_range = if req.time_end > req.time_start {
+            Some((req.time_start, req.time_end))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            path,
+            filenames,
+            time_range,
+            column_ids: req.column_ids.clone(),
+            key_schema: KeySchema::from_col_ids(&req.key_column_ids)?,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Project the bytes of a block to `column_ids`.
+    fn project_block(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        if self.column_ids.is_empty() {
+            return Ok(bytes);
+        }
+
+        let gridbuffer = GridBuffer::from_bytes(&bytes)?;
+        let projected = project_gridbuffer(&gridbuffer, &self.key_schema, &self.column_ids)?;
+
+        Ok(projected.to_bytes())
+    }
+
+    /// Send all blocks to `sender`, return the number of blocks sent.
+    ///
+    /// It reads files in blocking way, so it must be called in `spawn_blocking`. Stop when the
+    /// receiver is closed, e.g. the client is disconnected.
+    pub fn send_blocks(
+        &self,
+        sender: &Sender<Result<ReadPartitionResponse, Status>>,
+    ) -> Result<u64> {
+        let mut count_blocks = 0;
+
+        for filename in self.filenames.iter() {
+            let mut reader = GridSegmentReader::open(filename.as_str())?;
+
+            if let Some((start_ts, end_ts)) = self.time_range {
+                reader.seek_by_time(start_ts, end_ts)?;
+            }
+
+            while let Some(bytes) = reader.next_block_bytes()? {
+                let res = ReadPartitionResponse {
+                    gridbuffer_bytes: self.project_block(bytes)?,
+                };
+
+                if sender.blocking_send(Ok(res)).is_err() {
+                    info!(
+                        "receiver is closed, stop reading partition, path: {}",
+                        self.path.clone()
+                    );
+                    return Ok(count_blocks);
+                }
+
+                count_blocks += 1;
+            }
+        }
+
+        Ok(count_blocks)
+    }
+}
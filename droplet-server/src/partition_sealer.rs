@@ -0,0 +1,290 @@
+//! Seal partitions whose `sinker`s are done, and merge the files of the partition in background.
+//!
+//! A partition is sealed when `PartitionWatermark` says so, which is checked after each
+//! `SinkGridSample` and `FinishSinkPartition`, and periodically by `run_seal_checker` for the
+//! partitions without requests, e.g. all the `sinker`s crashed.
+//!
+//! Merging a large partition takes minutes, so it runs as a background job instead of in the
+//! `grpc` handler, at most `max_merge_jobs` jobs run at the same time. The state of each
+//! partition is tracked for `GetPartitionStatus`: `Writing`, `Merging`, `Sealed` or `Failed`.
+//!
+//! Sealed and failed partitions cannot be started again, a new `SampleSaver` would truncate the
+//! files of the partition and the next merge would overwrite the sorted files. A failed
+//! partition keeps its unmerged files, the `FAILED` file is written to the partition path with
+//! the error, and the partition could be written again only after an operator deletes it.
+//!
+//! Both `SUCCESS` and `FAILED` files survive restarts, so only the partitions being written or
+//! merged are kept in memory.
+use anyhow::{anyhow, bail, Result};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use log::{error, info};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::droplet::SinkPartitionState;
+use droplet_core::error_bail;
+use droplet_core::partition::parse_partition_path;
+
+use crate::sample_saver::{get_failed_filename, get_success_filename, MergeStats, SampleSaver};
+
+/// State of a partition, and the error of merging if `Failed`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartitionJobStatus {
+    pub state: SinkPartitionState,
+    pub error_message: String,
+}
+
+impl PartitionJobStatus {
+    pub fn new(state: SinkPartitionState) -> Self {
+        Self {
+            state,
+            error_message: String::new(),
+        }
+    }
+
+    pub fn failed(error_message: String) -> Self {
+        Self {
+            state: SinkPartitionState::Failed,
+            error_message,
+        }
+    }
+}
+
+/// Seal partitions of the `SampleSaver`s shared with `DropletServerImpl`.
+#[derive(Clone)]
+pub struct PartitionSealer {
+    store: Arc<dyn MetaStore>,
+
+    /// Sample savers of the partitions being written, key is `path_id`.
+    sample_savers: Arc<DashMap<u32, Arc<SampleSaver>>>,
+
+    /// Status of the partitions being written or merged, key is partition path.
+    ///
+    /// The entry is removed once the `SUCCESS` or `FAILED` file is written.
+    statuses: Arc<DashMap<String, PartitionJobStatus>>,
+
+    /// Limit the number of running merge jobs.
+    merge_permits: Arc<Semaphore>,
+}
+
+impl PartitionSealer {
+    pub fn new(
+        store: Arc<dyn MetaStore>,
+        sample_savers: Arc<DashMap<u32, Arc<SampleSaver>>>,
+        max_merge_jobs: usize,
+    ) -> Self {
+        Self {
+            store,
+            sample_savers,
+            statuses: Arc::new(DashMap::new()),
+            merge_permits: Arc::new(Semaphore::new(max_merge_jobs)),
+        }
+    }
+
+    /// Status of the partition.
+    ///
+    /// Partitions merged or failed before, including before the server restarted, are found by
+    /// the `SUCCESS` or `FAILED` file.
+    pub fn get_status(&self, path: &str) -> PartitionJobStatus {
+        if let Some(status) = self.statuses.get(path) {
+            return status.clone();
+        }
+
+        Self::get_file_status(path)
+            .unwrap_or_else(|| PartitionJobStatus::new(SinkPartitionState::NotFound))
+    }
+
+    /// Status by the `SUCCESS` or `FAILED` file, `None` if neither exists.
+    fn get_file_status(path: &str) -> Option<PartitionJobStatus> {
+        if Path::new(&get_success_filename(path)).exists() {
+            return Some(PartitionJobStatus::new(SinkPartitionState::Sealed));
+        }
+
+        let failed_filename = get_failed_filename(path);
+
+        if Path::new(&failed_filename).exists() {
+            let error_message = std::fs::read_to_string(&failed_filename).unwrap_or_default();
+            return Some(PartitionJobStatus::failed(error_message));
+        }
+
+        None
+    }
+
+    /// Mark the partition as `Writing` before creating its `SampleSaver`.
+    ///
+    /// Partitions being written, merging, merged or failed cannot be written again, otherwise
+    /// the files would be overwritten. Checked and marked under the lock of the entry, so only
+    /// one caller could start the partition.
+    pub fn start_writing(&self, path: &str) -> Result<()> {
+        let state = match self.statuses.entry(path.to_string()) {
+            Entry::Occupied(entry) => entry.get().state,
+            Entry::Vacant(entry) => match Self::get_file_status(path) {
+                Some(status) => status.state,
+                None => {
+                    entry.insert(PartitionJobStatus::new(SinkPartitionState::Writing));
+                    return Ok(());
+                }
+            },
+        };
+
+        error_bail!(
+            "Partition cannot be written, path: {}, state: {}",
+            path,
+            state.as_str_name()
+        );
+    }
+
+    /// Undo `start_writing` if the `SampleSaver` is not created, no data is written.
+    pub fn abort_writing(&self, path: &str) {
+        self.statuses.remove_if(path, |_, status| {
+            status.state == SinkPartitionState::Writing
+        });
+    }
+
+    /// `path_id`s of the partitions which could be sealed.
+    pub fn get_sealable_path_ids(&self) -> Vec<u32> {
+        self.sample_savers
+            .iter()
+            .filter(|x| x.should_seal())
+            .map(|x| *x.key())
+            .collect()
+    }
+
+    /// Seal the partition and start the merge job in background.
+    ///
+    /// The `SampleSaver` is removed first, so the partition is sealed only once, and no data is
+    /// accepted after. Return `false` if the partition is not found, e.g. it's already sealed.
+    pub fn seal(&self, path_id: u32) -> bool {
+        // Marked as `Merging` under the lock of the entry, so `StartSinkPartition` either finds
+        // the `SampleSaver` or the state.
+        let saver = match self.sample_savers.entry(path_id) {
+            Entry::Occupied(entry) => {
+                self.statuses.insert(
+                    entry.get().path().to_string(),
+                    PartitionJobStatus::new(SinkPartitionState::Merging),
+                );
+                entry.remove()
+            }
+            Entry::Vacant(_) => return false,
+        };
+
+        info!(
+            "Seal partition, path: {}, path_id: {}",
+            saver.path(),
+            path_id
+        );
+
+        saver.close_sender();
+
+        let path = saver.path().to_string();
+
+        let sealer = self.clone();
+
+        tokio::spawn(async move {
+            match sealer.merge(saver).await {
+                Ok(stats) => {
+                    info!("Merge partition done, path: {}, stats: {:?}", path, stats);
+                    sealer.update_partition_stats(&path, stats);
+
+                    // `get_status` finds the `SUCCESS` file.
+                    sealer.statuses.remove(&path);
+                }
+                Err(e) => {
+                    error!("Merge partition failed, path: {}, error: {}", path, e);
+                    sealer.set_failed(&path, e.to_string());
+                }
+            }
+        });
+
+        true
+    }
+
+    /// Wait the workers to write the received data, then merge the files and write the
+    /// `SUCCESS` file.
+    ///
+    /// Handlers may still hold the `SampleSaver` for a while, but nothing is sent after the
+    /// sender is closed.
+    async fn merge(&self, saver: Arc<SampleSaver>) -> Result<MergeStats> {
+        // Wait the workers done.
+        while !saver.is_workers_done() {
+            tokio::time::sleep(Duration::from_secs(3)).await;
+        }
+
+        let _permit = self.merge_permits.acquire().await?;
+
+        tokio::task::spawn_blocking(move || {
+            let stats = saver.merge_sort()?;
+            saver.write_success_file()?;
+
+            Ok(stats)
+        })
+        .await
+        .map_err(|e| anyhow!("merge job panicked, error: {}", e))?
+    }
+
+    /// Write the `FAILED` file of the partition, so it's not written again even after restart.
+    ///
+    /// The status is kept in memory if the file cannot be written.
+    fn set_failed(&self, path: &str, error_message: String) {
+        match std::fs::write(get_failed_filename(path), error_message.as_bytes()) {
+            Ok(()) => {
+                self.statuses.remove(path);
+            }
+            Err(e) => {
+                error!(
+                    "Write failed file of partition failed, path: {}, error: {}",
+                    path, e
+                );
+                self.statuses
+                    .insert(path.to_string(), PartitionJobStatus::failed(error_message));
+            }
+        }
+    }
+
+    /// Seal the partitions which could be sealed every `check_interval` seconds.
+    ///
+    /// Runs until the process exits.
+    pub async fn run_seal_checker(self, check_interval: u64) {
+        info!(
+            "Start partition seal checker, check_interval: {}",
+            check_interval
+        );
+
+        let mut interval = tokio::time::interval(Duration::from_secs(check_interval));
+
+        loop {
+            interval.tick().await;
+
+            for path_id in self.get_sealable_path_ids() {
+                self.seal(path_id);
+            }
+        }
+    }
+
+    /// Save the stats of the merged partition to meta store.
+    ///
+    /// The partition is already merged, so only log the error if failed.
+    fn update_partition_stats(&self, path: &str, stats: MergeStats) {
+        let res =
+            parse_partition_path(path).and_then(|(table, partition_date, partition_index)| {
+                self.store.update_partition_stats(
+                    table.as_str(),
+                    partition_date,
+                    partition_index,
+                    stats.row_count,
+                    stats.byte_size,
+                )
+            });
+
+        if let Err(e) = res {
+            error!(
+                "Update partition stats failed, path: {}, error: {}",
+                path, e
+            );
+        }
+    }
+}
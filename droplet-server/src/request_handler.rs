@@ -1,26 +1,31 @@
-use std::time::Duration;
-
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 
 use anyhow::Result;
-use log::error;
+use log::{error, info};
 
 use std::sync::Arc;
 
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 use droplet_core::droplet::droplet_server::Droplet;
 use droplet_core::droplet::{
-    FinishSinkPartitionRequest, FinishSinkPartitionResponse, HeartbeatRequest, HeartbeatResponse,
-    SinkGridSampleRequest, SinkGridSampleResponse, StartSinkPartitionRequest,
-    StartSinkPartitionResponse,
+    FinishSinkPartitionRequest, FinishSinkPartitionResponse, GetPartitionStatusRequest,
+    GetPartitionStatusResponse, HeartbeatRequest, HeartbeatResponse, ReadPartitionRequest,
+    ReadPartitionResponse, SinkGridSampleRequest, SinkGridSampleResponse,
+    StartSinkPartitionRequest, StartSinkPartitionResponse,
 };
 
-use droplet_core::db::db::DB;
-use droplet_core::db::meta_info::get_or_insert_key_id;
-use droplet_core::grpc_util::send_error_message;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::grpc_util::{get_error_status, send_bad_request_error, send_error_message};
+use droplet_core::partition::{check_table_name, get_partition_path, parse_partition_path};
 
+use crate::partition_reader::PartitionReader;
+use crate::partition_sealer::PartitionSealer;
 use crate::sample_saver::SampleSaver;
+use crate::sink_schema::SinkSchema;
+use crate::watermark::{PartitionWatermark, SealConfig};
 
 /// Droplet server implementation.
 ///
@@ -29,25 +34,134 @@ use crate::sample_saver::SampleSaver;
 /// We also need to handle data from different `sinker`s, which means we need to dispatch each request to different
 /// worker threads that are dedicated to a particular `sinker`.
 pub struct DropletServerImpl {
-    db: Arc<DB>,
+    store: Arc<dyn MetaStore>,
 
     /// Different sample savers.
     ///
     /// For performance consideration, we use global key of `path` as the key of `DashMap`, instead of `String`.
-    sample_savers: DashMap<u32, SampleSaver>,
+    ///
+    /// The `SampleSaver` is shared by `Arc`, so the handlers clone it out and release the lock of
+    /// the `DashMap` shard before `await`.
+    sample_savers: Arc<DashMap<u32, Arc<SampleSaver>>>,
+
+    /// Allowed lateness and lease timeout of partitions.
+    seal_config: SealConfig,
+
+    /// Seal the partitions in `sample_savers`.
+    sealer: PartitionSealer,
 }
 
 impl DropletServerImpl {
-    pub fn new(db: Arc<DB>) -> Self {
+    pub fn new(store: Arc<dyn MetaStore>) -> Self {
+        let sample_savers = Arc::new(DashMap::new());
+
         Self {
-            db,
-            sample_savers: DashMap::new(),
+            store: store.clone(),
+            sample_savers: sample_savers.clone(),
+            seal_config: SealConfig::default(),
+            sealer: PartitionSealer::new(
+                store,
+                sample_savers,
+                SealConfig::default().max_merge_jobs,
+            ),
         }
     }
 
-    fn get_path_id(&self, path: &str) -> u32 {
-        let mut conn = self.db.get_conn().unwrap();
-        get_or_insert_key_id(&mut conn, path)
+    pub fn with_seal_config(mut self, seal_config: SealConfig) -> Self {
+        self.sealer = PartitionSealer::new(
+            self.store.clone(),
+            self.sample_savers.clone(),
+            seal_config.max_merge_jobs,
+        );
+        self.seal_config = seal_config;
+        self
+    }
+
+    /// `PartitionSealer` of the server, used to run the seal checker.
+    pub fn sealer(&self) -> PartitionSealer {
+        self.sealer.clone()
+    }
+
+    fn get_path_id(&self, path: &str) -> Result<u32> {
+        self.store.get_or_insert_key_id(path)
+    }
+
+    /// Load the schema of the table of the partition for validating the sinked `GridBuffer`s.
+    fn load_sink_schema(&self, req: &StartSinkPartitionRequest) -> Result<SinkSchema> {
+        let (table, _, _) = parse_partition_path(req.path.as_str())?;
+
+        SinkSchema::load(self.store.as_ref(), table.as_str(), &req.key_column_ids)
+    }
+
+    /// Watermark to seal the partition, `time_end` of the partition plus the allowed lateness,
+    /// in the `timestamp_unit` of the table.
+    fn get_seal_watermark(&self, req: &StartSinkPartitionRequest) -> Result<u64> {
+        let (table, partition_date, partition_index) = parse_partition_path(req.path.as_str())?;
+
+        let scheme = self.store.get_partition_scheme(table.as_str())?;
+        let (_, time_end) = scheme.get_partition_time_range(partition_date, partition_index)?;
+
+        Ok(time_end + self.seal_config.allowed_lateness * scheme.units_per_second())
+    }
+
+    /// Load the schema and the seal watermark of the partition from `MetaStore`.
+    ///
+    /// Called before taking the entry of `sample_savers`, so the shard is not locked by the
+    /// queries of `MetaStore`.
+    fn load_partition_meta(
+        &self,
+        req: &StartSinkPartitionRequest,
+    ) -> Result<(SinkSchema, PartitionWatermark)> {
+        let schema = self.load_sink_schema(req)?;
+        let watermark = PartitionWatermark::new(
+            self.get_seal_watermark(req)?,
+            self.seal_config.lease_timeout,
+        );
+
+        Ok((schema, watermark))
+    }
+
+    /// Create the `SampleSaver` of the partition.
+    ///
+    /// The workers of `SampleSaver` create the files of the partition, so the partition must
+    /// not be merging or merged.
+    fn new_sample_saver(
+        &self,
+        req: &StartSinkPartitionRequest,
+        schema: SinkSchema,
+        watermark: PartitionWatermark,
+    ) -> Result<SampleSaver> {
+        self.sealer.start_writing(req.path.as_str())?;
+
+        SampleSaver::new(
+            req.path.as_str(),
+            req.path_id,
+            req.partition_index,
+            schema,
+            watermark,
+        )
+        .inspect_err(|_| self.sealer.abort_writing(req.path.as_str()))
+    }
+
+    /// Renew the lease and advance the watermark of the `sinker` in all the partitions of the
+    /// table, return the `path_id`s of the partitions which could be sealed.
+    ///
+    /// The watermark is the progress of the `sinker` in the whole table, so the partitions it
+    /// left without `FinishSinkPartition` could be sealed too.
+    fn update_watermarks(&self, table: &str, sinker_id: u32, watermark: u64) -> Vec<u32> {
+        self.sample_savers
+            .iter()
+            .filter(|saver| saver.table() == table)
+            .filter_map(|saver| {
+                saver.update_watermark(sinker_id, watermark);
+
+                if saver.should_seal() {
+                    Some(*saver.key())
+                } else {
+                    None
+                }
+            })
+            .collect()
     }
 }
 
@@ -66,29 +180,60 @@ impl Droplet for DropletServerImpl {
     ) -> Result<Response<StartSinkPartitionResponse>, Status> {
         let req = request.into_inner();
 
-        match self.sample_savers.get(&req.path_id) {
-            Some(saver) => saver.start_partition(req.sinker_id),
-            None => {
-                let saver =
-                    match SampleSaver::new(req.path.as_str(), req.path_id, req.partition_index) {
-                        Ok(saver) => saver,
-                        Err(e) => {
-                            error!(
-                                "Create sample saver failed, path: {}, error: {}",
-                                req.path.clone(),
-                                e
-                            );
-                            return send_error_message::<StartSinkPartitionResponse>(format!(
-                                "Create sample saver failed, path: {}, error: {}",
-                                req.path.clone(),
-                                e
-                            ));
-                        }
-                    };
+        if let Err(e) = parse_partition_path(req.path.as_str()) {
+            error!(
+                "Invalid partition path, path: {}, error: {}",
+                req.path.clone(),
+                e
+            );
+            return send_bad_request_error::<StartSinkPartitionResponse>("path", e.to_string());
+        }
+
+        if let Some(saver) = self.sample_savers.get(&req.path_id) {
+            saver.start_partition(req.sinker_id);
+            return Ok(Response::new(StartSinkPartitionResponse { success: true }));
+        }
+
+        let (schema, watermark) = match self.load_partition_meta(&req) {
+            Ok(res) => res,
+            Err(e) => {
+                error!(
+                    "Load partition meta failed, path: {}, error: {}",
+                    req.path.clone(),
+                    e
+                );
+                return send_error_message::<StartSinkPartitionResponse>(format!(
+                    "Load partition meta failed, path: {}, error: {}",
+                    req.path.clone(),
+                    e
+                ));
+            }
+        };
+
+        // Checked and inserted under the lock of the entry, so sinkers starting the same new
+        // partition share one `SampleSaver`, and a sealed partition is never started again.
+        match self.sample_savers.entry(req.path_id) {
+            Entry::Occupied(entry) => entry.get().start_partition(req.sinker_id),
+            Entry::Vacant(entry) => {
+                let saver = match self.new_sample_saver(&req, schema, watermark) {
+                    Ok(saver) => saver,
+                    Err(e) => {
+                        error!(
+                            "Create sample saver failed, path: {}, error: {}",
+                            req.path.clone(),
+                            e
+                        );
+                        return send_error_message::<StartSinkPartitionResponse>(format!(
+                            "Create sample saver failed, path: {}, error: {}",
+                            req.path.clone(),
+                            e
+                        ));
+                    }
+                };
 
                 saver.start_partition(req.sinker_id);
 
-                self.sample_savers.insert(req.path_id, saver);
+                entry.insert(Arc::new(saver));
             }
         }
 
@@ -103,24 +248,51 @@ impl Droplet for DropletServerImpl {
 
         let path_id = req.path_id;
 
-        match self.sample_savers.get(&path_id) {
-            Some(saver) => match saver.process(req).await {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("Save has error, path_id: {}, error: {}", path_id, e);
-                    return send_error_message::<SinkGridSampleResponse>(format!(
-                        "Save has error, path_id: {}, error: {}",
-                        path_id, e
-                    ));
-                }
-            },
+        // Cloned out of the `DashMap`, the guard must not be held across `process`, which waits
+        // for the workers when the channel is full, and blocks `seal` and `start_sink_partition`
+        // of the same shard.
+        let saver = match self.sample_savers.get(&path_id) {
+            Some(saver) => Arc::clone(saver.value()),
             None => {
-                error!("Sample saver not found for path_id: {}", path_id);
+                error!(
+                    "Sample saver not found, the partition may be sealed, path_id: {}",
+                    path_id
+                );
                 return send_error_message::<SinkGridSampleResponse>(format!(
-                    "Sample saver not found for path_id: {}",
+                    "Sample saver not found, the partition may be sealed, path_id: {}",
                     path_id
                 ));
             }
+        };
+
+        let gridbuffer = match saver.decode(&req.grid_sample_bytes) {
+            Ok(gridbuffer) => gridbuffer,
+            Err(violation) => {
+                error!(
+                    "Invalid grid sample, path: {}, sinker_id: {}, {}",
+                    saver.path(),
+                    req.sinker_id,
+                    violation
+                );
+                return send_bad_request_error::<SinkGridSampleResponse>(
+                    violation.field,
+                    violation.description,
+                );
+            }
+        };
+
+        // Fails if the partition is sealed in the meantime, the sender is closed then.
+        if let Err(e) = saver.process(gridbuffer).await {
+            error!("Save has error, path_id: {}, error: {}", path_id, e);
+            return send_error_message::<SinkGridSampleResponse>(format!(
+                "Save has error, path_id: {}, error: {}",
+                path_id, e
+            ));
+        }
+
+        for sealable_path_id in self.update_watermarks(saver.table(), req.sinker_id, req.watermark)
+        {
+            self.sealer.seal(sealable_path_id);
         }
 
         Ok(Response::new(SinkGridSampleResponse {
@@ -136,47 +308,110 @@ impl Droplet for DropletServerImpl {
     ) -> Result<Response<FinishSinkPartitionResponse>, Status> {
         let req = request.into_inner();
 
-        let mut is_done = false;
-
-        match self.sample_savers.get(&req.path_id) {
+        let should_seal = match self.sample_savers.get(&req.path_id) {
             Some(saver) => {
                 saver.finish_partition(req.sinker_id);
-
-                if saver.is_sinkers_done() {
-                    saver.close_sender();
-                    // Wait the workers done.
-                    while !saver.is_workers_done() {
-                        tokio::time::sleep(Duration::from_secs(3)).await;
-                    }
-
-                    match saver.merge_sort() {
-                        Ok(_) => {}
-                        Err(e) => {
-                            error!("Merge files failed, path: {}, error: {}", saver.path(), e);
-                            return send_error_message::<FinishSinkPartitionResponse>(format!(
-                                "Merge files failed, path: {}, error: {}",
-                                saver.path(),
-                                e
-                            ));
-                        }
-                    }
-
-                    is_done = true;
-                }
+                saver.should_seal()
             }
             None => {
-                error!("Sample saver not found for path_id: {}", req.path_id);
-                return send_error_message::<FinishSinkPartitionResponse>(format!(
-                    "Sample saver not found for path_id: {}",
-                    req.path_id
-                ));
+                // Sealed by watermark or lease expiration before the `sinker` finished.
+                info!(
+                    "Sample saver not found, the partition may be sealed, path_id: {}, sinker_id: {}",
+                    req.path_id, req.sinker_id
+                );
+                return Ok(Response::new(FinishSinkPartitionResponse { success: true }));
             }
-        }
+        };
 
-        if is_done {
-            self.sample_savers.remove(&req.path_id);
+        // Files are merged in background, the state could be checked by `GetPartitionStatus`.
+        if should_seal {
+            self.sealer.seal(req.path_id);
         }
 
         Ok(Response::new(FinishSinkPartitionResponse { success: true }))
     }
+
+    async fn get_partition_status(
+        &self,
+        request: Request<GetPartitionStatusRequest>,
+    ) -> Result<Response<GetPartitionStatusResponse>, Status> {
+        let req = request.into_inner();
+
+        if let Err(e) = check_table_name(req.table_name.as_str()) {
+            error!("Invalid table name, error: {}", e);
+            return send_bad_request_error::<GetPartitionStatusResponse>(
+                "table_name",
+                e.to_string(),
+            );
+        }
+
+        let path = get_partition_path(
+            req.table_name.as_str(),
+            req.partition_date,
+            req.partition_index,
+        );
+        let status = self.sealer.get_status(path.as_str());
+
+        Ok(Response::new(GetPartitionStatusResponse {
+            state: status.state.into(),
+            error_message: status.error_message,
+        }))
+    }
+
+    type ReadPartitionStream = ReceiverStream<Result<ReadPartitionResponse, Status>>;
+
+    async fn read_partition(
+        &self,
+        request: Request<ReadPartitionRequest>,
+    ) -> Result<Response<Self::ReadPartitionStream>, Status> {
+        let req = request.into_inner();
+
+        if let Err(e) = check_table_name(req.table_name.as_str()) {
+            error!("Invalid table name, error: {}", e);
+            return send_bad_request_error::<Self::ReadPartitionStream>(
+                "table_name",
+                e.to_string(),
+            );
+        }
+
+        let reader = match PartitionReader::new(&req) {
+            Ok(reader) => reader,
+            Err(e) => {
+                error!(
+                    "Open partition failed, table: {}, partition_date: {}, partition_index: {}, error: {}",
+                    req.table_name.clone(),
+                    req.partition_date,
+                    req.partition_index,
+                    e
+                );
+                return send_error_message::<Self::ReadPartitionStream>(format!(
+                    "Open partition failed, table: {}, partition_date: {}, partition_index: {}, error: {}",
+                    req.table_name.clone(),
+                    req.partition_date,
+                    req.partition_index,
+                    e
+                ));
+            }
+        };
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(16);
+
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = reader.send_blocks(&sender) {
+                error!(
+                    "Read partition failed, path: {}, error: {}",
+                    reader.path(),
+                    e
+                );
+
+                let _ = sender.blocking_send(Err(get_error_status(format!(
+                    "Read partition failed, path: {}, error: {}",
+                    reader.path(),
+                    e
+                ))));
+            }
+        });
+
+        Ok(Response::new(ReceiverStream::new(receiver)))
+    }
 }
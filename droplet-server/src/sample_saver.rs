@@ -1,15 +1,10 @@
 use anyhow::{anyhow, bail, Result};
-use dashmap::DashMap;
-use droplet_core::{droplet::SinkGridSampleRequest, window_heap::WindowHeap};
+use droplet_core::window_heap::WindowHeap;
 use likely_stable::unlikely;
 use log::{error, info};
 use std::fs::File;
 use tokio_graceful_shutdown::{SubsystemBuilder, SubsystemHandle, Toplevel};
 
-use std::io::BufRead;
-use std::io::BufReader;
-use std::io::Write;
-
 use std::time::Duration;
 
 use std::sync::Arc;
@@ -18,6 +13,13 @@ use sync_unsafe_cell::SyncUnsafeCell;
 use gridbuffer::core::gridbuffer::GridBuffer;
 
 use droplet_core::error_bail;
+use droplet_core::grid_sample::KeySchema;
+use droplet_core::grid_segment::{GridSegmentReader, GridSegmentWriter};
+use droplet_core::partition::{get_sorted_path, list_grid_files, parse_partition_path};
+use droplet_core::tool::get_timestamp_secs;
+
+use crate::sink_schema::{SchemaViolation, SinkSchema};
+use crate::watermark::PartitionWatermark;
 
 #[derive(Default, Debug, Clone, Eq, PartialEq)]
 enum WorkerState {
@@ -27,9 +29,18 @@ enum WorkerState {
     Success,
 }
 
+/// Row count and byte size of the sorted files after `merge_sort`.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MergeStats {
+    pub row_count: u64,
+    pub byte_size: u64,
+}
+
 #[derive(Default)]
 pub struct WorkerInfo {
     worker_id: u32,
+
+    /// Number of blocks written to file.
     total: u64,
     worker_state: WorkerState,
 }
@@ -37,10 +48,21 @@ pub struct WorkerInfo {
 unsafe impl Send for WorkerInfo {}
 unsafe impl Sync for WorkerInfo {}
 
+/// The `SUCCESS` file of the partition path, exists only if the partition is merged.
+pub fn get_success_filename(path: &str) -> String {
+    format!("{}/SUCCESS", path)
+}
+
+/// The `FAILED` file of the partition path, exists if merging the partition failed, the content
+/// is the error.
+pub fn get_failed_filename(path: &str) -> String {
+    format!("{}/FAILED", path)
+}
+
 /// `SampleSaver` is responsible for saving `GridSample`s to different partitions.
 ///
 /// One `SampleSaver` is responsible for one partition. The data would come from multiple `sinker`s.
-/// If all `sinker`s are done with the partition, the `SampleSaver` would be dropped.
+/// When the partition is sealed by `PartitionWatermark`, the `SampleSaver` would be dropped.
 ///
 /// There are multiple concurrent tasks involved. Such as sorting `GridSample`s by `SampleKey`,
 /// save to different files, merge files, etc.
@@ -63,16 +85,17 @@ pub struct SampleSaver {
     /// All file names under `path`.
     filenames: Vec<String>,
 
-    /// All `sinker` ids.
-    ///
-    /// Key: `sinker` id.
-    /// Value: `true` if the `sinker` is connected. One `sinker` is a thread of one `sinker` worker.
+    /// Table name, parsed from `path`.
+    table: String,
+
+    /// Watermarks and leases of the active `sinker`s.
     ///
-    /// `grpc` request `start_sink_partition` and `finish_sink_partition` are used to update the `sinker_ids`.
-    sinker_ids: DashMap<u32, bool>,
+    /// One `sinker` is a thread of one `sinker` worker. `grpc` request `start_sink_partition`,
+    /// `sink_grid_sample` and `finish_sink_partition` are used to update it.
+    watermark: PartitionWatermark,
 
-    /// Sender of `SinkGridSampleRequest`.
-    sender: async_channel::Sender<SinkGridSampleRequest>,
+    /// Sender of the validated `GridBuffer`s.
+    sender: async_channel::Sender<GridBuffer>,
 
     /// Number of workers.
     worker_num: u32,
@@ -90,15 +113,34 @@ pub struct SampleSaver {
 
     /// Window size for final merge sort.
     window_size: u32,
+
+    /// Key columns of the table, the rows are sorted by them.
+    key_schema: KeySchema,
+
+    /// Schema of the table, used to validate the sinked `GridBuffer`s.
+    schema: SinkSchema,
 }
 
 impl SampleSaver {
-    pub fn new(path: &str, path_id: u32, partition_index: u32) -> Result<Self> {
-        let (sender, receiver) = async_channel::bounded::<SinkGridSampleRequest>(256);
+    pub fn new(
+        path: &str,
+        path_id: u32,
+        partition_index: u32,
+        schema: SinkSchema,
+        watermark: PartitionWatermark,
+    ) -> Result<Self> {
+        let (table, _, _) = parse_partition_path(path)?;
+
+        let (sender, receiver) = async_channel::bounded::<GridBuffer>(256);
+
+        let key_schema = schema.key_schema().clone();
 
         let worker_num = 8;
 
-        let worker_infos = vec![Arc::new(SyncUnsafeCell::new(WorkerInfo::default())); worker_num];
+        // Each worker has its own state, `merge_sort` must wait all of them.
+        let worker_infos = (0..worker_num)
+            .map(|_| Arc::new(SyncUnsafeCell::new(WorkerInfo::default())))
+            .collect::<Vec<_>>();
 
         let mut filenames = Vec::with_capacity(worker_num);
         for i in 0..worker_num {
@@ -107,10 +149,17 @@ impl SampleSaver {
 
         {
             let filenames_clone = filenames.clone();
-            Self::start_worker(receiver, &filenames_clone, path, path_id, &worker_infos);
+            Self::start_worker(
+                receiver,
+                &filenames_clone,
+                path,
+                path_id,
+                &worker_infos,
+                &key_schema,
+            );
         }
 
-        let path_sorted = path.replace("droplet", "droplet_sorted").to_string();
+        let path_sorted = get_sorted_path(path);
 
         std::fs::create_dir_all(path)?;
         std::fs::create_dir_all(path_sorted.clone())?;
@@ -121,22 +170,26 @@ impl SampleSaver {
             partition_index,
             cur_filename: "".to_string(),
             filenames,
-            sinker_ids: DashMap::new(),
+            table,
+            watermark,
             sender,
             worker_num: worker_num as u32,
             worker_infos,
             path_sorted,
             batch_size: 4,
             window_size: 256,
+            key_schema,
+            schema,
         })
     }
 
     fn start_worker(
-        receiver: async_channel::Receiver<SinkGridSampleRequest>,
+        receiver: async_channel::Receiver<GridBuffer>,
         filenames: &Vec<String>,
         _path: &str,
         path_id: u32,
         worker_infos: &Vec<Arc<SyncUnsafeCell<WorkerInfo>>>,
+        key_schema: &KeySchema,
     ) {
         for (i, filename) in filenames.iter().enumerate() {
             info!("start sample saver worker {}", i);
@@ -153,6 +206,7 @@ impl SampleSaver {
                 filename_clone.as_str(),
                 new_receiver,
                 worker_info_clone,
+                key_schema,
             );
 
             tokio::spawn(async move {
@@ -172,12 +226,24 @@ impl SampleSaver {
         &self.path
     }
 
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
     pub fn start_partition(&self, sinker_id: u32) {
-        self.sinker_ids.insert(sinker_id, true);
+        self.watermark.start(sinker_id, get_timestamp_secs());
+    }
+
+    /// Renew the lease of the `sinker` and advance its watermark, if it's active.
+    pub fn update_watermark(&self, sinker_id: u32, watermark: u64) {
+        if self.watermark.contains(sinker_id) {
+            self.watermark
+                .update(sinker_id, watermark, get_timestamp_secs());
+        }
     }
 
     pub fn finish_partition(&self, sinker_id: u32) {
-        self.sinker_ids.remove(&sinker_id);
+        self.watermark.finish(sinker_id);
     }
 
     pub fn close_sender(&self) {
@@ -185,7 +251,12 @@ impl SampleSaver {
     }
 
     pub fn is_sinkers_done(&self) -> bool {
-        self.sinker_ids.len() == 0
+        self.watermark.is_empty()
+    }
+
+    /// Whether all active `sinker`s passed the end of the partition, or their leases expired.
+    pub fn should_seal(&self) -> bool {
+        self.watermark.should_seal(get_timestamp_secs())
     }
 
     pub fn is_workers_done(&self) -> bool {
@@ -195,9 +266,15 @@ impl SampleSaver {
         })
     }
 
-    pub async fn process(&self, req: SinkGridSampleRequest) -> Result<()> {
+    /// Decode and validate the bytes of `GridBuffer` against the schema of the table.
+    pub fn decode(&self, bytes: &[u8]) -> std::result::Result<GridBuffer, SchemaViolation> {
+        self.schema.decode(bytes)
+    }
+
+    /// Send the `GridBuffer` to workers, it must be validated by `decode`.
+    pub async fn process(&self, gridbuffer: GridBuffer) -> Result<()> {
         self.sender
-            .send(req)
+            .send(gridbuffer)
             .await
             .map_err(|_| anyhow!("send request to sample saver failed"))
     }
@@ -217,19 +294,19 @@ impl SampleSaver {
     }
 
     /// Use empty file `SUCCESS` to indicate the partition is done.
+    ///
+    /// It's written only after `merge_sort` succeeds, so the sorted files are complete.
     pub fn write_success_file(&self) -> Result<()> {
         if unlikely(self.path.is_empty()) {
             error_bail!("path is empty");
         }
 
-        let filename = format!("{}/SUCCESS", self.path.to_string());
-
-        File::create(filename)?;
+        File::create(get_success_filename(&self.path))?;
 
         Ok(())
     }
 
-    fn get_total_lines(&self) -> u64 {
+    fn get_total_blocks(&self) -> u64 {
         self.worker_infos
             .iter()
             .map(|x| unsafe {
@@ -239,7 +316,26 @@ impl SampleSaver {
             .sum()
     }
 
-    pub fn merge_sort(&self) -> Result<()> {
+    /// Read next `GridBuffer` from the reader. Return `None` if the reader is done or has error.
+    fn read_next_gridbuffer(reader: &mut GridSegmentReader) -> Option<GridBuffer> {
+        match reader.next() {
+            Some(Ok(gridbuffer)) => Some(gridbuffer),
+            Some(Err(err)) => {
+                error!(
+                    "read gridbuffer failed, filename: {}, error: {}",
+                    reader.filename(),
+                    err
+                );
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Merge the unsorted files of workers into sorted files under `path_sorted`.
+    ///
+    /// Return the row count and byte size of the sorted files.
+    pub fn merge_sort(&self) -> Result<MergeStats> {
         if !self.is_workers_done() {
             error_bail!(
                 "sample saver workers are not done, path: {}",
@@ -248,38 +344,34 @@ impl SampleSaver {
         }
 
         let mut readers = Vec::with_capacity(self.worker_num as usize);
-        for (_i, filename) in self.filenames.iter().enumerate() {
-            let file = File::open(filename)?;
-            readers.push(BufReader::new(file));
+        for filename in self.filenames.iter() {
+            readers.push(GridSegmentReader::open(filename)?);
         }
 
-        let mut window_heap = WindowHeap::new(self.window_size as usize, self.batch_size as usize);
+        let mut window_heap = WindowHeap::new(self.window_size as usize, self.batch_size as usize)
+            .with_key_schema(&self.key_schema);
 
+        let mut is_reader_done = vec![false; readers.len()];
         let mut count_done = 0;
         let mut last_reader_index = 0;
+        let mut row_count = 0;
 
         let mut is_full = false;
-        // Read lines until window heap is full.
+        // Read gridbuffers until window heap is full.
         for _i in 0..self.window_size {
-            if is_full {
+            if is_full || count_done == readers.len() {
                 break;
             }
 
             for j in 0..readers.len() {
-                let mut line = String::new();
-                match readers[j].read_line(&mut line) {
-                    Ok(0) => {
-                        count_done += 1;
-
-                        if count_done == readers.len() {
-                            break;
-                        }
-                    }
-                    Ok(_) => {
-                        let line = line.trim_end();
-                        let gridbuffer = GridBuffer::from_base64(line)?;
+                if is_reader_done[j] {
+                    continue;
+                }
 
+                match Self::read_next_gridbuffer(&mut readers[j]) {
+                    Some(gridbuffer) => {
                         last_reader_index = j;
+                        row_count += gridbuffer.num_rows() as u64;
 
                         match window_heap.push_with_reader_index(gridbuffer, j) {
                             Ok(_) => {
@@ -293,50 +385,48 @@ impl SampleSaver {
                             }
                         }
                     }
-                    Err(_err) => {
+                    None => {
+                        is_reader_done[j] = true;
                         count_done += 1;
-
-                        if count_done == readers.len() {
-                            break;
-                        }
                     }
                 }
             }
         }
 
         let mut cur_file_index = 0;
-        let mut file =
-            File::create(format!("{}/{}.grid", self.path_sorted, cur_file_index).as_str())?;
-        let mut count_write_line = 0;
+        let mut writer = GridSegmentWriter::create(
+            format!("{}/{}.grid", self.path_sorted, cur_file_index).as_str(),
+        )?
+        .with_key_schema(&self.key_schema);
+        let mut count_write_block = 0;
 
-        let total_lines = self.get_total_lines();
-        let lines_per_file = total_lines / self.worker_num as u64;
+        let total_blocks = self.get_total_blocks();
+        let blocks_per_file = (total_blocks / self.worker_num as u64).max(1);
 
         while count_done < readers.len() {
-            let mut line = String::new();
-            match readers[last_reader_index].read_line(&mut line) {
-                Ok(0) => {
-                    count_done += 1;
-                    last_reader_index = (last_reader_index + 1) % readers.len();
-                }
-                Ok(_) => {
-                    let line = line.trim_end();
-                    let gridbuffer = GridBuffer::from_base64(line)?;
+            if is_reader_done[last_reader_index] {
+                last_reader_index = (last_reader_index + 1) % readers.len();
+                continue;
+            }
 
+            match Self::read_next_gridbuffer(&mut readers[last_reader_index]) {
+                Some(gridbuffer) => {
+                    row_count += gridbuffer.num_rows() as u64;
                     window_heap.push(gridbuffer)?;
 
                     self.process_out_gridbuffers(
                         &mut window_heap,
-                        &mut file,
-                        &mut count_write_line,
+                        &mut writer,
+                        &mut count_write_block,
                         &mut cur_file_index,
-                        lines_per_file,
+                        blocks_per_file,
                         &mut last_reader_index,
                     )?;
                 }
-                Err(err) => {
+                None => {
+                    is_reader_done[last_reader_index] = true;
                     count_done += 1;
-                    info!("read line from reader done, error: {}", err);
+                    last_reader_index = (last_reader_index + 1) % readers.len();
                 }
             }
         }
@@ -345,37 +435,50 @@ impl SampleSaver {
 
         self.process_out_gridbuffers(
             &mut window_heap,
-            &mut file,
-            &mut count_write_line,
+            &mut writer,
+            &mut count_write_block,
             &mut cur_file_index,
-            lines_per_file,
+            blocks_per_file,
             &mut last_reader_index,
         )?;
 
-        Ok(())
+        writer.finish()?;
+
+        let mut byte_size = 0;
+        for filename in list_grid_files(self.path_sorted.as_str())? {
+            byte_size += std::fs::metadata(filename)?.len();
+        }
+
+        Ok(MergeStats {
+            row_count,
+            byte_size,
+        })
     }
 
     fn process_out_gridbuffers(
         &self,
         window_heap: &mut WindowHeap,
-        file: &mut File,
-        count_write_line: &mut u64,
+        writer: &mut GridSegmentWriter,
+        count_write_block: &mut u64,
         cur_file_index: &mut u32,
-        lines_per_file: u64,
+        blocks_per_file: u64,
         last_reader_index: &mut usize,
     ) -> Result<()> {
         if window_heap.out_gridbuffers().len() > 0 {
             while let Some(gridbuffer) = window_heap.get_out_gridbuffer() {
-                file.write_all(gridbuffer.to_base64().as_bytes())?;
-                file.write_all(b"\n")?;
+                writer.write(&gridbuffer)?;
 
-                *count_write_line += 1;
-                if *count_write_line >= lines_per_file {
+                *count_write_block += 1;
+                if *count_write_block >= blocks_per_file {
                     *cur_file_index += 1;
-                    *file = File::create(
+
+                    let new_writer = GridSegmentWriter::create(
                         format!("{}/{}.grid", self.path_sorted, cur_file_index).as_str(),
-                    )?;
-                    *count_write_line = 0;
+                    )?
+                    .with_key_schema(&self.key_schema);
+                    std::mem::replace(writer, new_writer).finish()?;
+
+                    *count_write_block = 0;
                 }
             }
 
@@ -395,8 +498,8 @@ pub struct SampleSaverWorker {
     /// Worker id.
     worker_id: u32,
 
-    /// Receiver of `SinkGridSampleRequest`.
-    receiver: async_channel::Receiver<SinkGridSampleRequest>,
+    /// Receiver of the validated `GridBuffer`s.
+    receiver: async_channel::Receiver<GridBuffer>,
 
     /// Window heap for sorting `GridSample`s.
     window_heap: WindowHeap,
@@ -409,14 +512,18 @@ pub struct SampleSaverWorker {
 
     /// Worker states.
     worker_info: Arc<SyncUnsafeCell<WorkerInfo>>,
+
+    /// Key columns of the table.
+    key_schema: KeySchema,
 }
 
 impl SampleSaverWorker {
     pub fn new(
         worker_id: u32,
         filename: &str,
-        receiver: async_channel::Receiver<SinkGridSampleRequest>,
+        receiver: async_channel::Receiver<GridBuffer>,
         worker_info: Arc<SyncUnsafeCell<WorkerInfo>>,
+        key_schema: &KeySchema,
     ) -> Self {
         let window_size = 256;
         let batch_size = 4;
@@ -425,10 +532,11 @@ impl SampleSaverWorker {
             filename: filename.to_string(),
             worker_id,
             receiver,
-            window_heap: WindowHeap::new(window_size, batch_size),
+            window_heap: WindowHeap::new(window_size, batch_size).with_key_schema(key_schema),
             window_size: window_size as u32,
             batch_size: batch_size as u32,
             worker_info,
+            key_schema: key_schema.clone(),
         }
     }
 
@@ -442,15 +550,14 @@ impl SampleSaverWorker {
             error_bail!("filename is empty, worker_id: {}", self.worker_id);
         }
 
-        let mut file = File::create(self.filename.as_str())?;
+        let mut writer =
+            GridSegmentWriter::create(self.filename.as_str())?.with_key_schema(&self.key_schema);
 
         loop {
             tokio::select! {
-                req = self.receiver.recv() => {
-                    match req {
-                        Ok(req) => {
-                            let gridbuffer = GridBuffer::from_bytes(&req.grid_sample_bytes)?;
-
+                gridbuffer = self.receiver.recv() => {
+                    match gridbuffer {
+                        Ok(gridbuffer) => {
                             self.window_heap.push(gridbuffer)?;
 
                             if self.window_heap.out_gridbuffers().len() > 0 {
@@ -461,8 +568,7 @@ impl SampleSaverWorker {
                                         self.worker_id
                                     );
 
-                                    file.write_all(gridbuffer.to_base64().as_bytes())?;
-                                    file.write_all(b"\n")?;
+                                    writer.write(&gridbuffer)?;
 
                                     let worker_info = unsafe { &mut *self.worker_info.get() };
                                     worker_info.total += 1;
@@ -471,14 +577,12 @@ impl SampleSaverWorker {
                         }
                         Err(err) => {
                             info!("receive request error! read data done, error: {}", err);
-                            self.set_worker_state(WorkerState::Success);
                             break;
                         }
                     }
                 },
                 _ = subsys.on_shutdown_requested() => {
                     info!("sample saver worker shutdown!");
-                    self.set_worker_state(WorkerState::Success);
                     break;
                 }
             }
@@ -488,14 +592,18 @@ impl SampleSaverWorker {
 
         if self.window_heap.out_gridbuffers().len() > 0 {
             while let Some(gridbuffer) = self.window_heap.get_out_gridbuffer() {
-                file.write_all(gridbuffer.to_base64().as_bytes())?;
-                file.write_all(b"\n")?;
+                writer.write(&gridbuffer)?;
 
                 let worker_info = unsafe { &mut *self.worker_info.get() };
                 worker_info.total += 1;
             }
         }
 
+        writer.finish()?;
+
+        // The file must be complete before `merge_sort` could read it.
+        self.set_worker_state(WorkerState::Success);
+
         info!(
             "sample saver worker done, filename: {}",
             self.filename.clone()
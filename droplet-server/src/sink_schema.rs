@@ -0,0 +1,228 @@
+//! Schema of the table used to validate the `GridBuffer`s sinked to a partition.
+//!
+//! The schema is loaded from meta store when `StartSinkPartition` creates the `SampleSaver`,
+//! each `GridBuffer` in `SinkGridSample` is checked before it's sent to the workers, so bad
+//! batches are rejected with `InvalidArgument` instead of failing in the `WindowHeap` of the
+//! worker.
+use anyhow::{bail, Result};
+use log::error;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use gridbuffer::core::gridbuffer::{GridBuffer, GridCell};
+
+use droplet_core::data_type::DataType;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::error_bail;
+use droplet_core::grid_sample::KeySchema;
+
+/// Reason why a `GridBuffer` is rejected.
+///
+/// `field` and `description` are used to build the bad request violation of the status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    pub field: String,
+    pub description: String,
+}
+
+impl SchemaViolation {
+    pub fn new(field: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            field: field.into(),
+            description: description.into(),
+        }
+    }
+}
+
+impl fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.description)
+    }
+}
+
+impl std::error::Error for SchemaViolation {}
+
+/// Schema of the table for one partition.
+pub struct SinkSchema {
+    /// Key columns of the table, must be the first columns of each `GridBuffer`.
+    key_schema: KeySchema,
+
+    /// Data type of the columns which could be written, by global id.
+    ///
+    /// Deprecated columns are not included. Key columns are always `U64`.
+    column_types: HashMap<u32, DataType>,
+
+    /// `col_ids` of the first valid `GridBuffer`, and the `col_ids_hash` computed from it.
+    ///
+    /// All `GridBuffer`s of a partition are merged by `WindowHeap`, so they must have the same
+    /// `col_ids`.
+    col_ids: OnceLock<(Vec<u32>, u32)>,
+}
+
+impl SinkSchema {
+    pub fn new(key_schema: KeySchema, column_types: HashMap<u32, DataType>) -> Self {
+        let mut column_types = column_types;
+
+        for col_id in key_schema.col_ids() {
+            column_types.insert(*col_id, DataType::U64);
+        }
+
+        Self {
+            key_schema,
+            column_types,
+            col_ids: OnceLock::new(),
+        }
+    }
+
+    /// Load the schema of the table from meta store.
+    ///
+    /// `key_column_ids` is from `StartSinkPartitionRequest`, it must be the same as the key
+    /// columns of the table if not empty.
+    pub fn load(store: &dyn MetaStore, table: &str, key_column_ids: &[u32]) -> Result<Self> {
+        let key_schema = store.get_key_schema(table)?;
+
+        if !key_column_ids.is_empty() && key_column_ids != key_schema.col_ids() {
+            error_bail!(
+                "key_column_ids is not same as the table, table: {}, key_column_ids: {:?}, table key_column_ids: {:?}",
+                table,
+                key_column_ids,
+                key_schema.col_ids()
+            );
+        }
+
+        // Key columns use the fixed ids of `KeySchema`, not the ids of the column names.
+        let key_columns = store.get_key_columns(table)?;
+
+        let mut column_types = HashMap::new();
+        for column in store.get_table_column_infos(table)? {
+            if column.deprecated || key_columns.contains(&column.column_name) {
+                continue;
+            }
+
+            column_types.insert(
+                column.column_id,
+                DataType::from_column_type(column.column_type)?,
+            );
+        }
+
+        Ok(Self::new(key_schema, column_types))
+    }
+
+    pub fn key_schema(&self) -> &KeySchema {
+        &self.key_schema
+    }
+
+    /// Decode `grid_sample_bytes` of `SinkGridSampleRequest` and validate it.
+    pub fn decode(&self, bytes: &[u8]) -> std::result::Result<GridBuffer, SchemaViolation> {
+        let gridbuffer = GridBuffer::from_bytes(bytes).map_err(|e| {
+            SchemaViolation::new(
+                "grid_sample_bytes",
+                format!("invalid GridBuffer bytes, error: {}", e),
+            )
+        })?;
+
+        self.validate(&gridbuffer)?;
+
+        Ok(gridbuffer)
+    }
+
+    /// Check the `col_ids`, `col_ids_hash`, key columns and cell types of the `GridBuffer`.
+    pub fn validate(&self, gridbuffer: &GridBuffer) -> std::result::Result<(), SchemaViolation> {
+        let col_ids = gridbuffer.col_ids();
+
+        if !self.key_schema.is_valid_gridbuffer(gridbuffer) {
+            return Err(SchemaViolation::new(
+                "col_ids",
+                format!(
+                    "the first columns must be the key columns, key_column_ids: {:?}, col_ids: {:?}",
+                    self.key_schema.col_ids(),
+                    col_ids
+                ),
+            ));
+        }
+
+        for (i, col_id) in col_ids.iter().enumerate() {
+            if !self.column_types.contains_key(col_id) {
+                return Err(SchemaViolation::new(
+                    "col_ids",
+                    format!("unknown or deprecated column id: {}", col_id),
+                ));
+            }
+
+            if col_ids[..i].contains(col_id) {
+                return Err(SchemaViolation::new(
+                    "col_ids",
+                    format!("duplicate column id: {}", col_id),
+                ));
+            }
+        }
+
+        self.validate_cells(gridbuffer)?;
+
+        let (first_col_ids, col_ids_hash) = self
+            .col_ids
+            .get_or_init(|| (col_ids.clone(), get_col_ids_hash(col_ids)));
+
+        if first_col_ids != col_ids {
+            return Err(SchemaViolation::new(
+                "col_ids",
+                format!(
+                    "col_ids is not same as the previous batches of the partition, expected: {:?}, got: {:?}",
+                    first_col_ids, col_ids
+                ),
+            ));
+        }
+
+        if *col_ids_hash != gridbuffer.col_ids_hash() {
+            return Err(SchemaViolation::new(
+                "col_ids_hash",
+                format!(
+                    "col_ids_hash does not match col_ids, expected: {}, got: {}",
+                    col_ids_hash,
+                    gridbuffer.col_ids_hash()
+                ),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// `F32` columns must be stored in `f32` cells, other columns in `u64` cells.
+    fn validate_cells(&self, gridbuffer: &GridBuffer) -> std::result::Result<(), SchemaViolation> {
+        let col_ids = gridbuffer.col_ids();
+
+        for (col, col_id) in col_ids.iter().enumerate() {
+            let data_type = self.column_types[col_id];
+
+            for row in 0..gridbuffer.num_rows() {
+                let is_f32_cell = match gridbuffer.get_cell(row, col) {
+                    Some(GridCell::U64Cell(_)) => false,
+                    Some(GridCell::F32Cell(_)) => true,
+                    _ => continue,
+                };
+
+                if is_f32_cell != data_type.is_f32_cell() {
+                    return Err(SchemaViolation::new(
+                        "cells",
+                        format!(
+                            "cell type does not match column type, row: {}, col_id: {}, column_type: {}, cell: {}",
+                            row,
+                            col_id,
+                            data_type,
+                            if is_f32_cell { "f32" } else { "u64" }
+                        ),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `col_ids_hash` of `col_ids`, computed by `GridBuffer` itself so it's always the same as the
+/// hash of the `GridBuffer`s built by the sinkers.
+fn get_col_ids_hash(col_ids: &[u32]) -> u32 {
+    GridBuffer::new_with_num_rows_col_ids(0, col_ids.to_vec()).col_ids_hash()
+}
@@ -0,0 +1,110 @@
+//! Disk usage of the data roots, reported to meta server for placement of partitions.
+use anyhow::{anyhow, bail, Result};
+use log::{error, info};
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::os::unix::fs::MetadataExt;
+
+use droplet_core::droplet::ReportStorageInfoRequest;
+use droplet_core::error_bail;
+use droplet_core::partition::{SORTED_TABLE_ROOT, TABLE_ROOT};
+
+use crate::tool::run_with_meta_client;
+
+/// Interval of storage report to meta server in seconds.
+pub const STORAGE_REPORT_INTERVAL_SECS: u64 = 300;
+
+/// Disk size in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub used_disk_size: u64,
+    pub total_disk_size: u64,
+}
+
+/// Get the disk usage of the file system the path is on, by `statvfs`.
+///
+/// Space reserved for root is counted as used, so `total - used` is the space we can write.
+fn get_file_system_usage(path: &str) -> Result<DiskUsage> {
+    let c_path = CString::new(path)?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        error_bail!(
+            "Failed to statvfs, path: {}, error: {}",
+            path,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    let block_size = stat.f_frsize as u64;
+    let total_disk_size = stat.f_blocks as u64 * block_size;
+    let available_disk_size = stat.f_bavail as u64 * block_size;
+
+    Ok(DiskUsage {
+        used_disk_size: total_disk_size.saturating_sub(available_disk_size),
+        total_disk_size,
+    })
+}
+
+/// Get the disk usage of all the roots.
+///
+/// The roots are created if not exist. Roots on the same file system are counted once.
+pub fn get_disk_usage(roots: &[&str]) -> Result<DiskUsage> {
+    let mut devices = HashSet::new();
+    let mut disk_usage = DiskUsage::default();
+
+    for root in roots {
+        std::fs::create_dir_all(root)?;
+
+        if !devices.insert(std::fs::metadata(root)?.dev()) {
+            continue;
+        }
+
+        let usage = get_file_system_usage(root)?;
+
+        disk_usage.used_disk_size += usage.used_disk_size;
+        disk_usage.total_disk_size += usage.total_disk_size;
+    }
+
+    Ok(disk_usage)
+}
+
+/// Get the disk usage of `TABLE_ROOT` and `SORTED_TABLE_ROOT`.
+pub fn get_data_disk_usage() -> Result<DiskUsage> {
+    get_disk_usage(&[TABLE_ROOT, SORTED_TABLE_ROOT])
+}
+
+/// Scan the data roots and report to meta server every `STORAGE_REPORT_INTERVAL_SECS` seconds.
+///
+/// Runs until the process exits. Errors are logged, and retried in the next round.
+pub async fn run_storage_report_to_meta_server(node_id: u32) {
+    run_with_meta_client(STORAGE_REPORT_INTERVAL_SECS, move |mut client| async move {
+        let disk_usage = match get_data_disk_usage() {
+            Ok(disk_usage) => disk_usage,
+            Err(e) => {
+                // Not an error of the connection, so the client is kept.
+                error!("Failed to get disk usage, error: {}", e);
+                return Ok(());
+            }
+        };
+
+        let req = ReportStorageInfoRequest {
+            node_id,
+            used_disk_size: disk_usage.used_disk_size,
+            total_disk_size: disk_usage.total_disk_size,
+        };
+
+        client
+            .report_storage_info(req)
+            .await
+            .map_err(|e| anyhow!("Failed to report storage info, error: {}", e))?;
+
+        info!(
+            "Report storage info, node_id: {}, disk_usage: {:?}",
+            node_id, disk_usage
+        );
+
+        Ok(())
+    })
+    .await
+}
@@ -1,18 +1,26 @@
-use anyhow::{bail, Result};
+use anyhow::{anyhow, bail, Result};
 use droplet_core::droplet::droplet_client::DropletClient;
+use droplet_core::droplet::meta_client::MetaClient;
 use droplet_core::tool::MESSAGE_LIMIT;
 use gethostname::gethostname;
 use local_ip_address::local_ip;
 
 use log::{error, info};
+use std::future::Future;
+use std::time::Duration;
+use tonic::transport::Channel;
 
-use droplet_core::droplet::RegisterNodeRequest;
+use droplet_core::droplet::{HeartbeatRequest, NodeStatus, RegisterNodeRequest};
 use droplet_core::error_bail;
 use droplet_meta_server::tool::get_meta_server_default_client;
 
 pub const DROPPLET_SERVER_PORT: i32 = 50052;
 
-pub async fn register_node_to_meta_server() -> Result<()> {
+/// Interval of heartbeat to meta server in seconds.
+pub const HEARTBEAT_INTERVAL_SECS: u64 = 10;
+
+/// Register node to meta server, return the node id.
+pub async fn register_node_to_meta_server() -> Result<u32> {
     let hostname = gethostname()
         .into_string()
         .map_err(|_| anyhow::anyhow!("Failed to get hostname"))?;
@@ -35,7 +43,7 @@ pub async fn register_node_to_meta_server() -> Result<()> {
                     "Registered node to meta server successfully, node_id: {}, node_name: {}, node_ip: {}, node_port: {}",
                     resp.node_id, hostname, local_ip, DROPPLET_SERVER_PORT
                 );
-                Ok(())
+                Ok(resp.node_id)
             } else {
                 error_bail!(
                     "Failed to register node to meta server: {:?}",
@@ -49,6 +57,61 @@ pub async fn register_node_to_meta_server() -> Result<()> {
     }
 }
 
+/// Call `f` with the client of meta server every `interval_secs` seconds.
+///
+/// Runs until the process exits. The client is connected when needed, if `f` fails the error
+/// is logged and a new connection is used in the next round, so the task recovers after the
+/// meta server restarts.
+pub async fn run_with_meta_client<F, Fut>(interval_secs: u64, mut f: F)
+where
+    F: FnMut(MetaClient<Channel>) -> Fut,
+    Fut: Future<Output = Result<()>>,
+{
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    let mut meta_client: Option<MetaClient<Channel>> = None;
+
+    loop {
+        interval.tick().await;
+
+        let client = match meta_client.as_ref() {
+            Some(client) => client.clone(),
+            None => match get_meta_server_default_client().await {
+                Ok(client) => meta_client.insert(client).clone(),
+                Err(e) => {
+                    error!("Failed to connect to meta server, error: {}", e);
+                    continue;
+                }
+            },
+        };
+
+        if let Err(e) = f(client).await {
+            error!("{}", e);
+            meta_client = None;
+        }
+    }
+}
+
+/// Send heartbeat to meta server every `HEARTBEAT_INTERVAL_SECS` seconds.
+///
+/// Failed heartbeat is retried in the next round, the meta server marks the node `Unhealthy` if
+/// heartbeat is missing for a while.
+pub async fn run_heartbeat_to_meta_server(node_id: u32) {
+    run_with_meta_client(HEARTBEAT_INTERVAL_SECS, move |mut client| async move {
+        let req = HeartbeatRequest {
+            node_id,
+            status: NodeStatus::Healthy.into(),
+        };
+
+        client
+            .heartbeat(req)
+            .await
+            .map_err(|e| anyhow!("Failed to send heartbeat to meta server, error: {}", e))?;
+
+        Ok(())
+    })
+    .await
+}
+
 pub async fn get_droplet_default_client() -> Result<DropletClient<tonic::transport::Channel>> {
     let my_local_ip = local_ip()?;
 
@@ -0,0 +1,187 @@
+//! Watermarks and leases of the sinkers of a partition, used to decide when to seal it.
+//!
+//! Each `SinkGridSample` carries the event-time watermark of the sinker, the sinker will not send
+//! rows with smaller timestamp any more. A sinker is active from `StartSinkPartition` until
+//! `FinishSinkPartition`, or until no request is received from it for `lease_timeout` seconds.
+//!
+//! The partition is sealed once there is no active sinker, or the minimum watermark of the
+//! active sinkers reaches `time_end` of the partition plus the allowed lateness. So a sinker
+//! which crashed or never sends `FinishSinkPartition` cannot keep the partition open forever.
+use anyhow::{bail, Result};
+use dashmap::DashMap;
+use log::{error, info};
+
+use droplet_core::error_bail;
+use droplet_core::tool::get_env_u64;
+
+/// Environment variable of seconds of rows later than `time_end` of the partition still accepted.
+pub const ALLOWED_LATENESS_ENV: &str = "DROPLET_ALLOWED_LATENESS";
+
+/// Environment variable of seconds without request before the lease of a sinker expires.
+pub const SINKER_LEASE_TIMEOUT_ENV: &str = "DROPLET_SINKER_LEASE_TIMEOUT";
+
+/// Environment variable of the max number of partitions merged at the same time.
+pub const MAX_MERGE_JOBS_ENV: &str = "DROPLET_MAX_MERGE_JOBS";
+
+/// Config of sealing partitions, times are in seconds.
+#[derive(Clone, Debug)]
+pub struct SealConfig {
+    pub allowed_lateness: u64,
+    pub lease_timeout: u64,
+
+    /// Interval to check the partitions which could be sealed.
+    pub check_interval: u64,
+
+    /// Max number of partitions merged at the same time, others wait in background.
+    pub max_merge_jobs: usize,
+}
+
+impl Default for SealConfig {
+    fn default() -> Self {
+        Self {
+            allowed_lateness: 60,
+            lease_timeout: 600,
+            check_interval: 10,
+            max_merge_jobs: 4,
+        }
+    }
+}
+
+impl SealConfig {
+    /// Read config from environment variables, default values are used if not set.
+    pub fn from_env() -> Result<Self> {
+        let mut config = Self::default();
+
+        if let Some(lateness) = get_env_u64(ALLOWED_LATENESS_ENV)? {
+            config.allowed_lateness = lateness;
+        }
+
+        if let Some(timeout) = get_env_u64(SINKER_LEASE_TIMEOUT_ENV)? {
+            config.lease_timeout = timeout;
+        }
+
+        if let Some(max_merge_jobs) = get_env_u64(MAX_MERGE_JOBS_ENV)? {
+            config.max_merge_jobs = max_merge_jobs as usize;
+        }
+
+        if config.lease_timeout == 0 {
+            error_bail!("lease_timeout must be greater than 0");
+        }
+
+        if config.max_merge_jobs == 0 {
+            error_bail!("max_merge_jobs must be greater than 0");
+        }
+
+        Ok(config)
+    }
+}
+
+/// Watermark and lease of one sinker.
+#[derive(Clone, Copy, Debug)]
+struct SinkerLease {
+    /// `0` if the sinker never reports a watermark.
+    watermark: u64,
+
+    /// Seconds of the last request of the sinker.
+    last_active: u64,
+}
+
+/// Active sinkers of one partition.
+///
+/// Times of leases are seconds passed in by the caller, watermarks are in the `timestamp_unit`
+/// of the table.
+pub struct PartitionWatermark {
+    /// `time_end` of the partition plus the allowed lateness.
+    seal_watermark: u64,
+
+    /// Seconds without request before the lease of a sinker expires.
+    lease_timeout: u64,
+
+    /// Active sinkers, key is `sinker_id`.
+    sinkers: DashMap<u32, SinkerLease>,
+}
+
+impl PartitionWatermark {
+    pub fn new(seal_watermark: u64, lease_timeout: u64) -> Self {
+        Self {
+            seal_watermark,
+            lease_timeout,
+            sinkers: DashMap::new(),
+        }
+    }
+
+    pub fn seal_watermark(&self) -> u64 {
+        self.seal_watermark
+    }
+
+    /// Whether the sinker is active.
+    pub fn contains(&self, sinker_id: u32) -> bool {
+        self.sinkers.contains_key(&sinker_id)
+    }
+
+    /// Add the sinker or renew its lease.
+    pub fn start(&self, sinker_id: u32, now: u64) {
+        self.update(sinker_id, 0, now);
+    }
+
+    /// Renew the lease of the sinker and advance its watermark.
+    ///
+    /// The watermark never goes back, so `0` only renews the lease. A sinker whose lease already
+    /// expired becomes active again.
+    pub fn update(&self, sinker_id: u32, watermark: u64, now: u64) {
+        self.sinkers
+            .entry(sinker_id)
+            .and_modify(|lease| {
+                lease.watermark = lease.watermark.max(watermark);
+                lease.last_active = lease.last_active.max(now);
+            })
+            .or_insert(SinkerLease {
+                watermark,
+                last_active: now,
+            });
+    }
+
+    /// The sinker is done with the partition.
+    pub fn finish(&self, sinker_id: u32) {
+        self.sinkers.remove(&sinker_id);
+    }
+
+    /// Remove the sinkers whose lease expired, return their ids.
+    pub fn expire_leases(&self, now: u64) -> Vec<u32> {
+        let expired = self
+            .sinkers
+            .iter()
+            .filter(|x| now >= x.last_active + self.lease_timeout)
+            .map(|x| *x.key())
+            .collect::<Vec<_>>();
+
+        for sinker_id in expired.iter() {
+            info!(
+                "Sinker lease expired, sinker_id: {}, seal_watermark: {}",
+                sinker_id, self.seal_watermark
+            );
+            self.sinkers.remove(sinker_id);
+        }
+
+        expired
+    }
+
+    /// Minimum watermark of the active sinkers, `None` if there is no active sinker.
+    pub fn min_watermark(&self) -> Option<u64> {
+        self.sinkers.iter().map(|x| x.watermark).min()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinkers.is_empty()
+    }
+
+    /// Whether the partition could be sealed, sinkers whose lease expired are removed first.
+    pub fn should_seal(&self, now: u64) -> bool {
+        self.expire_leases(now);
+
+        match self.min_watermark() {
+            Some(watermark) => watermark >= self.seal_watermark,
+            None => true,
+        }
+    }
+}
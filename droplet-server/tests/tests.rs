@@ -1 +1,274 @@
+use anyhow::Result;
+use dashmap::DashMap;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use droplet_core::db::memory_store::MemoryMetaStore;
+use droplet_core::db::meta_store::MetaStore;
+use droplet_core::droplet::{ColumnInfo, DataType, SinkPartitionState};
+use droplet_core::grid_sample::{KeySchema, SampleKey};
+use droplet_core::partition::{get_partition_path, get_sorted_path, PartitionScheme};
+use droplet_core::tool::setup_log;
+use droplet_server::partition_sealer::PartitionSealer;
+use droplet_server::sample_saver::{get_failed_filename, get_success_filename, SampleSaver};
+use droplet_server::sink_schema::SinkSchema;
+use droplet_server::storage::get_disk_usage;
+use droplet_server::watermark::PartitionWatermark;
+
+#[test]
+fn test_get_disk_usage() -> Result<()> {
+    setup_log();
+
+    let root_a = "/tmp/droplet_test/storage/a";
+    let root_b = "/tmp/droplet_test/storage/b";
+
+    let usage = get_disk_usage(&[root_a])?;
+
+    assert!(usage.total_disk_size > 0);
+    assert!(usage.used_disk_size <= usage.total_disk_size);
+
+    // Roots on the same file system are counted once.
+    let usage_same_fs = get_disk_usage(&[root_a, root_b])?;
+    assert_eq!(usage_same_fs.total_disk_size, usage.total_disk_size);
+
+    Ok(())
+}
+
+#[test]
+fn test_sink_schema_validate() -> Result<()> {
+    setup_log();
+
+    let store = MemoryMetaStore::new();
+
+    let columns = [
+        ("ctr", DataType::F32),
+        ("uid", DataType::Ui64),
+        ("old", DataType::I64),
+    ]
+    .iter()
+    .enumerate()
+    .map(|(i, (name, column_type))| ColumnInfo {
+        column_name: name.to_string(),
+        column_type: (*column_type).into(),
+        column_index: i as u32,
+        ..Default::default()
+    })
+    .collect::<Vec<_>>();
+
+    store.insert_table_info("droplet_test", &PartitionScheme::new(24), &[], &columns)?;
+    store.alter_table("droplet_test", &[], &["old".to_string()])?;
+
+    let ctr_id = store.get_or_insert_key_id("ctr")?;
+    let uid_id = store.get_or_insert_key_id("uid")?;
+    let old_id = store.get_or_insert_key_id("old")?;
+
+    assert!(SinkSchema::load(&store, "droplet_test", &[2, 4]).is_err());
+
+    let schema = SinkSchema::load(&store, "droplet_test", &[2, 4, 5, 6])?;
+
+    let new_gridbuffer = |col_ids: Vec<u32>, f32_cols: &[usize]| {
+        let num_cols = col_ids.len();
+        let mut gb = GridBuffer::new_with_num_rows_col_ids(2, col_ids);
+
+        for row in 0..2 {
+            for col in 0..num_cols {
+                if f32_cols.contains(&col) {
+                    gb.push_f32(row, col, 0.5);
+                } else {
+                    gb.push_u64(row, col, (row + col) as u64);
+                }
+            }
+        }
+
+        gb
+    };
+
+    let get_field = |gb: &GridBuffer| schema.validate(gb).unwrap_err().field;
+
+    // Key columns must be the first columns.
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, ctr_id], &[3])),
+        "col_ids"
+    );
+
+    // Unknown and deprecated columns.
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, 6, 99999], &[])),
+        "col_ids"
+    );
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, 6, old_id], &[])),
+        "col_ids"
+    );
+
+    // `ctr` is `F32`, `uid` is `U64`.
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, 6, ctr_id], &[])),
+        "cells"
+    );
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, 6, uid_id], &[4])),
+        "cells"
+    );
+
+    let gb = new_gridbuffer(vec![2, 4, 5, 6, ctr_id, uid_id], &[4]);
+    assert!(schema.validate(&gb).is_ok());
+
+    // Column ids never collide with the fixed ids of the key columns.
+    let unique_col_ids = gb.col_ids().iter().collect::<HashSet<_>>();
+    assert_eq!(unique_col_ids.len(), gb.col_ids().len());
+
+    let decoded = schema.decode(&gb.to_bytes())?;
+    assert_eq!(decoded.col_ids(), gb.col_ids());
+
+    // All batches of the partition must have the same `col_ids`.
+    assert_eq!(
+        get_field(&new_gridbuffer(vec![2, 4, 5, 6, ctr_id], &[4])),
+        "col_ids"
+    );
+
+    // `col_ids_hash` must be computed from `col_ids`, even if the first batch has a wrong one.
+    let col_ids = vec![2, 4, 5, 6, ctr_id, uid_id];
+    let wrong_hash = gb.col_ids_hash().wrapping_add(1);
+
+    let schema = SinkSchema::load(&store, "droplet_test", &[2, 4, 5, 6])?;
+    let wrong_gb = GridBuffer::new_with_num_rows_col_ids_hash(2, col_ids, wrong_hash);
+
+    assert_eq!(
+        schema.validate(&wrong_gb).unwrap_err().field,
+        "col_ids_hash"
+    );
+    assert!(schema.validate(&gb).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn test_partition_watermark() -> Result<()> {
+    setup_log();
+
+    let scheme = PartitionScheme::new(24);
+    let (_, time_end) = scheme.get_partition_time_range(20241011, 1)?;
+
+    // Allowed lateness is 60 seconds, lease timeout is 600 seconds.
+    let watermark = PartitionWatermark::new(time_end + 60, 600);
+
+    watermark.start(1, 1000);
+    watermark.start(2, 1000);
+    assert!(!watermark.should_seal(1000));
+
+    // Watermark never goes back.
+    watermark.update(1, time_end + 100, 1010);
+    watermark.update(1, time_end, 1020);
+    assert_eq!(watermark.min_watermark(), Some(0));
+    assert!(!watermark.should_seal(1020));
+
+    // Not sealed until every active sinker passes the lateness.
+    watermark.update(2, time_end + 30, 1020);
+    assert_eq!(watermark.min_watermark(), Some(time_end + 30));
+    assert!(!watermark.should_seal(1020));
+
+    watermark.update(2, time_end + 60, 1030);
+    assert!(watermark.should_seal(1030));
+
+    // Sinker 2 finished, sinker 1 crashed and its lease expires.
+    let watermark = PartitionWatermark::new(time_end + 60, 600);
+    watermark.start(1, 1000);
+    watermark.start(2, 1000);
+    watermark.update(1, time_end - 100, 1100);
+    watermark.finish(2);
+
+    assert!(!watermark.should_seal(1699));
+    assert!(watermark.expire_leases(1650).is_empty());
+    assert_eq!(watermark.expire_leases(1700), vec![1]);
+    assert!(watermark.is_empty());
+    assert!(watermark.should_seal(1700));
+
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_partition_sealer() -> Result<()> {
+    setup_log();
+
+    let store: Arc<dyn MetaStore> = Arc::new(MemoryMetaStore::new());
+    let sample_savers = Arc::new(DashMap::new());
+    let sealer = PartitionSealer::new(store, sample_savers.clone(), 1);
+
+    let path = get_partition_path("droplet_test_sealer", 20241011, 1);
+    let _ = std::fs::remove_dir_all(&path);
+    let _ = std::fs::remove_dir_all(get_sorted_path(&path));
+
+    assert_eq!(sealer.get_status(&path).state, SinkPartitionState::NotFound);
+
+    sealer.start_writing(&path)?;
+    assert_eq!(sealer.get_status(&path).state, SinkPartitionState::Writing);
+
+    let schema = SinkSchema::new(KeySchema::default(), HashMap::new());
+    let saver = SampleSaver::new(&path, 1, 1, schema, PartitionWatermark::new(u64::MAX, 600))?;
+    saver.start_partition(1);
+
+    let key_ids = SampleKey::get_sample_key_ids().to_vec();
+    let mut gb = GridBuffer::new_with_num_rows_col_ids(2, key_ids.clone());
+    for row in 0..2 {
+        for col in 0..key_ids.len() {
+            gb.push_u64(row, col, 1728608400 + row as u64);
+        }
+    }
+
+    saver.process(saver.decode(&gb.to_bytes())?).await?;
+
+    saver.finish_partition(1);
+    assert!(saver.should_seal());
+
+    sample_savers.insert(1, Arc::new(saver));
+
+    // Sealed only once, and cannot be written while merging.
+    assert!(sealer.seal(1));
+    assert!(!sealer.seal(1));
+    assert!(sealer.start_writing(&path).is_err());
+
+    let mut status = sealer.get_status(&path);
+    for _ in 0..60 {
+        if status.state != SinkPartitionState::Merging {
+            break;
+        }
+
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        status = sealer.get_status(&path);
+    }
+
+    assert_eq!(status.state, SinkPartitionState::Sealed, "{:?}", status);
+    assert!(Path::new(&get_success_filename(&path)).exists());
+    assert!(sealer.start_writing(&path).is_err());
+
+    // A failed partition cannot be written until the `FAILED` file is deleted, even after the
+    // server restarts.
+    let failed_path = get_partition_path("droplet_test_sealer", 20241011, 2);
+    let _ = std::fs::remove_dir_all(&failed_path);
+    std::fs::create_dir_all(&failed_path)?;
+    std::fs::write(get_failed_filename(&failed_path), "merge failed")?;
+
+    let status = sealer.get_status(&failed_path);
+    assert_eq!(status.state, SinkPartitionState::Failed);
+    assert_eq!(status.error_message, "merge failed");
+    assert!(sealer.start_writing(&failed_path).is_err());
+
+    std::fs::remove_file(get_failed_filename(&failed_path))?;
+    sealer.start_writing(&failed_path)?;
+    assert!(sealer.start_writing(&failed_path).is_err());
+
+    sealer.abort_writing(&failed_path);
+    assert_eq!(
+        sealer.get_status(&failed_path).state,
+        SinkPartitionState::NotFound
+    );
+
+    std::fs::remove_dir_all(&failed_path)?;
+
+    Ok(())
+}
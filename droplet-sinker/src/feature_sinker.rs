@@ -1,53 +1,94 @@
+use anyhow::bail;
 use anyhow::Result;
-use gridbuffer::core::performance::parse_simple_features;
 use log::{error, info};
 use std::time::Duration;
 use tokio_graceful_shutdown::SubsystemBuilder;
 use tokio_graceful_shutdown::Toplevel;
 
+use std::sync::Arc;
+use std::sync::RwLock;
+
 use tokio::task;
 
 use tokio_graceful_shutdown::SubsystemHandle;
 
-use gridbuffer::core::feature_batcher::SimpleFeaturesBatcher;
-
+use droplet_core::data_converter::feature_converter::{
+    parse_simple_features, SimpleFeaturesConverter,
+};
+use droplet_core::error_bail;
+use droplet_core::id_mapping::IDMapping;
 use droplet_core::local_file_reader::{get_test_feature_filenames, LocalFileReader};
+use droplet_core::sniper::SimpleFeatures;
+use droplet_core::window_heap::WindowHeap;
+use droplet_meta_client::client::MetaClientWrapper;
+
+use crate::partition_sender::PartitionSender;
 
 /// `FeatureSinker` is responsible for converting SimpleFeatures into GridBuffer format
 /// and sending it to the appropriate worker node.
 ///
+/// Every `num_rows` `SimpleFeatures` are converted to one `GridSample` by
+/// `SimpleFeaturesConverter` with the columns of the table, then sorted by `WindowHeap` and
+/// sent to the server of each partition, same as `GridSinker`.
+///
+/// `SimpleFeatures` only has the fields of `SampleKey`, so the table must use the default key
+/// columns.
+///
 /// One `FeatureSinker` instance is responsible for one table.
 pub struct FeatureSinker<T: Iterator<Item = Result<String>>> {
     table_name: String,
     reader: T,
     num_rows: usize,
-    num_cols: usize,
+
+    /// Convert `SimpleFeatures` to `GridSample` with the columns of the table.
+    converter: SimpleFeaturesConverter,
+
+    /// `window_heap` to sort the converted `GridBuffer`s by `SampleKey`.
+    window_heap: WindowHeap,
+
+    /// Send sorted `GridBuffer`s to the server of each partition.
+    sender: PartitionSender,
 }
 
 impl<T: Iterator<Item = Result<String>>> FeatureSinker<T> {
     /// Create a new FeatureSinker instance using a BufRead.
-    pub fn new(table_name: &String, reader: T, num_rows: usize, num_cols: usize) -> Self {
-        Self {
-            table_name: table_name.clone(),
+    pub async fn new(
+        table_name: &str,
+        reader: T,
+        num_rows: usize,
+        id_mapping: Arc<RwLock<IDMapping>>,
+        mut meta_client: MetaClientWrapper,
+    ) -> Result<Self> {
+        let batch_size = 4;
+
+        let key_schema = meta_client.get_key_schema(table_name).await?;
+        let columns = meta_client.get_table_columns(table_name).await?;
+        let converter = match id_mapping.read() {
+            Ok(id_mapping) => {
+                SimpleFeaturesConverter::from_columns(&id_mapping, &key_schema, &columns)?
+            }
+            Err(e) => {
+                error_bail!("Failed to read id_mapping, error: {}", e);
+            }
+        };
+
+        Ok(Self {
+            table_name: table_name.to_string(),
             reader,
             num_rows,
-            num_cols,
-        }
+            converter,
+            window_heap: WindowHeap::new(2, batch_size).with_key_schema(&key_schema),
+            sender: PartitionSender::new(table_name, meta_client).await?,
+        })
     }
 
     /// Start the FeatureSinker process
-    pub async fn run(self, _subsys: SubsystemHandle) -> Result<()> {
+    pub async fn run(mut self, _subsys: SubsystemHandle) -> Result<()> {
         info!("Starting FeatureSinker process");
 
         let features = self.reader.filter_map(|line| match line {
             Ok(line) => match parse_simple_features(&line) {
-                Ok(features) => {
-                    info!(
-                        "Received features, llsid: {}, user_id: {}, item_id: {}, timestamp: {}",
-                        features.llsid, features.user_id, features.item_id, features.timestamp
-                    );
-                    Some(features)
-                }
+                Ok(features) => Some(features),
                 Err(e) => {
                     error!("Failed to parse simple features, error: {}", e);
                     None
@@ -59,19 +100,63 @@ impl<T: Iterator<Item = Result<String>>> FeatureSinker<T> {
             }
         });
 
-        let batcher = SimpleFeaturesBatcher::new(features, self.num_rows);
+        let mut batch = Vec::with_capacity(self.num_rows);
+
+        for simple_features in features {
+            batch.push(simple_features);
 
-        for batch in batcher {
-            info!("read one batch, bytes: {}", batch.estimated_bytes());
+            if batch.len() >= self.num_rows {
+                Self::process_and_send_data(
+                    &self.converter,
+                    &mut self.window_heap,
+                    &mut self.sender,
+                    &batch,
+                )
+                .await?;
+                batch.clear();
+            }
+        }
+
+        if !batch.is_empty() {
+            Self::process_and_send_data(
+                &self.converter,
+                &mut self.window_heap,
+                &mut self.sender,
+                &batch,
+            )
+            .await?;
+        }
+
+        self.window_heap.process_remain_data();
+        while let Some(gridbuffer) = self.window_heap.get_out_gridbuffer() {
+            self.sender.send(gridbuffer).await?;
         }
 
-        info!("Sinker process completed");
+        self.sender.finish().await?;
+
+        info!("Sinker process completed, table: {}", self.table_name);
         Ok(())
     }
 
-    /// Process and send data to the appropriate worker node.
-    fn process_and_send_data(self, _data: String) -> Result<()> {
-        // TODO: Implement the main logic for processing and sending data
+    /// Convert one batch of `SimpleFeatures` and send the sorted data to the worker nodes.
+    async fn process_and_send_data(
+        converter: &SimpleFeaturesConverter,
+        window_heap: &mut WindowHeap,
+        sender: &mut PartitionSender,
+        batch: &[SimpleFeatures],
+    ) -> Result<()> {
+        let grid_sample = converter.to_grid_sample(batch)?;
+
+        window_heap.push(grid_sample.gridbuffer)?;
+
+        while let Some(gridbuffer) = window_heap.get_out_gridbuffer() {
+            sender.send(gridbuffer).await?;
+        }
+
+        if let Some(timestamp) = window_heap.min_timestamp() {
+            sender.advance_watermark(timestamp);
+        }
+
         Ok(())
     }
 
@@ -79,7 +164,6 @@ impl<T: Iterator<Item = Result<String>>> FeatureSinker<T> {
     pub async fn start_local_file_sinker(
         table_name: &String,
         num_rows: usize,
-        num_cols: usize,
         num_threads: usize,
     ) -> Result<()> {
         let filenames = get_test_feature_filenames(num_threads);
@@ -88,11 +172,22 @@ impl<T: Iterator<Item = Result<String>>> FeatureSinker<T> {
 
         let mut handlers = Vec::new();
 
+        let id_mapping = Arc::new(RwLock::new(IDMapping::new()?));
+
         for chunk in filenames.chunks(chunk_size) {
             let chunk_files = chunk.to_vec();
 
             let reader = LocalFileReader::new(&chunk_files)?;
-            let sinker = FeatureSinker::new(table_name, reader, num_rows, num_cols);
+
+            let meta_client = MetaClientWrapper::get_default_client().await?;
+            let sinker = FeatureSinker::new(
+                table_name,
+                reader,
+                num_rows,
+                id_mapping.clone(),
+                meta_client,
+            )
+            .await?;
 
             let handler = task::spawn(async move {
                 Toplevel::new(|s| async move {
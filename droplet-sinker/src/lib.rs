@@ -76,3 +76,4 @@
 
 pub mod feature_sinker;
 pub mod grid_sinker;
+pub mod partition_sender;
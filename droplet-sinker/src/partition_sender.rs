@@ -0,0 +1,258 @@
+use anyhow::{bail, Result};
+use gethostname::gethostname;
+use likely_stable::unlikely;
+use log::error;
+use std::collections::HashMap;
+
+use gridbuffer::core::gridbuffer::GridBuffer;
+
+use droplet_client::client::Client;
+use droplet_core::error_bail;
+use droplet_core::grid_sample::{split_gridbuffer_by_partition, SampleKey};
+use droplet_core::partition::PartitionScheme;
+use droplet_meta_client::client::MetaClientWrapper;
+
+/// `PartitionSender` sends sorted `GridBuffer`s of one table to the server of the partition.
+///
+/// Each `GridBuffer` is split by the partitions of the timestamp of rows. When the partition is
+/// changed, the current partition is finished, and the new partition is started on the node
+/// chosen by meta server, so data from all sinkers of the same partition lands on the same
+/// server.
+///
+/// The endpoints of partitions and the clients of endpoints are cached, the node of a
+/// partition doesn't change once it's placed.
+///
+/// It's shared by `GridSinker` and `FeatureSinker`.
+pub struct PartitionSender {
+    /// Table name.
+    table_name: String,
+
+    /// Meta client to get meta information.
+    meta_client: MetaClientWrapper,
+
+    /// Partition count, timestamp unit and timezone of the table, same as meta server.
+    partition_scheme: PartitionScheme,
+
+    /// The ID of the sinker.
+    sinker_id: u32,
+
+    /// Server endpoints of partitions, key is (`partition_date`, `partition_index`).
+    endpoints: HashMap<(u32, u32), String>,
+
+    /// Clients of server endpoints.
+    clients: HashMap<String, Client>,
+
+    /// Endpoint of the server of current partition.
+    server_endpoint: String,
+
+    /// (`partition_date`, `partition_index`) of current partition, `None` before the first
+    /// `GridBuffer` is sent.
+    partition: Option<(u32, u32)>,
+
+    /// The ID of the path of current partition, returned by `start_sink_partition`.
+    path_id: u32,
+
+    /// Event-time watermark sent with each `GridBuffer`, advanced by the sinker with
+    /// `advance_watermark`, `0` means unknown.
+    ///
+    /// `WindowHeap` only sorts rows within its window, so it's not the timestamp of the sent
+    /// rows, but the min timestamp of the rows still held by `WindowHeap`, which are sent later.
+    /// Input rows which are earlier than it when they are read are late, and only accepted if
+    /// their partitions are not sealed yet, see the allowed lateness of the server.
+    watermark: u64,
+}
+
+impl PartitionSender {
+    pub async fn new(table_name: &str, mut meta_client: MetaClientWrapper) -> Result<Self> {
+        let partition_scheme = meta_client.get_partition_scheme(table_name).await?;
+
+        let hostname = gethostname();
+        let sinker_id = meta_client
+            .get_or_insert_key_id(&hostname.to_string_lossy())
+            .await?;
+
+        Ok(Self {
+            table_name: table_name.to_string(),
+            meta_client,
+            partition_scheme,
+            sinker_id,
+            endpoints: HashMap::new(),
+            clients: HashMap::new(),
+            server_endpoint: String::new(),
+            partition: None,
+            path_id: 0,
+            watermark: 0,
+        })
+    }
+
+    pub fn sinker_id(&self) -> u32 {
+        self.sinker_id
+    }
+
+    /// Advance the watermark sent with the next `GridBuffer`s, it never goes back.
+    ///
+    /// Call it after the sorted `GridBuffer`s are sent, with `WindowHeap::min_timestamp`, so
+    /// the rows already taken from `WindowHeap` are sent with the previous watermark.
+    pub fn advance_watermark(&mut self, watermark: u64) {
+        self.watermark = self.watermark.max(watermark);
+    }
+
+    /// Send the `GridBuffer` to the servers of its partitions, empty `GridBuffer` is ignored.
+    ///
+    /// The rows of a `GridBuffer` may cross the boundary of partitions, so it's split by
+    /// partitions first, each partition only receives the rows in its time range.
+    pub async fn send(&mut self, gridbuffer: GridBuffer) -> Result<()> {
+        if unlikely(gridbuffer.num_rows() == 0) {
+            return Ok(());
+        }
+
+        for (partition, gridbuffer) in
+            split_gridbuffer_by_partition(gridbuffer, &self.partition_scheme)?
+        {
+            self.send_partition(partition, gridbuffer).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Send the `GridBuffer` whose rows are all in `partition`.
+    async fn send_partition(
+        &mut self,
+        partition: (u32, u32),
+        gridbuffer: GridBuffer,
+    ) -> Result<()> {
+        let timestamp = SampleKey::from_gridbuffer(&gridbuffer, 0).timestamp;
+
+        if self.partition != Some(partition) {
+            self.finish().await?;
+            self.start_partition(timestamp, partition).await?;
+        }
+
+        let (_, partition_index) = partition;
+
+        match self.clients.get_mut(&self.server_endpoint) {
+            Some(client) => {
+                client
+                    .sink_grid_sample(
+                        &self.table_name,
+                        Some(self.path_id),
+                        self.sinker_id,
+                        partition_index,
+                        gridbuffer,
+                        self.watermark,
+                    )
+                    .await
+            }
+            None => {
+                error_bail!(
+                    "Client not found, table: {}, server_endpoint: {}",
+                    self.table_name,
+                    self.server_endpoint
+                );
+            }
+        }
+    }
+
+    /// Finish current partition, nothing to do if no `GridBuffer` is sent.
+    pub async fn finish(&mut self) -> Result<()> {
+        let (_, partition_index) = match self.partition.take() {
+            Some(partition) => partition,
+            None => return Ok(()),
+        };
+
+        let path_id = self.path_id;
+        let sinker_id = self.sinker_id;
+
+        self.get_client()?
+            .finish_sink_partition(path_id, sinker_id, partition_index)
+            .await
+    }
+
+    /// Get the server endpoint of the partition from meta server, the result is cached.
+    async fn get_server_endpoint(
+        &mut self,
+        timestamp: u64,
+        partition: (u32, u32),
+    ) -> Result<String> {
+        if let Some(endpoint) = self.endpoints.get(&partition) {
+            return Ok(endpoint.clone());
+        }
+
+        let partition_info = self
+            .meta_client
+            .get_partition_info(&self.table_name, timestamp)
+            .await?;
+
+        // Same as the endpoint in `MetaStore::get_server_endpoint_by_partition_index`.
+        let endpoint = format!("{}:{}", partition_info.node_name, partition_info.node_port);
+        self.endpoints.insert(partition, endpoint.clone());
+
+        Ok(endpoint)
+    }
+
+    /// Client of current server endpoint.
+    fn get_client(&mut self) -> Result<&mut Client> {
+        match self.clients.get_mut(&self.server_endpoint) {
+            Some(client) => Ok(client),
+            None => {
+                error_bail!(
+                    "Client not found, table: {}, server_endpoint: {}",
+                    self.table_name,
+                    self.server_endpoint
+                );
+            }
+        }
+    }
+
+    /// Start sinking the partition on the server chosen by meta server.
+    ///
+    /// The client of the server is created if not exists.
+    async fn start_partition(&mut self, timestamp: u64, partition: (u32, u32)) -> Result<()> {
+        let server_endpoint = self.get_server_endpoint(timestamp, partition).await?;
+
+        if !self.clients.contains_key(&server_endpoint) {
+            let mut client = match Client::new_client_by_server_endpoint(&server_endpoint).await {
+                Ok(client) => client,
+                Err(e) => {
+                    error_bail!(
+                        "Failed to new client, server_endpoint: {}, error: {}",
+                        server_endpoint,
+                        e
+                    );
+                }
+            };
+
+            if let Err(e) = client.heartbeat(self.sinker_id).await {
+                error_bail!("Failed to heartbeat, error: {}", e);
+            }
+
+            self.clients.insert(server_endpoint.clone(), client);
+        }
+
+        self.server_endpoint = server_endpoint;
+
+        let (partition_date, partition_index) = partition;
+        let table_name = self.table_name.clone();
+        let sinker_id = self.sinker_id;
+
+        let res = self
+            .get_client()?
+            .start_sink_partition(&table_name, sinker_id, partition_date, partition_index)
+            .await;
+
+        self.path_id = match res {
+            Ok(path_id) => path_id,
+            Err(e) => {
+                error_bail!(
+                    "Failed to start sink partition, server_endpoint: {}, error: {}",
+                    self.server_endpoint,
+                    e
+                );
+            }
+        };
+
+        self.partition = Some(partition);
+
+        Ok(())
+    }
+}